@@ -0,0 +1,70 @@
+use chip8::cpu::CPU;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::fs;
+use std::sync::mpsc::channel;
+
+const STEPS: usize = 10_000;
+
+fn create_cpu(rom: &[u8], instruction_cache: bool) -> CPU {
+    // Dropping the senders makes Fx0A return immediately instead of waiting
+    // for a key forever.
+    let mut cpu = CPU::new(channel().1);
+    cpu.set_instruction_cache(instruction_cache);
+    cpu.load_game_data(rom).expect("unable to load ROM");
+    cpu
+}
+
+// Compares stepping through a bundled ROM with and without the decoded
+// instruction cache. Setting up the CPU isn't timed, nor is dropping it.
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    for name in ["BRIX", "BLITZ"].iter() {
+        let rom = fs::read(name).expect("unable to read ROM");
+        for &cached in [false, true].iter() {
+            let id = BenchmarkId::new(if cached { "cached" } else { "uncached" }, name);
+            group.bench_with_input(id, &rom, |b, rom| {
+                b.iter_batched(
+                    || create_cpu(rom, cached),
+                    |mut cpu| {
+                        for _ in 0..STEPS {
+                            cpu.step().expect("ROM faulted");
+                        }
+                        cpu
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+// Runs a bundled ROM through the same number of instructions with the
+// recompiler.
+fn recompile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("recompile");
+    for name in ["BRIX", "BLITZ"].iter() {
+        let rom = fs::read(name).expect("unable to read ROM");
+        group.bench_with_input(BenchmarkId::from_parameter(name), &rom, |b, rom| {
+            b.iter_batched(
+                || {
+                    let mut cpu = create_cpu(rom, true);
+                    cpu.set_recompiler(true);
+                    cpu
+                },
+                |mut cpu| {
+                    let mut executed = 0;
+                    while executed < STEPS {
+                        executed += cpu.run_block().expect("ROM faulted");
+                    }
+                    cpu
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step, recompile);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+#![no_main]
+use chip8::cpu::CPU;
+use libfuzzer_sys::fuzz_target;
+use std::sync::mpsc::channel;
+
+// Enough to get through the loops of most programs without making each run
+// too slow.
+const MAX_CYCLES: usize = 10_000;
+
+fuzz_target!(|rom: &[u8]| {
+    // Dropping the senders makes Fx0A return immediately instead of waiting
+    // for a key forever.
+    let mut cpu = CPU::new(channel().1);
+    if cpu.load_game_data(rom).is_err() {
+        return;
+    }
+    for _ in 0..MAX_CYCLES {
+        if cpu.step().is_err() {
+            break;
+        }
+    }
+});
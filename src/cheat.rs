@@ -0,0 +1,251 @@
+#[cfg(test)]
+mod tests;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Most candidates listed by the debugger's "list" command.
+pub const MAX_LISTED_CANDIDATES: usize = 32;
+
+pub const DEBUGGER_HELP: &str = "\
+commands (numbers are hex):
+  regs                   print the registers and call stack
+  search                 start a memory search from the current values
+  eq VALUE               keep addresses that now hold VALUE
+  changed | unchanged    keep addresses whose value changed or didn't
+  inc | dec              keep addresses whose value increased or decreased
+  list                   print the remaining addresses
+  freeze ADDR|Vx VALUE   hold a memory address or register at VALUE
+  unfreeze ADDR|Vx       stop holding it
+  cheats                 print the frozen addresses and registers
+  pause | resume         pause or resume the program
+  reset                  restart the program from the beginning
+  reload                 read the ROM from its file again and restart it
+  load FILE              replace the program with the ROM in FILE
+  quit                   stop the program
+  help                   print this message";
+
+#[derive(Debug)]
+pub enum CheatError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    // A cheat holds a register other than V0 to VF.
+    InvalidRegister(usize),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheatError::Io(e) => write!(f, "unable to read cheats: {}", e),
+            CheatError::Parse(e) => write!(f, "unable to parse cheats: {}", e),
+            CheatError::InvalidRegister(register) => {
+                write!(f, "invalid cheat register {}, must be 0 to 15", register)
+            }
+        }
+    }
+}
+
+impl error::Error for CheatError {}
+
+impl From<io::Error> for CheatError {
+    fn from(e: io::Error) -> CheatError {
+        CheatError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for CheatError {
+    fn from(e: serde_json::Error) -> CheatError {
+        CheatError::Parse(e)
+    }
+}
+
+/// What a cheat holds at a fixed value.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Target {
+    Memory { address: usize },
+    Register { register: usize },
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Memory { address } => write!(f, "[{:03X}]", address),
+            Target::Register { register } => write!(f, "V{:X}", register),
+        }
+    }
+}
+
+/// A memory address or register that is set to |value| every frame.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Cheat {
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    pub target: Target,
+    pub value: u8,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {:02X}", self.target, self.value)?;
+        if !self.name.is_empty() {
+            write!(f, " ({})", self.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cheat lists keyed by the SHA-1 of the ROM they are for.
+#[derive(Debug, Default)]
+pub struct CheatFile {
+    games: HashMap<String, Vec<Cheat>>,
+}
+
+impl CheatFile {
+    pub fn from_json(json: &str) -> Result<CheatFile, CheatError> {
+        let games: HashMap<String, Vec<Cheat>> = serde_json::from_str(json)?;
+        for cheat in games.values().flatten() {
+            if let Target::Register { register } = cheat.target {
+                if register > 0xF {
+                    return Err(CheatError::InvalidRegister(register));
+                }
+            }
+        }
+        Ok(CheatFile {
+            games: games
+                .into_iter()
+                .map(|(hash, cheats)| (hash.to_lowercase(), cheats))
+                .collect(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CheatFile, CheatError> {
+        CheatFile::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Returns the cheats for the ROM whose hex encoded SHA-1 is |rom_sha1|.
+    pub fn lookup(&self, rom_sha1: &str) -> &[Cheat] {
+        self.games.get(rom_sha1).map_or(&[], |cheats| &cheats[..])
+    }
+}
+
+/// How to narrow down a memory search, comparing each candidate's value now
+/// with its value at the previous step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    fn matches(self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Equal(value) => current == value,
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+        }
+    }
+}
+
+/// Finds the address of a value, such as a score or a number of lives, by
+/// repeatedly filtering memory as the game runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemorySearch {
+    // Memory at the previous step
+    previous: Vec<u8>,
+    // Addresses that matched every filter so far
+    candidates: Vec<usize>,
+}
+
+impl MemorySearch {
+    /// Starts a search with every address in |memory| as a candidate.
+    pub fn new(memory: &[u8]) -> MemorySearch {
+        MemorySearch {
+            previous: memory.to_vec(),
+            candidates: (0..memory.len()).collect(),
+        }
+    }
+
+    /// Keeps the candidates that match |filter|, and remembers |memory| for
+    /// the next step.
+    pub fn filter(&mut self, memory: &[u8], filter: Filter) {
+        let previous = &self.previous;
+        self.candidates.retain(|addr| match memory.get(*addr) {
+            Some(current) => filter.matches(previous[*addr], *current),
+            None => false,
+        });
+        self.previous = memory.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+/// A debugger command, read from the console.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Registers,
+    Search,
+    Filter(Filter),
+    List,
+    Freeze(Cheat),
+    Unfreeze(Target),
+    Cheats,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["help"] => Command::Help,
+            ["regs"] => Command::Registers,
+            ["search"] => Command::Search,
+            ["eq", value] => Command::Filter(Filter::Equal(parse_byte(value)?)),
+            ["changed"] => Command::Filter(Filter::Changed),
+            ["unchanged"] => Command::Filter(Filter::Unchanged),
+            ["inc"] => Command::Filter(Filter::Increased),
+            ["dec"] => Command::Filter(Filter::Decreased),
+            ["list"] => Command::List,
+            ["freeze", target, value] => Command::Freeze(Cheat {
+                name: String::new(),
+                target: parse_target(target)?,
+                value: parse_byte(value)?,
+            }),
+            ["unfreeze", target] => Command::Unfreeze(parse_target(target)?),
+            ["cheats"] => Command::Cheats,
+            _ => return Err(format!("unknown command '{}', try 'help'", line.trim())),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_byte(value: &str) -> Result<u8, String> {
+    u8::from_str_radix(value, 16).map_err(|_| format!("invalid byte '{}'", value))
+}
+
+// Parses a register name such as "V3", or a hex memory address.
+fn parse_target(target: &str) -> Result<Target, String> {
+    let invalid = || format!("invalid address or register '{}'", target);
+    match target.strip_prefix(|c| c == 'V' || c == 'v') {
+        Some(register) if register.len() == 1 => Ok(Target::Register {
+            register: usize::from_str_radix(register, 16).map_err(|_| invalid())?,
+        }),
+        Some(_) => Err(invalid()),
+        None => Ok(Target::Memory {
+            address: usize::from_str_radix(target, 16).map_err(|_| invalid())?,
+        }),
+    }
+}
@@ -0,0 +1,121 @@
+use crate::cheat::*;
+
+const JSON: &str = r#"{
+  "A9993E364706816ABA3E25717850C26C9CD0D89D": [
+    { "name": "Infinite lives", "address": 756, "value": 3 },
+    { "register": 14, "value": 0 }
+  ]
+}"#;
+
+#[test]
+fn lookup_cheats() {
+    let file = CheatFile::from_json(JSON).unwrap();
+    assert_eq!(
+        vec![
+            Cheat {
+                name: String::from("Infinite lives"),
+                target: Target::Memory { address: 0x2F4 },
+                value: 3,
+            },
+            Cheat {
+                name: String::new(),
+                target: Target::Register { register: 0xE },
+                value: 0,
+            },
+        ],
+        file.lookup("a9993e364706816aba3e25717850c26c9cd0d89d")
+    );
+    assert!(file
+        .lookup("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+        .is_empty());
+}
+
+#[test]
+fn invalid_register() {
+    match CheatFile::from_json(r#"{"abc": [{"register": 16, "value": 1}]}"#) {
+        Err(CheatError::InvalidRegister(16)) => {}
+        other => panic!("expected an invalid register, got {:?}", other),
+    }
+}
+
+#[test]
+fn load_invalid() {
+    let path = std::env::temp_dir().join(format!("chip8-bad-cheats-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"abc": [{"value": 1}]}"#).unwrap();
+    let loaded = CheatFile::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    match loaded {
+        Err(CheatError::Parse(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn memory_search() {
+    let mut memory = vec![5, 5, 5, 5];
+    let mut search = MemorySearch::new(&memory);
+    assert_eq!(&[0, 1, 2, 3], search.candidates());
+
+    memory[1] = 4;
+    memory[2] = 6;
+    memory[3] = 7;
+    search.filter(&memory, Filter::Changed);
+    assert_eq!(&[1, 2, 3], search.candidates());
+
+    // Compares with the memory at the previous step, not the first.
+    memory[2] = 7;
+    search.filter(&memory, Filter::Increased);
+    assert_eq!(&[2], search.candidates());
+
+    search.filter(&memory, Filter::Unchanged);
+    assert_eq!(&[2], search.candidates());
+    search.filter(&memory, Filter::Equal(6));
+    assert!(search.candidates().is_empty());
+}
+
+#[test]
+fn memory_search_decreased() {
+    let mut search = MemorySearch::new(&[3, 3, 3]);
+    search.filter(&[2, 3, 4], Filter::Decreased);
+    assert_eq!(&[0], search.candidates());
+}
+
+#[test]
+fn parse_command() {
+    assert_eq!(Ok(Command::Search), Command::parse("search"));
+    assert_eq!(
+        Ok(Command::Filter(Filter::Equal(0x1F))),
+        Command::parse("  eq 1f ")
+    );
+    assert_eq!(
+        Ok(Command::Freeze(Cheat {
+            name: String::new(),
+            target: Target::Memory { address: 0x2F4 },
+            value: 3,
+        })),
+        Command::parse("freeze 2F4 03")
+    );
+    assert_eq!(
+        Ok(Command::Unfreeze(Target::Register { register: 0xA })),
+        Command::parse("unfreeze VA")
+    );
+    assert!(Command::parse("freeze V10 1").is_err());
+    assert!(Command::parse("eq 100").is_err());
+    assert!(Command::parse("poke").is_err());
+}
+
+#[test]
+fn display_cheat() {
+    let cheat = Cheat {
+        name: String::from("Lives"),
+        target: Target::Memory { address: 0x2F4 },
+        value: 3,
+    };
+    assert_eq!("[2F4] = 03 (Lives)", cheat.to_string());
+    let cheat = Cheat {
+        name: String::new(),
+        target: Target::Register { register: 0xE },
+        value: 0xFF,
+    };
+    assert_eq!("VE = FF", cheat.to_string());
+}
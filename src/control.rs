@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod tests;
+
+use crate::cheat::Command;
+use crate::rom;
+use piston_window::Event;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How often a watched ROM is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Messages that control the CPU thread. The window, the debugger console and
+/// anything else that drives the CPU send them down the one channel, so the
+/// CPU can wait on all of them at once.
+#[derive(Debug)]
+pub enum Control {
+    // A key pressed or released in the window
+    Input(Event),
+    // A command typed into the debugger console
+    Command(Command),
+    // Pauses the program, or resumes it with false
+    Pause(bool),
+    // Restarts the program from the beginning
+    Reset,
+    // Replaces the program with the given ROM and starts it
+    LoadRom(Vec<u8>),
+    // Reads the ROM from its file again and starts it
+    Reload,
+    // Stops running, as when the window is closed
+    Quit,
+}
+
+impl Control {
+    /// Parses a line typed into the debugger console. Besides the debugger's
+    /// commands, it can be "pause", "resume", "reset", "reload", "quit" or
+    /// "load FILE", which reads the ROM in FILE.
+    pub fn parse(line: &str) -> Result<Control, String> {
+        let line = line.trim();
+        let control = match line {
+            "pause" => Control::Pause(true),
+            "resume" => Control::Pause(false),
+            "reset" => Control::Reset,
+            "reload" => Control::Reload,
+            "quit" => Control::Quit,
+            _ => match line.strip_prefix("load ") {
+                Some(path) => {
+                    let path = path.trim();
+                    Control::LoadRom(rom::read(path).map_err(|e| format!("{}: {}", path, e))?)
+                }
+                None => Control::Command(Command::parse(line)?),
+            },
+        };
+        Ok(control)
+    }
+}
+
+/// Sends Reload to |controls| every time the file at |path| changes, checking
+/// its size and modification time on another thread. A change is only sent
+/// once the file has stayed the same for a whole interval, so that a ROM still
+/// being written isn't loaded half done. The thread stops once nothing is
+/// listening.
+pub fn watch_rom(path: PathBuf, controls: Sender<Control>) {
+    thread::Builder::new()
+        .name("watch".to_string())
+        .spawn(move || {
+            let mut loaded = file_version(&path);
+            let mut previous = loaded;
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+                let current = file_version(&path);
+                if current.is_some() && current != loaded && current == previous {
+                    loaded = current;
+                    if controls.send(Control::Reload).is_err() {
+                        return;
+                    }
+                }
+                previous = current;
+            }
+        })
+        .expect("failed to spawn watch thread");
+}
+
+// Returns what identifies the contents of the file at |path| without reading
+// it, or None if it can't be read.
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
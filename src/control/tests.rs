@@ -0,0 +1,53 @@
+use crate::cheat::Command;
+use crate::control::*;
+use std::fs;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+#[test]
+fn parse_lifecycle() {
+    assert!(matches!(Control::parse("pause"), Ok(Control::Pause(true))));
+    assert!(matches!(
+        Control::parse(" resume "),
+        Ok(Control::Pause(false))
+    ));
+    assert!(matches!(Control::parse("reset"), Ok(Control::Reset)));
+    assert!(matches!(Control::parse("reload"), Ok(Control::Reload)));
+    assert!(matches!(Control::parse("quit"), Ok(Control::Quit)));
+}
+
+#[test]
+fn parse_debugger_command() {
+    assert!(matches!(
+        Control::parse("search"),
+        Ok(Control::Command(Command::Search))
+    ));
+    assert!(Control::parse("poke").is_err());
+}
+
+#[test]
+fn parse_load() {
+    let path = std::env::temp_dir().join(format!("chip8-control-{}.ch8", std::process::id()));
+    fs::write(&path, [0x12, 0x00]).unwrap();
+    let control = Control::parse(&format!("load {}", path.display()));
+    fs::remove_file(&path).unwrap();
+    match control {
+        Ok(Control::LoadRom(data)) => assert_eq!(vec![0x12, 0x00], data),
+        other => panic!("expected a ROM, got {:?}", other),
+    }
+    let error = Control::parse("load /nonexistent/rom.ch8").unwrap_err();
+    assert!(error.starts_with("/nonexistent/rom.ch8: "));
+}
+
+#[test]
+fn watch_rom_sends_reload_on_change() {
+    let path = std::env::temp_dir().join(format!("chip8-watch-{}.ch8", std::process::id()));
+    fs::write(&path, [0x12, 0x00]).unwrap();
+    let (sender, receiver) = channel();
+    watch_rom(path.clone(), sender);
+    assert!(receiver.recv_timeout(Duration::from_millis(600)).is_err());
+    fs::write(&path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+    let control = receiver.recv_timeout(Duration::from_secs(5));
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(control, Ok(Control::Reload)));
+}
@@ -0,0 +1,156 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::str::FromStr;
+
+// Flags recorded for each address.
+pub const EXECUTED: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITTEN: u8 = 4;
+
+// Addresses per row of the image, and the size of each address in pixels.
+const IMAGE_COLUMNS: usize = 64;
+const IMAGE_SCALE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoverageFormat {
+    Png,
+    Json,
+}
+
+impl FromStr for CoverageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CoverageFormat, String> {
+        match s {
+            "png" => Ok(CoverageFormat::Png),
+            "json" => Ok(CoverageFormat::Json),
+            _ => Err(format!("unknown coverage format '{}'", s)),
+        }
+    }
+}
+
+/// Records which addresses were fetched as instructions, and which were read
+/// or written as data, so code can be told apart from data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage {
+    // EXECUTED, READ and WRITTEN flags for each address
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Coverage {
+        Coverage {
+            flags: vec![0; memory_size],
+        }
+    }
+
+    /// Marks the instruction fetched from |pc|.
+    pub fn fetch(&mut self, pc: usize) {
+        self.mark(pc, 2, EXECUTED);
+    }
+
+    /// Marks |len| bytes read as data from |addr|.
+    pub fn read(&mut self, addr: usize, len: usize) {
+        self.mark(addr, len, READ);
+    }
+
+    /// Marks |len| bytes written as data to |addr|.
+    pub fn write(&mut self, addr: usize, len: usize) {
+        self.mark(addr, len, WRITTEN);
+    }
+
+    // Addresses wrap around memory, like they do for the CPU.
+    fn mark(&mut self, addr: usize, len: usize, flag: u8) {
+        let size = self.flags.len();
+        for offset in 0..len {
+            self.flags[(addr + offset) % size] |= flag;
+        }
+    }
+
+    /// Returns the flags recorded for |addr|.
+    pub fn flags(&self, addr: usize) -> u8 {
+        self.flags.get(addr).copied().unwrap_or(0)
+    }
+
+    /// Returns the ranges of addresses with each flag.
+    pub fn map(&self) -> CoverageMap {
+        CoverageMap {
+            size: self.flags.len(),
+            executed: self.ranges(EXECUTED),
+            read: self.ranges(READ),
+            written: self.ranges(WRITTEN),
+        }
+    }
+
+    // Returns the first and last address of each run of addresses with |flag|.
+    fn ranges(&self, flag: u8) -> Vec<[usize; 2]> {
+        let mut ranges: Vec<[usize; 2]> = Vec::new();
+        for (addr, flags) in self.flags.iter().enumerate() {
+            if flags & flag == 0 {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(range) if range[1] + 1 == addr => range[1] = addr,
+                _ => ranges.push([addr, addr]),
+            }
+        }
+        ranges
+    }
+
+    /// Draws memory as an image with a row for every 64 addresses. Code is
+    /// green, data read is blue and data written is red, mixed where an
+    /// address was used more than one way. Untouched addresses are black.
+    pub fn to_rgb(&self) -> (u32, u32, Vec<u8>) {
+        let rows = self.flags.len().div_ceil(IMAGE_COLUMNS);
+        let width = IMAGE_COLUMNS * IMAGE_SCALE;
+        let height = rows * IMAGE_SCALE;
+        let mut pixels = vec![0; width * height * 3];
+        for (addr, flags) in self.flags.iter().enumerate() {
+            let color = [
+                if flags & WRITTEN != 0 { 0xFF } else { 0 },
+                if flags & EXECUTED != 0 { 0xFF } else { 0 },
+                if flags & READ != 0 { 0xFF } else { 0 },
+            ];
+            let x = addr % IMAGE_COLUMNS * IMAGE_SCALE;
+            let y = addr / IMAGE_COLUMNS * IMAGE_SCALE;
+            for row in y..y + IMAGE_SCALE {
+                for column in x..x + IMAGE_SCALE {
+                    let offset = (row * width + column) * 3;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        }
+        (width as u32, height as u32, pixels)
+    }
+
+    /// Writes the coverage to |path| as a PNG image or a JSON map.
+    pub fn save(&self, format: CoverageFormat, path: &str) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        match format {
+            CoverageFormat::Json => serde_json::to_writer(writer, &self.map())?,
+            CoverageFormat::Png => {
+                use png::HasParameters;
+                let (width, height, pixels) = self.to_rgb();
+                let mut encoder = png::Encoder::new(writer, width, height);
+                encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+                encoder.write_header()?.write_image_data(&pixels)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The addresses used each way, as inclusive ranges, for tools like a
+/// disassembler to split code from data.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CoverageMap {
+    pub size: usize,
+    pub executed: Vec<[usize; 2]>,
+    pub read: Vec<[usize; 2]>,
+    pub written: Vec<[usize; 2]>,
+}
@@ -0,0 +1,123 @@
+use crate::coverage::*;
+use crate::cpu::CPU;
+use std::sync::mpsc::channel;
+
+#[test]
+fn records_flags() {
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0x200);
+    coverage.read(0x300, 2);
+    coverage.write(0x301, 2);
+    assert_eq!(EXECUTED, coverage.flags(0x200));
+    assert_eq!(EXECUTED, coverage.flags(0x201));
+    assert_eq!(0, coverage.flags(0x202));
+    assert_eq!(READ, coverage.flags(0x300));
+    assert_eq!(READ | WRITTEN, coverage.flags(0x301));
+    assert_eq!(WRITTEN, coverage.flags(0x302));
+}
+
+#[test]
+fn wraps_around_memory() {
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0xFFF);
+    coverage.write(0xFFE, 3);
+    assert_eq!(EXECUTED | WRITTEN, coverage.flags(0xFFF));
+    assert_eq!(EXECUTED | WRITTEN, coverage.flags(0x000));
+    assert_eq!(WRITTEN, coverage.flags(0xFFE));
+}
+
+#[test]
+fn map_merges_ranges() {
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0x200);
+    coverage.fetch(0x202);
+    coverage.fetch(0x208);
+    coverage.read(0x300, 5);
+    assert_eq!(
+        CoverageMap {
+            size: 4096,
+            executed: vec![[0x200, 0x203], [0x208, 0x209]],
+            read: vec![[0x300, 0x304]],
+            written: vec![],
+        },
+        coverage.map()
+    );
+}
+
+#[test]
+fn map_round_trips_json() {
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0x200);
+    coverage.write(0x300, 1);
+    let json = serde_json::to_string(&coverage.map()).unwrap();
+    assert_eq!(
+        "{\"size\":4096,\"executed\":[[512,513]],\"read\":[],\"written\":[[768,768]]}",
+        json
+    );
+    assert_eq!(
+        coverage.map(),
+        serde_json::from_str::<CoverageMap>(&json).unwrap()
+    );
+}
+
+#[test]
+fn to_rgb_colors_addresses() {
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0x000);
+    coverage.read(0x001, 1);
+    coverage.write(0x041, 1);
+    let (width, height, pixels) = coverage.to_rgb();
+    assert_eq!((256, 256), (width, height));
+    let pixel = |x: usize, y: usize| {
+        let offset = (y * width as usize + x) * 3;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2]]
+    };
+    assert_eq!([0, 0xFF, 0], pixel(0, 0));
+    assert_eq!([0, 0xFF, 0], pixel(3, 3));
+    assert_eq!([0, 0xFF, 0xFF], pixel(4, 0));
+    assert_eq!([0xFF, 0, 0], pixel(4, 4));
+    assert_eq!([0, 0, 0], pixel(8, 0));
+}
+
+#[test]
+fn save_writes_png() {
+    let path = std::env::temp_dir().join(format!("chip8-coverage-{}.png", std::process::id()));
+    let mut coverage = Coverage::new(4096);
+    coverage.fetch(0x200);
+    coverage
+        .save(CoverageFormat::Png, path.to_str().unwrap())
+        .unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&[0x89, b'P', b'N', b'G'], &data[..4]);
+}
+
+#[test]
+fn parse_format() {
+    assert_eq!(Ok(CoverageFormat::Png), "png".parse());
+    assert_eq!(Ok(CoverageFormat::Json), "json".parse());
+    assert!("gif".parse::<CoverageFormat>().is_err());
+}
+
+#[test]
+fn separates_cpu_code_and_data() {
+    let mut cpu = CPU::new(channel().1);
+    cpu.set_coverage(true);
+    cpu.load_game_data(&[
+        0xA3, 0x00, // 0x200: LD I, 0x300
+        0xF1, 0x65, // 0x202: LD V1, [I]
+        0xA3, 0x10, // 0x204: LD I, 0x310
+        0xF0, 0x55, // 0x206: LD [I], V0
+        0xD0, 0x03, // 0x208: DRW V0, V0, 3
+        0xF0, 0x33, // 0x20A: LD B, V0
+    ])
+    .unwrap();
+    for _ in 0..6 {
+        cpu.step().unwrap();
+    }
+    let map = cpu.coverage().unwrap().map();
+    assert_eq!(vec![[0x200, 0x20B]], map.executed);
+    // DRW reads from I after LD [I], V0 advanced it.
+    assert_eq!(vec![[0x300, 0x301], [0x311, 0x313]], map.read);
+    assert_eq!(vec![[0x310, 0x313]], map.written);
+}
@@ -1,27 +1,89 @@
+mod controls;
+mod debugger;
 mod decode;
 #[cfg(test)]
+mod differential;
+mod disassemble;
+#[cfg(test)]
+#[macro_use]
+mod execute_tests;
+mod gdb;
+mod idle;
+mod recompile;
+#[cfg(test)]
+mod reference;
+mod scripting;
+mod snapshot;
+#[cfg(test)]
 mod tests;
 
 use log::*;
 use piston_window::*;
 use rand::prelude::*;
 use rand::rngs::mock::StepRng;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::Result;
-use std::sync::mpsc::Receiver;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::sync::RwLock;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use self::decode::*;
+pub use self::disassemble::disassemble;
+pub use self::gdb::SessionEnd;
+use self::recompile::Blocks;
+pub use self::snapshot::Snapshot;
 use self::OpCode::*;
+use super::cheat::{Cheat, MemorySearch};
+use super::control::Control;
+use super::coverage::Coverage;
+use super::database;
 use super::digits::DIGITS;
-use super::FRAME_BUFFER_BYTES;
+use super::display::Display;
+use super::memory_view::{MemoryView, Poke};
+use super::movie::{decode_keys, encode_keys, Movie};
+use super::platform::{Platform, PROGRAM_START};
+use super::profile::{Profiler, Report};
+use super::quirks::Quirks;
+use super::rom;
+use super::rom::RomError;
+use super::script::{Hook, Script};
+use super::trace::{TraceRecord, Tracer};
 
-const SLEEP_DURATION: Duration = Duration::from_micros(1500);
+// Prints the registers and call stack when pressed.
+const DEBUG_VIEW_KEY: Key = Key::F1;
+// Pauses or resumes the emulation.
+const PAUSE_KEY: Key = Key::F2;
+// Runs a single frame while paused.
+const FRAME_ADVANCE_KEY: Key = Key::F3;
+// Toggles slow motion.
+const SLOW_MOTION_KEY: Key = Key::F4;
+// Runs the emulation as fast as possible while held.
+const FAST_FORWARD_KEY: Key = Key::Tab;
+// Save the state and restore it later.
+const SAVE_STATE_KEY: Key = Key::F5;
+const LOAD_STATE_KEY: Key = Key::F9;
+// Restarts the program from the beginning.
+const RESET_KEY: Key = Key::F7;
+// Reads the ROM from its file again and restarts it.
+const RELOAD_ROM_KEY: Key = Key::F8;
+// How many times longer each frame lasts in slow motion.
+const SLOW_MOTION_FACTOR: u32 = 4;
+// The stack depth of the original interpreter.
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+// Far more than any program needs, to keep a bad setting from using up memory.
+pub const MAX_STACK_DEPTH: usize = 256;
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+// Roughly the speed this interpreter has always run at.
+pub const DEFAULT_TICK_RATE: u32 = 11;
+// The size of the COSMAC VIP's CHIP-8 interpreter, which sits below the
+// program.
+const VIP_INTERPRETER_SIZE: usize = 0x200;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum OpCode {
@@ -122,136 +184,912 @@ enum OpCode {
         reg_y: usize,
     },
     Sys,
+    // Any opcode this interpreter doesn't recognize
+    Unknown {
+        opcode: u16,
+    },
     XorRegs {
         reg_x: usize,
         reg_y: usize,
     },
 }
 
+/// An error that stops the program, because the interpreter can't sensibly
+/// continue. The state is left as it was before the faulting instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    UnknownOpcode { pc: usize, opcode: u16 },
+    // |call_chain| has the addresses of the calls already on the stack, from
+    // outermost to innermost.
+    StackOverflow { pc: usize, call_chain: Vec<usize> },
+    StackUnderflow { pc: usize },
+    // A script hook failed, or one of its assertions didn't hold.
+    Script { pc: usize, message: String },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc)
+            }
+            Fault::StackOverflow { pc, call_chain } => {
+                write!(f, "stack overflow at {:03X}, call chain:", pc)?;
+                for call_site in call_chain {
+                    write!(f, " {:03X}", call_site)?;
+                }
+                Ok(())
+            }
+            Fault::StackUnderflow { pc } => write!(f, "stack underflow at {:03X}", pc),
+            Fault::Script { pc, message } => write!(f, "at {:03X}, {}", pc, message),
+        }
+    }
+}
+
+impl error::Error for Fault {}
+
+/// A subroutine call that hasn't returned yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CallFrame {
+    // Address of the call instruction
+    pub call_site: usize,
+    // Address of the subroutine that was called, unless the call instruction
+    // has since been overwritten
+    pub target: Option<usize>,
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     // General-purpose registers
     v: [u8; 16],
     // Memory address register
     i: usize,
+    // TODO: implement the sound timer
+    #[allow(dead_code)]
     sound_timer: u8,
-    delay_timer: Arc<Mutex<u8>>,
+    delay_timer: u8,
 
     // Program counter
     pc: usize,
-    stack: [u16; 16],
+    // Addresses of the call instructions of the active subroutines
+    stack: Vec<u16>,
     // Stack pointer
     sp: usize,
-    // Address space
-    memory: [u8; 4096],
+    // Address space, sized according to the platform
+    memory: Vec<u8>,
+    // Decoded instructions by address, or empty if the cache is disabled
+    decoded: Vec<Option<OpCode>>,
+    // Translated blocks, if the recompiler is enabled
+    blocks: Option<Blocks>,
+    platform: Platform,
+    quirks: Quirks,
+    // Number of instructions executed per 60Hz frame
+    tick_rate: u32,
+    // Host keys mapped to keypad keys, in addition to the default layout
+    key_bindings: HashMap<Key, usize>,
     // State of the 16 input keys
     key_state: [bool; 16],
+    // Whether Fx0A waits by checking the key state each time it runs, rather
+    // than blocking on key events. Movies need this, since they only capture
+    // the key state once per frame.
+    poll_keys: bool,
+    // The movie being recorded, if any
+    movie: Option<Movie>,
 
-    frame_buffer: Arc<RwLock<[u8; FRAME_BUFFER_BYTES]>>,
+    display: Display,
+    // Where to publish each completed frame, for the window to show
+    frame_sender: Option<SyncSender<Display>>,
+    // Generation of the display as last published
+    published_generation: Option<u64>,
 
     // Random number generator used for Rand operations
     rng: WrappedRng,
+    // The page of the VIP interpreter its random routine reads, if given
+    vip_code_page: Option<Arc<[u8; 256]>>,
+
+    // Number of instructions executed so far
+    cycles: u64,
+    // Number of 60Hz frames run so far
+    frames: u64,
+    speed: Speed,
+    // The state saved with the save state key
+    saved_state: Option<Snapshot>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    // Set by Dxyn with the vblank quirk, to end the frame early
+    waiting_for_vblank: bool,
+    // Whether to fast-forward through loops waiting on the delay timer
+    skip_idle_loops: bool,
+    memory_view: Option<MemoryViewLink>,
+    // Memory addresses and registers held at fixed values
+    cheats: Vec<Cheat>,
+    // The memory search run from the debugger, if any
+    search: Option<MemorySearch>,
+    script: Option<Script>,
+    // The program as loaded, to start again from on reset
+    rom: Vec<u8>,
+    // The file the program was read from, to reload it from
+    rom_path: Option<PathBuf>,
+
+    // Key events, debugger commands and requests to pause, reset, load a ROM
+    // or quit, from the window and the console
+    controls: Receiver<Control>,
+    // Controls received while waiting for a key, to handle between frames
+    pending_controls: VecDeque<Control>,
+    // Set once the CPU has been asked to stop, or nothing can control it
+    quitting: bool,
+}
+
+// Connects the CPU to the memory viewer.
+struct MemoryViewLink {
+    view: Arc<RwLock<MemoryView>>,
+    pokes: Receiver<Poke>,
+    // One more than the frame each address was last written in
+    last_write: Vec<u64>,
+}
+
+/// How the emulation is paced, as controlled from the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Speed {
+    pub paused: bool,
+    // Whether a single frame should run even though the emulation is paused
+    pub frame_advance: bool,
+    pub slow_motion: bool,
+    pub fast_forward: bool,
+}
+
+/// The routine that generates random numbers for Cxkk.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RandomRoutine {
+    // A xorshift64* generator
+    #[default]
+    Xorshift,
+    // Modeled on the COSMAC VIP interpreter's routine
+    Vip,
+}
+
+impl FromStr for RandomRoutine {
+    type Err = String;
 
-    // Allows the CPU to be notified when the emulator window is closed, so it can complete as
-    // well.
-    window_closed_receiver: Receiver<bool>,
-    key_event_receiver: Receiver<Event>,
+    fn from_str(s: &str) -> Result<RandomRoutine, String> {
+        match s {
+            "xorshift" => Ok(RandomRoutine::Xorshift),
+            "vip" => Ok(RandomRoutine::Vip),
+            _ => Err(format!("unknown random routine '{}'", s)),
+        }
+    }
 }
 
 #[allow(dead_code)]
+#[derive(Clone)]
 enum WrappedRng {
-    // Used for normal operation
-    Standard(ThreadRng),
+    // Used for normal operation, seeded randomly unless the run needs to be
+    // reproducible. Holds the state of a xorshift64* generator.
+    Seeded(u64),
+    // Runs the VIP interpreter's routine.
+    Vip(VipRandom),
     // Used for testing
     Mock(StepRng),
 }
 
 impl WrappedRng {
+    fn seeded(seed: u64) -> WrappedRng {
+        // The state must not be zero.
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        WrappedRng::Seeded(if state == 0 { seed } else { state })
+    }
+
+    // The VIP routine needs the interpreter's |code_page|; without it, this
+    // falls back to xorshift.
+    fn new(routine: RandomRoutine, seed: u64, code_page: Option<&Arc<[u8; 256]>>) -> WrappedRng {
+        match (routine, code_page) {
+            (RandomRoutine::Xorshift, _) => WrappedRng::seeded(seed),
+            (RandomRoutine::Vip, Some(code_page)) => WrappedRng::Vip(VipRandom {
+                r9: seed as u16,
+                code_page: code_page.clone(),
+            }),
+            (RandomRoutine::Vip, None) => {
+                warn!("The VIP random routine needs the VIP interpreter, using xorshift");
+                WrappedRng::seeded(seed)
+            }
+        }
+    }
+
+    fn routine(&self) -> RandomRoutine {
+        match self {
+            WrappedRng::Vip(_) => RandomRoutine::Vip,
+            _ => RandomRoutine::Xorshift,
+        }
+    }
+
     fn gen_byte(&mut self) -> u8 {
         match self {
-            WrappedRng::Standard(rng) => rng.gen(),
+            WrappedRng::Seeded(state) => {
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            WrappedRng::Vip(vip) => vip.gen_byte(),
             WrappedRng::Mock(rng) => rng.gen(),
         }
     }
 }
 
+#[derive(Clone)]
+struct VipRandom {
+    // The VIP's R9 register
+    r9: u16,
+    // The second page of the VIP interpreter, 0x100 to 0x1FF, which its
+    // routine reads
+    code_page: Arc<[u8; 256]>,
+}
+
+impl VipRandom {
+    // Like the VIP interpreter, advances the low byte of R9, adds the byte it
+    // points to in the interpreter's code page into the high byte, and returns
+    // the high byte.
+    fn gen_byte(&mut self) -> u8 {
+        let [high, low] = self.r9.to_be_bytes();
+        let low = low.wrapping_add(1);
+        let high = high.wrapping_add(self.code_page[usize::from(low)]);
+        self.r9 = u16::from_be_bytes([high, low]);
+        high
+    }
+}
+
 impl CPU {
-    pub fn new(
-        delay_timer: Arc<Mutex<u8>>,
-        frame_buffer: Arc<RwLock<[u8; FRAME_BUFFER_BYTES]>>,
-        window_closed_receiver: Receiver<bool>,
-        key_event_receiver: Receiver<Event>,
-    ) -> CPU {
-        let mut memory = [0 as u8; 4096];
-        memory[..DIGITS.len()].clone_from_slice(&DIGITS);
+    pub fn new(controls: Receiver<Control>) -> CPU {
+        let platform = Platform::default();
         CPU {
             v: [0; 16],
             i: 0,
             sound_timer: 0,
-            delay_timer,
+            delay_timer: 0,
             // Most chip8 programs start at 0x200
-            pc: 0x200,
-            stack: [0; 16],
+            pc: PROGRAM_START,
+            stack: vec![0; DEFAULT_STACK_DEPTH],
             sp: 0,
-            memory,
+            memory: initial_memory(platform),
+            decoded: vec![None; platform.memory_size()],
+            blocks: None,
+            platform,
+            quirks: Quirks::default(),
+            tick_rate: DEFAULT_TICK_RATE,
+            key_bindings: HashMap::new(),
             key_state: [false; 16],
-            frame_buffer,
-            rng: WrappedRng::Standard(thread_rng()),
-            window_closed_receiver,
-            key_event_receiver,
+            poll_keys: false,
+            movie: None,
+            display: Display::default(),
+            frame_sender: None,
+            published_generation: None,
+            rng: WrappedRng::seeded(random()),
+            vip_code_page: None,
+            cycles: 0,
+            frames: 0,
+            speed: Speed::default(),
+            saved_state: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            waiting_for_vblank: false,
+            skip_idle_loops: true,
+            memory_view: None,
+            cheats: Vec::new(),
+            search: None,
+            script: None,
+            rom: Vec::new(),
+            rom_path: None,
+            controls,
+            pending_controls: VecDeque::new(),
+            quitting: false,
+        }
+    }
+
+    /// Switches the emulated platform. This resets memory, so it must be called
+    /// before loading the game data.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.memory = initial_memory(platform);
+        if !self.decoded.is_empty() {
+            self.set_instruction_cache(true);
+        }
+        if self.blocks.is_some() {
+            self.set_recompiler(true);
+        }
+        if self.profiler.is_some() {
+            self.set_profiling(true);
+        }
+        if self.coverage.is_some() {
+            self.set_coverage(true);
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: u32) {
+        self.tick_rate = tick_rate.max(1);
+    }
+
+    pub fn set_key_bindings(&mut self, key_bindings: HashMap<Key, usize>) {
+        self.key_bindings = key_bindings;
+    }
+
+    /// Sets how many nested subroutine calls are allowed. Some SCHIP programs
+    /// need more than the original 16, but none more than |MAX_STACK_DEPTH|.
+    /// This clears the stack, so it must be called before running the
+    /// program.
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack = vec![0; depth.clamp(1, MAX_STACK_DEPTH)];
+        self.sp = 0;
+    }
+
+    /// Returns the active subroutine calls, from outermost to innermost.
+    pub fn call_stack(&self) -> Vec<CallFrame> {
+        self.stack[..self.sp]
+            .iter()
+            .map(|call_site| {
+                let call_site = *call_site as usize;
+                let code = [
+                    self.memory[self.address(call_site)],
+                    self.memory[self.address(call_site + 1)],
+                ];
+                let target = match decode_instruction(&code) {
+                    Call { addr } => Some(addr),
+                    _ => None,
+                };
+                CallFrame { call_site, target }
+            })
+            .collect()
+    }
+
+    /// Describes the registers and the call stack, for debugging.
+    pub fn debug_view(&self) -> String {
+        let mut view = format!(
+            "PC {:03X}  I {:03X}  SP {}/{}  DT {}\n",
+            self.pc,
+            self.i,
+            self.sp,
+            self.stack.len(),
+            self.delay_timer
+        );
+        for (reg, val) in self.v.iter().enumerate() {
+            view.push_str(&format!(
+                "V{:X} {:02X}{}",
+                reg,
+                val,
+                if reg % 8 == 7 { "\n" } else { "  " }
+            ));
+        }
+        view.push_str("Call stack:\n");
+        for (depth, frame) in self.call_stack().iter().enumerate() {
+            match frame.target {
+                Some(target) => view.push_str(&format!(
+                    "  #{} {:03X}: CALL {:03X}\n",
+                    depth, frame.call_site, target
+                )),
+                None => view.push_str(&format!(
+                    "  #{} {:03X}: (overwritten)\n",
+                    depth, frame.call_site
+                )),
+            }
+        }
+        view
+    }
+
+    /// Makes the random numbers reproducible, by generating them from |seed|
+    /// with the current routine.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.set_random(self.rng.routine(), seed);
+    }
+
+    /// Generates random numbers with |routine|, starting from |seed|.
+    /// The VIP routine needs the interpreter, see |set_vip_interpreter|.
+    pub fn set_random(&mut self, routine: RandomRoutine, seed: u64) {
+        self.rng = WrappedRng::new(routine, seed, self.vip_code_page.as_ref());
+    }
+
+    /// Takes the page of the interpreter that the VIP random routine reads
+    /// from |image|, a dump of the COSMAC VIP's CHIP-8 interpreter, 0x000 to
+    /// 0x1FF.
+    pub fn set_vip_interpreter(&mut self, image: &[u8]) -> Result<(), String> {
+        if image.len() != VIP_INTERPRETER_SIZE {
+            return Err(format!(
+                "expected the {} byte VIP interpreter, got {} bytes",
+                VIP_INTERPRETER_SIZE,
+                image.len()
+            ));
         }
+        let mut code_page = [0; 256];
+        code_page.copy_from_slice(&image[0x100..]);
+        self.vip_code_page = Some(Arc::new(code_page));
+        Ok(())
+    }
+
+    /// Starts recording the key state in every frame into |movie|, generating
+    /// random numbers as the movie specifies. The movie takes the quirks and
+    /// stack depth the CPU is set up with, so they must be set first.
+    pub fn record_movie(&mut self, mut movie: Movie) {
+        movie.quirks = self.quirks;
+        movie.stack_depth = self.stack.len();
+        self.set_random(movie.random, movie.seed);
+        self.poll_keys = true;
+        self.movie = Some(movie);
+    }
+
+    /// Stops recording, and returns the movie along with the final frame.
+    pub fn finish_movie(&mut self) -> Option<Movie> {
+        let mut movie = self.movie.take()?;
+        movie.final_frame_sha1 = Some(self.frame_sha1());
+        Some(movie)
     }
 
-    pub fn load_game_data(&mut self, file_name: &str) -> Result<()> {
-        let mut game_file = File::open(file_name)?;
-        assert!(game_file.read(&mut self.memory[0x200..]).unwrap() > 0);
+    /// Replays the frames of |movie| as fast as possible, without taking any
+    /// input from the window, with the tick rate, quirks and stack depth it
+    /// was recorded with. The ROM and platform must already match the
+    /// movie's.
+    pub fn play_movie(&mut self, movie: &Movie) -> Result<(), Fault> {
+        self.set_tick_rate(movie.tick_rate);
+        self.set_quirks(movie.quirks);
+        self.set_stack_depth(movie.stack_depth);
+        self.set_random(movie.random, movie.seed);
+        self.poll_keys = true;
+        for frame in &movie.frames {
+            if self.script_exited() {
+                break;
+            }
+            self.key_state = decode_keys(*frame);
+            self.run_frame()?;
+        }
         Ok(())
     }
 
+    /// Returns the hex encoded SHA-1 of the display, to compare runs.
+    pub fn frame_sha1(&self) -> String {
+        database::hash(self.display.as_bytes())
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Counts every instruction run from now on. Profiling runs with the
+    /// interpreter.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler = if enabled {
+            Some(Profiler::new(self.memory.len()))
+        } else {
+            None
+        };
+    }
+
+    /// Summarizes the instructions run while profiling, if enabled. With
+    /// |annotate|, the report includes a disassembly of the program.
+    pub fn profile_report(&self, annotate: bool) -> Option<Report> {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.report(&self.memory, annotate))
+    }
+
+    /// Records which addresses are run as code and which are used as data
+    /// from now on. Coverage is recorded with the interpreter.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled {
+            Some(Coverage::new(self.memory.len()))
+        } else {
+            None
+        };
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn load_game_data(&mut self, data: &[u8]) -> Result<(), RomError> {
+        rom::validate(data, self.platform)?;
+        self.memory[PROGRAM_START..PROGRAM_START + data.len()].copy_from_slice(data);
+        self.rom = data.to_vec();
+        if !self.decoded.is_empty() {
+            self.set_instruction_cache(true);
+        }
+        if self.blocks.is_some() {
+            self.set_recompiler(true);
+        }
+        Ok(())
+    }
+
+    /// Runs frames until told to quit, every sender of controls is gone or
+    /// the program faults, paced according to the speed controls.
     pub fn run(&mut self) {
+        let mut next_frame = Instant::now();
         loop {
-            if let Ok(true) = self.window_closed_receiver.try_recv() {
+            self.handle_controls();
+            if self.quitting {
                 break;
             }
+            self.update_memory_view();
+            if self.speed.paused && !self.speed.frame_advance {
+                // Restoring a state or resetting changes the display between
+                // frames.
+                self.publish_frame();
+                thread::sleep(FRAME_DURATION);
+                next_frame = Instant::now();
+                continue;
+            }
+            self.speed.frame_advance = false;
 
-            self.update_key_state();
+            if let Some(movie) = &mut self.movie {
+                movie.frames.push(encode_keys(&self.key_state));
+            }
 
-            let pc = self.pc as usize;
-            let code = &self.memory[pc..pc + 2];
-            let instr = decode_instruction(&code);
-            self.execute(instr);
+            if let Err(fault) = self.run_frame() {
+                error!("Halting: {}\n{}", fault, self.debug_view());
+                break;
+            }
+            if self.script_exited() {
+                break;
+            }
 
-           thread::sleep(SLEEP_DURATION);
+            let now = Instant::now();
+            if self.speed.fast_forward {
+                next_frame = now;
+                continue;
+            }
+            next_frame += if self.speed.slow_motion {
+                FRAME_DURATION * SLOW_MOTION_FACTOR
+            } else {
+                FRAME_DURATION
+            };
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                // Don't try to catch up after falling behind.
+                next_frame = now;
+            }
         }
     }
 
-    fn update_key_state(&mut self) {
-        while let Ok(event) = self.key_event_receiver.try_recv() {
-            event.press(|button| {
-                if let Some(keycode) = decode_key(button) {
-                    self.key_state[keycode] = true;
+    /// Applies the cheats, calls the script's frame hook, runs one 60Hz
+    /// frame's worth of instructions, then ticks the timers. With the vblank
+    /// quirk, a draw ends the frame early. Timers follow the
+    /// emulated frames rather than the wall clock, so they stay consistent
+    /// when the emulation is sped up or slowed down.
+    pub fn run_frame(&mut self) -> Result<(), Fault> {
+        self.begin_frame()?;
+        let tick_rate = self.tick_rate as usize;
+        let mut executed = 0;
+        while executed < tick_rate
+            && !self.waiting_for_vblank
+            && self.pending_controls.is_empty()
+            && !self.quitting
+        {
+            let remaining = tick_rate - executed;
+            executed += match self.skip_idle_loop(remaining) {
+                0 => self.run_block_within(remaining)?,
+                skipped => skipped,
+            };
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    fn begin_frame(&mut self) -> Result<(), Fault> {
+        self.apply_cheats();
+        self.run_hook(Hook::Frame)
+    }
+
+    fn end_frame(&mut self) {
+        self.waiting_for_vblank = false;
+        self.tick_timers();
+        self.frames += 1;
+        self.publish_frame();
+    }
+
+    /// Sends a copy of the display to |sender| at the end of every frame that
+    /// changed it. Only complete frames are sent, so the receiver never sees
+    /// one half drawn, and it never has to wait on the CPU to read them.
+    /// |sender| should hold a single frame, so frames the receiver hasn't
+    /// caught up with don't pile up; one that doesn't fit is sent again with
+    /// the next frame.
+    pub fn set_frame_sender(&mut self, sender: SyncSender<Display>) {
+        self.frame_sender = Some(sender);
+        self.published_generation = None;
+    }
+
+    fn publish_frame(&mut self) {
+        let sender = match &self.frame_sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        if self.published_generation == Some(self.display.generation()) {
+            return;
+        }
+        match sender.try_send(self.display.clone()) {
+            // The receiver hasn't taken the last frame yet.
+            Err(TrySendError::Full(_)) => return,
+            // The window may already be closed, in which case nobody is
+            // watching.
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+        }
+        self.published_generation = Some(self.display.generation());
+    }
+
+    /// Publishes memory to |view| every frame while it's shown, and applies the bytes received
+    /// from |pokes| while paused.
+    pub fn set_memory_view(&mut self, view: Arc<RwLock<MemoryView>>, pokes: Receiver<Poke>) {
+        self.memory_view = Some(MemoryViewLink {
+            view,
+            pokes,
+            last_write: vec![0; self.memory.len()],
+        });
+    }
+
+    fn update_memory_view(&mut self) {
+        if self.memory_view.is_none() {
+            return;
+        }
+        if self.speed.paused {
+            let pokes: Vec<Poke> = self
+                .memory_view
+                .as_ref()
+                .map(|link| link.pokes.try_iter().collect())
+                .unwrap_or_default();
+            for poke in pokes {
+                info!("Poking {:02X} into {:03X}", poke.value, poke.addr);
+                self.write_memory(poke.addr, poke.value);
+            }
+        }
+        if let Some(link) = &mut self.memory_view {
+            link.last_write.resize(self.memory.len(), 0);
+            let mut view = link.view.write().unwrap();
+            if !view.shown {
+                return;
+            }
+            view.memory.clone_from(&self.memory);
+            view.pc = self.pc;
+            view.i = self.i;
+            view.paused = self.speed.paused;
+            view.frame = self.frames;
+            view.last_write.clone_from(&link.last_write);
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        // The VIP's interrupt routine advanced R9 every frame, so the random
+        // numbers depend on timing.
+        if let WrappedRng::Vip(vip) = &mut self.rng {
+            vip.r9 = vip.r9.wrapping_add(1);
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        if self.script.is_some() {
+            self.run_hook(Hook::Instruction(self.pc))?;
+        }
+        let instr = self.decode();
+        if self.tracer.is_some() {
+            self.trace(instr);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(self.pc, instr.kind());
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.fetch(self.pc);
+        }
+        self.execute(instr)?;
+        self.cycles += 1;
+        Ok(())
+    }
+
+    // Decodes the instruction at PC, reusing the result of earlier decodes
+    // when the instruction cache is enabled.
+    fn decode(&mut self) -> OpCode {
+        if self.decoded.is_empty() {
+            return decode_instruction(&self.fetch());
+        }
+        match self.decoded[self.pc] {
+            Some(instr) => instr,
+            None => {
+                let instr = decode_instruction(&self.fetch());
+                self.decoded[self.pc] = Some(instr);
+                instr
+            }
+        }
+    }
+
+    /// Enables or disables caching decoded instructions. The cache is enabled
+    /// by default.
+    pub fn set_instruction_cache(&mut self, enabled: bool) {
+        self.decoded = if enabled {
+            vec![None; self.memory.len()]
+        } else {
+            Vec::new()
+        };
+    }
+
+    // Writes to memory, invalidating any cached instruction that overlaps
+    // |addr|, so self-modifying code behaves correctly.
+    fn write_memory(&mut self, addr: usize, value: u8) {
+        let addr = self.address(addr);
+        self.memory[addr] = value;
+        if !self.decoded.is_empty() {
+            let previous = self.address(addr.wrapping_sub(1));
+            self.decoded[addr] = None;
+            self.decoded[previous] = None;
+        }
+        if let Some(blocks) = &mut self.blocks {
+            blocks.invalidate(addr);
+        }
+        if let Some(link) = &mut self.memory_view {
+            if let Some(written) = link.last_write.get_mut(addr) {
+                *written = self.frames + 1;
+            }
+        }
+    }
+
+    // Records |len| bytes from |addr| used as data by the program.
+    fn cover_data(&mut self, addr: usize, len: usize, written: bool) {
+        let addr = self.address(addr);
+        match &mut self.coverage {
+            Some(coverage) if written => coverage.write(addr, len),
+            Some(coverage) => coverage.read(addr, len),
+            None => {}
+        }
+    }
+
+    fn fetch(&self) -> [u8; 2] {
+        [
+            self.memory[self.address(self.pc)],
+            self.memory[self.address(self.pc + 1)],
+        ]
+    }
+
+    // Wraps |addr| around the address space, so that out of range accesses by
+    // a misbehaving program can't panic.
+    fn address(&self, addr: usize) -> usize {
+        addr & (self.memory.len() - 1)
+    }
+
+    fn trace(&mut self, instr: OpCode) {
+        let record = TraceRecord {
+            cycle: self.cycles,
+            pc: self.pc as u16,
+            opcode: u16::from_be_bytes(self.fetch()),
+            mnemonic: instr.to_string(),
+            v: self.v,
+            i: self.i as u16,
+            sp: self.sp as u16,
+        };
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.record(&record) {
+                warn!("Unable to write trace, disabling it: {}", e);
+                self.tracer = None;
+            }
+        }
+    }
+
+    fn handle_key_event(&mut self, event: &Event) {
+        event.press(|button| {
+            if self.handle_control_key(button, true) {
+                return;
+            }
+            if let Some(keycode) = self.keycode(button) {
+                self.key_state[keycode] = true;
+            }
+        });
+        event.release(|button| {
+            if self.handle_control_key(button, false) {
+                return;
+            }
+            if let Some(keycode) = self.keycode(button) {
+                self.key_state[keycode] = false;
+            }
+        });
+    }
+
+    // Handles the host keys that control the emulator rather than the
+    // program. Returns whether |button| is one of them.
+    fn handle_control_key(&mut self, button: Button, pressed: bool) -> bool {
+        let key = match button {
+            Button::Keyboard(key) => key,
+            _ => return false,
+        };
+        match key {
+            _ if key == DEBUG_VIEW_KEY => {
+                if pressed {
+                    eprint!("{}", self.debug_view());
+                }
+            }
+            _ if key == PAUSE_KEY => {
+                if pressed {
+                    self.speed.paused = !self.speed.paused;
+                    info!("Paused: {}", self.speed.paused);
                 }
-            });
-            event.release(|button| {
-                if let Some(keycode) = decode_key(button) {
-                    self.key_state[keycode] = false;
+            }
+            _ if key == FRAME_ADVANCE_KEY => {
+                if pressed && self.speed.paused {
+                    self.speed.frame_advance = true;
+                }
+            }
+            _ if key == SLOW_MOTION_KEY => {
+                if pressed {
+                    self.speed.slow_motion = !self.speed.slow_motion;
+                    info!("Slow motion: {}", self.speed.slow_motion);
                 }
-            });
+            }
+            _ if key == FAST_FORWARD_KEY => self.speed.fast_forward = pressed,
+            // Like restarting, restoring a state would leave a movie that
+            // doesn't replay.
+            _ if (key == SAVE_STATE_KEY || key == LOAD_STATE_KEY) && self.movie.is_some() => {
+                if pressed {
+                    warn!("Unable to save or restore the state while recording a movie");
+                }
+            }
+            _ if key == SAVE_STATE_KEY => {
+                if pressed {
+                    self.saved_state = Some(self.snapshot());
+                    info!("Saved state at cycle {}", self.cycles);
+                }
+            }
+            _ if key == LOAD_STATE_KEY => {
+                if pressed {
+                    if let Some(snapshot) = self.saved_state.take() {
+                        self.restore(&snapshot);
+                        info!("Restored state from cycle {}", snapshot.cycles());
+                        self.saved_state = Some(snapshot);
+                    }
+                }
+            }
+            _ if key == RESET_KEY => {
+                if pressed {
+                    self.handle_control(Control::Reset);
+                }
+            }
+            _ if key == RELOAD_ROM_KEY => {
+                if pressed {
+                    self.handle_control(Control::Reload);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Maps a host button to a keypad key, preferring the per-game bindings.
+    fn keycode(&self, button: Button) -> Option<usize> {
+        if let Button::Keyboard(key) = button {
+            if let Some(keycode) = self.key_bindings.get(&key) {
+                return Some(*keycode);
+            }
         }
+        decode_key(button)
     }
 
-    fn execute(&mut self, op: OpCode) {
+    fn execute(&mut self, op: OpCode) -> Result<(), Fault> {
         let mut new_pc = self.pc + 2;
         match op {
             AddIReg { reg } => {
                 let reg_val = self.v[reg];
-                info!(
+                trace!(
                     "Adding {:x} from reg V{} to I's current value {:x}",
-                    reg_val, reg, self.i
+                    reg_val,
+                    reg,
+                    self.i
                 );
                 self.i += reg_val as usize;
             }
             AddRegByte { reg, val } => {
-                info!(
+                trace!(
                     "Adding val {:x} to register V{} to get value: {}",
                     val,
                     reg,
@@ -260,36 +1098,50 @@ impl CPU {
                 self.v[reg] = self.v[reg].wrapping_add(val);
             }
             AddRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "Adding val {}(V{}) and {}(V{}), storing in V{}",
-                    self.v[reg_x], reg_x, self.v[reg_y], reg_y, reg_x
+                    self.v[reg_x],
+                    reg_x,
+                    self.v[reg_y],
+                    reg_y,
+                    reg_x
                 );
                 let (sum, did_overflow) = self.v[reg_x].overflowing_add(self.v[reg_y]);
                 self.v[reg_x] = sum;
                 self.v[0xF] = if did_overflow { 1 } else { 0 };
             }
             AndRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "AND-ing register V{} and V{}, storing value in V{}",
-                    reg_x, reg_y, reg_x
+                    reg_x,
+                    reg_y,
+                    reg_x
                 );
                 self.v[reg_x] &= self.v[reg_y];
+                self.reset_vf_after_logic();
             }
             Call { addr } => {
-                info!(
+                trace!(
                     "Storing current PC {:x} on the stack and jumping to {:x}",
-                    self.pc, addr
+                    self.pc,
+                    addr
                 );
+                if self.sp == self.stack.len() {
+                    return Err(Fault::StackOverflow {
+                        pc: self.pc,
+                        call_chain: self.stack[..self.sp]
+                            .iter()
+                            .map(|call_site| *call_site as usize)
+                            .collect(),
+                    });
+                }
                 self.stack[self.sp] = self.pc as u16;
                 self.sp += 1;
                 new_pc = addr;
             }
             Clear => {
-                info!("Clearing screen");
-                {
-                    let mut fb = self.frame_buffer.write().unwrap();
-                    *fb = [0; FRAME_BUFFER_BYTES];
-                }
+                trace!("Clearing screen");
+                self.display.clear();
             }
             Draw {
                 reg_x,
@@ -299,27 +1151,35 @@ impl CPU {
                 let sprite_bytes = sprite_bytes as usize;
                 let x = self.v[reg_x];
                 let y = self.v[reg_y];
-                info!(
+                trace!(
                     "Drawing {} bytes of sprite from address {:x} at location {},{} on the screen",
-                    sprite_bytes, self.i, x, y
+                    sprite_bytes,
+                    self.i,
+                    x,
+                    y
                 );
+                self.cover_data(self.i, sprite_bytes, false);
                 self.draw_sprite(self.i, sprite_bytes, x, y);
+                // The VIP drew sprites during the vertical blank, stalling
+                // the program until the next frame.
+                self.waiting_for_vblank = self.quirks.vblank;
             }
             LdDtReg { reg } => {
-                info!("Loading delay timer with {}(V{})", self.v[reg], reg);
-                let mut delay_timer = self.delay_timer.lock().unwrap();
-                *delay_timer = self.v[reg];
+                trace!("Loading delay timer with {}(V{})", self.v[reg], reg);
+                self.delay_timer = self.v[reg];
             }
             LdIAddr { addr } => {
-                info!("Loading reg I with address {:x}", addr);
-                self.i = addr as usize;
+                trace!("Loading reg I with address {:x}", addr);
+                self.i = addr;
             }
             LdIDigitReg { reg } => {
                 let sprite_digit = self.v[reg];
                 let addr = 5 * u16::from(sprite_digit);
-                info!(
+                trace!(
                     "Loading I with address {:x} from V{}, where sprite digit {:x} is stored",
-                    addr, reg, sprite_digit
+                    addr,
+                    reg,
+                    sprite_digit
                 );
                 self.i = addr as usize;
             }
@@ -328,106 +1188,164 @@ impl CPU {
                 let hundreds = reg_val / 100;
                 let tens = reg_val / 10 % 10;
                 let ones = reg_val % 10;
-                self.memory[self.i] = hundreds;
-                self.memory[self.i + 1] = tens;
-                self.memory[self.i + 2] = ones;
+                self.cover_data(self.i, 3, true);
+                for (offset, digit) in [hundreds, tens, ones].iter().enumerate() {
+                    self.write_memory(self.i + offset, *digit);
+                }
             }
             LdMemIRegs { last_reg } => {
-                info!(
+                let increment = self.memory_i_increment(last_reg);
+                trace!(
                     "Copying regs 0 through {} into memory address {:x} and incrementing I by {}",
                     last_reg,
                     self.i,
-                    last_reg + 1
+                    increment
                 );
+                self.cover_data(self.i, last_reg + 1, true);
                 for i in 0..=last_reg {
-                    self.memory[self.i + i] = self.v[i];
+                    self.write_memory(self.i + i, self.v[i]);
                 }
-                self.i += last_reg + 1;
+                self.i += increment;
             }
             LdRegByte { reg, val } => {
-                info!("Loading reg V{} with value {:x}", reg, val);
+                trace!("Loading reg V{} with value {:x}", reg, val);
                 self.v[reg] = val;
             }
             LdRegDt { reg } => {
-                info!("Loading reg V{} with value {} from DT", reg, self.v[reg]);
-                self.v[reg] = *self.delay_timer.lock().unwrap();
-            }
-            LdRegKey { reg } => {
-                while let Ok(event) = self.key_event_receiver.recv() {
-                    if let Some(true) = event.press(|button| {
-                        if let Some(keycode) = decode_key(button) {
-                            self.key_state[keycode] = true;
-                            self.v[reg] = keycode as u8;
-                            return true;
-                        }
-                        false
-                    }) {
+                trace!("Loading reg V{} with value {} from DT", reg, self.v[reg]);
+                self.v[reg] = self.delay_timer;
+            }
+            LdRegKey { reg } if self.poll_keys => {
+                // Runs again until a key is pressed.
+                match self.key_state.iter().position(|pressed| *pressed) {
+                    Some(keycode) => self.v[reg] = keycode as u8,
+                    None => new_pc = self.pc,
+                }
+            }
+            LdRegKey { reg } => loop {
+                let event = match self.controls.recv() {
+                    Ok(Control::Input(event)) => event,
+                    // Anything else is handled between frames, after which
+                    // this runs again.
+                    Ok(control) => {
+                        self.pending_controls.push_back(control);
+                        new_pc = self.pc;
+                        break;
+                    }
+                    // Nothing can press a key anymore, so the program stops
+                    // here.
+                    Err(_) => {
+                        new_pc = self.pc;
+                        self.quitting = true;
+                        break;
+                    }
+                };
+                // Resetting or restoring the machine here would be undone as
+                // this instruction finishes, so those keys wait until after it.
+                if let Some(Button::Keyboard(key)) = event.press_args() {
+                    if [RESET_KEY, RELOAD_ROM_KEY, LOAD_STATE_KEY].contains(&key) {
+                        self.pending_controls.push_back(Control::Input(event));
+                        new_pc = self.pc;
                         break;
                     }
-                    event.release(|button| {
-                        if let Some(keycode) = decode_key(button) {
-                            self.key_state[keycode] = false;
-                        }
-                    });
                 }
-            }
+                if let Some(true) = event.press(|button| {
+                    if self.handle_control_key(button, true) {
+                        return false;
+                    }
+                    if let Some(keycode) = self.keycode(button) {
+                        self.key_state[keycode] = true;
+                        self.v[reg] = keycode as u8;
+                        return true;
+                    }
+                    false
+                }) {
+                    break;
+                }
+                event.release(|button| {
+                    if self.handle_control_key(button, false) {
+                        return;
+                    }
+                    if let Some(keycode) = self.keycode(button) {
+                        self.key_state[keycode] = false;
+                    }
+                });
+            },
             LdRegsMemI { last_reg } => {
-                info!(
+                let increment = self.memory_i_increment(last_reg);
+                trace!(
                     "Loading regs 0 through {} with data in memory starting at address {:x} and incrementing I by {}",
-                    last_reg, self.i, last_reg + 1
+                    last_reg, self.i, increment
                 );
+                self.cover_data(self.i, last_reg + 1, false);
                 for i in 0..=last_reg {
-                    self.v[i] = self.memory[self.i + i]
+                    self.v[i] = self.memory[self.address(self.i + i)]
                 }
-                self.i += last_reg + 1;
+                self.i += increment;
             }
             LdRegReg { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "Setting the value of V{} to {}(V{})",
-                    reg_x, self.v[reg_y], reg_y
+                    reg_x,
+                    self.v[reg_y],
+                    reg_y
                 );
                 self.v[reg_x] = self.v[reg_y];
             }
             Jump { addr } => {
-                info!("Jumping to address {:x} instead of {:x}", addr, new_pc);
+                trace!("Jumping to address {:x} instead of {:x}", addr, new_pc);
                 new_pc = addr;
             }
             OrRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "ORing {}(V{}) with {}(V{}) and storing in V{}",
-                    self.v[reg_x], reg_x, self.v[reg_y], reg_y, reg_x
+                    self.v[reg_x],
+                    reg_x,
+                    self.v[reg_y],
+                    reg_y,
+                    reg_x
                 );
                 self.v[reg_x] |= self.v[reg_y];
+                self.reset_vf_after_logic();
             }
             RandRegByte { reg, val } => {
                 let rand_val = self.rng.gen_byte();
-                info!(
+                trace!(
                     "Generating a random byte, {:x}, AND-ing with {:x}, and storing in V{}",
-                    rand_val, val, reg
+                    rand_val,
+                    val,
+                    reg
                 );
                 self.v[reg] = val & rand_val;
             }
             Ret => {
+                if self.sp == 0 {
+                    return Err(Fault::StackUnderflow { pc: self.pc });
+                }
                 self.sp -= 1;
-                info!("returning to address {:x}", self.stack[self.sp]);
+                trace!("returning to address {:x}", self.stack[self.sp]);
                 new_pc = self.stack[self.sp] as usize + 2;
             }
             ShiftLeftReg { reg } => {
-                info!("Shifting-left V{} value: {:x}", reg, self.v[reg]);
-                self.v[0xF] = (self.v[reg] >> 7) & 1;
+                trace!("Shifting-left V{} value: {:x}", reg, self.v[reg]);
+                let shifted_out = (self.v[reg] >> 7) & 1;
                 self.v[reg] <<= 1;
+                self.v[0xF] = shifted_out;
             }
             ShiftRightReg { reg } => {
-                info!("Shifting-right V{} value: {:x}", reg, self.v[reg]);
-                self.v[0xF] = self.v[reg] & 1;
+                trace!("Shifting-right V{} value: {:x}", reg, self.v[reg]);
+                let shifted_out = self.v[reg] & 1;
                 self.v[reg] >>= 1;
+                self.v[0xF] = shifted_out;
             }
             SkipEqRegBytes { reg, val } => {
                 let reg_val = self.v[reg];
                 if reg_val == val {
-                    info!(
+                    trace!(
                         "Skiping next instr because {}(V{}) == {}",
-                        reg_val, reg, val
+                        reg_val,
+                        reg,
+                        val
                     );
                     new_pc += 2;
                 }
@@ -435,108 +1353,128 @@ impl CPU {
             SkipNEqRegBytes { reg, val } => {
                 let reg_val = self.v[reg];
                 if reg_val != val {
-                    info!(
+                    trace!(
                         "Skiping next instr because {}(V{}) != {}",
-                        reg_val, reg, val
+                        reg_val,
+                        reg,
+                        val
                     );
                     new_pc += 2;
                 }
             }
             SkipNEqRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "Skipping next instruction if {}(V{}) != {}(V{})",
-                    self.v[reg_x], reg_x, self.v[reg_y], reg_y
+                    self.v[reg_x],
+                    reg_x,
+                    self.v[reg_y],
+                    reg_y
                 );
                 if self.v[reg_x] != self.v[reg_y] {
                     new_pc += 2;
                 }
             }
             SkipRegKeyPressed { reg } => {
-                info!(
+                trace!(
                     "Skipping next instr if key {:x}, indicated by register V{} is pressed",
-                    self.v[reg], reg
+                    self.v[reg],
+                    reg
                 );
-                if self.key_state[self.v[reg] as usize] {
+                if self.key_state[self.v[reg] as usize & 0xF] {
                     new_pc += 2;
                 }
             }
             SkipRegKeyNPressed { reg } => {
-                info!(
+                trace!(
                     "Skipping next instr if key {:x}, indicated by register V{} is _not_ pressed",
-                    self.v[reg], reg
+                    self.v[reg],
+                    reg
                 );
-                if !self.key_state[self.v[reg] as usize] {
+                if !self.key_state[self.v[reg] as usize & 0xF] {
                     new_pc += 2;
                 }
             }
             SubRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "Subtracting {}(V{}) from {}(V{}) and storing in V{}",
-                    self.v[reg_y], reg_y, self.v[reg_x], reg_x, reg_x
+                    self.v[reg_y],
+                    reg_y,
+                    self.v[reg_x],
+                    reg_x,
+                    reg_x
                 );
-                self.v[0xF] = if self.v[reg_x] > self.v[reg_y] { 1 } else { 0 };
+                // VF is set when there is no borrow, including when the values
+                // are equal.
+                let no_borrow = self.v[reg_x] >= self.v[reg_y];
                 self.v[reg_x] = self.v[reg_x].wrapping_sub(self.v[reg_y]);
+                self.v[0xF] = if no_borrow { 1 } else { 0 };
+            }
+            Sys => trace!("SYS instruction found, ignoring"),
+            Unknown { opcode } => {
+                return Err(Fault::UnknownOpcode {
+                    pc: self.pc,
+                    opcode,
+                })
             }
-            Sys => info!("SYS instruction found, ignoring"),
             XorRegs { reg_x, reg_y } => {
-                info!(
+                trace!(
                     "XORing {}(V{}) with {}(V{}) and storing in V{}",
-                    self.v[reg_x], reg_x, self.v[reg_y], reg_y, reg_x
+                    self.v[reg_x],
+                    reg_x,
+                    self.v[reg_y],
+                    reg_y,
+                    reg_x
                 );
                 self.v[reg_x] ^= self.v[reg_y];
+                self.reset_vf_after_logic();
             }
         }
 
-        self.pc = new_pc;
-    }
-
-    fn draw_sprite(&mut self, sprite_location: usize, sprite_bytes: usize, x: u8, y: u8) {
-        let sprite: &[u8] = &self.memory[sprite_location..(sprite_location + sprite_bytes)];
-
-        let first_byte = (y * 8 + (x / 8)) as usize;
-        let mut second_byte = first_byte + 1;
-
-        // Wrap around rather than going to the next row
-        if second_byte % 8 == 0 {
-            second_byte -= 8;
+        self.pc = self.address(new_pc);
+        if let (Draw { .. }, Some(_)) = (op, &self.script) {
+            self.run_hook(Hook::Draw)?;
         }
+        Ok(())
+    }
 
-        let mut collision = false;
-
-        let mut frame_buffer = self.frame_buffer.write().unwrap();
-
-        for (i, byte) in sprite.iter().enumerate() {
-            let bit_offset = x % 8;
-            let sprite_location_first_byte = (first_byte + (i * 8)) % FRAME_BUFFER_BYTES;
-            let sprite_location_second_byte = (second_byte + (i * 8)) % FRAME_BUFFER_BYTES;
-
-            let old_first_byte: u8 = frame_buffer[sprite_location_first_byte];
-            let old_second_byte: u8 = frame_buffer[sprite_location_second_byte];
-
-            frame_buffer[sprite_location_first_byte] ^= byte >> bit_offset;
-
-            let new_first_byte = frame_buffer[sprite_location_first_byte];
-
-            if let Some(lower_bits) = byte.checked_shl(u32::from(8 - bit_offset)) {
-                frame_buffer[sprite_location_second_byte] ^= lower_bits
-            }
-
-            let new_second_byte = frame_buffer[sprite_location_second_byte];
-
-            // Check if any pixel went from 1 -> 0
-            for i in 0..8 {
-                if ((new_first_byte >> i) & 0x1) < ((old_first_byte >> i) & 0x1)
-                    || ((new_second_byte >> i) & 0x1) < ((old_second_byte >> i) & 0x1)
-                {
-                    collision = true;
-                }
-            }
+    // Returns how much Fx55/Fx65 advance I by, which depends on the quirks.
+    fn memory_i_increment(&self, last_reg: usize) -> usize {
+        if self.quirks.memory_leave_i_unchanged {
+            0
+        } else if self.quirks.memory_increment_by_x {
+            last_reg
+        } else {
+            last_reg + 1
         }
+    }
 
-        if collision {
-            self.v[0xF] = 1;
-        } else {
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic {
             self.v[0xF] = 0;
         }
     }
+
+    fn draw_sprite(&mut self, sprite_location: usize, sprite_bytes: usize, x: u8, y: u8) {
+        let sprite: Vec<u8> = (sprite_location..sprite_location + sprite_bytes)
+            .map(|addr| self.memory[self.address(addr)])
+            .collect();
+        let collision =
+            self.display
+                .draw_sprite(usize::from(x), usize::from(y), &sprite, self.quirks.wrap);
+        self.v[0xF] = collision as u8;
+    }
+}
+
+// Lets the instruction tests, which are shared with the recompiler, execute
+// instructions directly.
+#[cfg(test)]
+fn execute_for_test(cpu: &mut CPU, op: OpCode) -> Result<(), Fault> {
+    cpu.execute(op)
+}
+
+// Returns zeroed memory for |platform|, with the built-in digit sprites loaded.
+fn initial_memory(platform: Platform) -> Vec<u8> {
+    let mut memory = vec![0; platform.memory_size()];
+    memory[..DIGITS.len()].clone_from_slice(&DIGITS);
+    memory
 }
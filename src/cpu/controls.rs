@@ -0,0 +1,126 @@
+use super::{initial_memory, CPU};
+use crate::control::Control;
+use crate::display::Display;
+use crate::platform::PROGRAM_START;
+use crate::rom::{self, RomError};
+use log::*;
+use std::path::PathBuf;
+use std::sync::mpsc::TryRecvError;
+
+impl CPU {
+    // Handles the controls received since this was last called, including
+    // any that arrived while waiting for a key. Once every sender is gone,
+    // nothing can stop the CPU anymore, so it quits.
+    pub(super) fn handle_controls(&mut self) {
+        loop {
+            let control = match self.pending_controls.pop_front() {
+                Some(control) => control,
+                None => match self.controls.try_recv() {
+                    Ok(control) => control,
+                    Err(TryRecvError::Empty) => return,
+                    Err(TryRecvError::Disconnected) => {
+                        self.quitting = true;
+                        return;
+                    }
+                },
+            };
+            self.handle_control(control);
+        }
+    }
+
+    pub(super) fn handle_control(&mut self, control: Control) {
+        match control {
+            // Replaying the movie wouldn't start over where the program did.
+            Control::Reset | Control::LoadRom(_) | Control::Reload if self.movie.is_some() => {
+                warn!("Unable to restart the program while recording a movie");
+            }
+            Control::Input(event) => self.handle_key_event(&event),
+            Control::Command(command) => println!("{}", self.run_command(command)),
+            Control::Pause(paused) => {
+                self.speed.paused = paused;
+                info!("Paused: {}", paused);
+            }
+            Control::Reset => {
+                self.reset();
+                info!("Reset");
+            }
+            Control::LoadRom(data) => match self.load_rom(&data) {
+                Ok(()) => info!("Loaded a {} byte ROM", data.len()),
+                Err(e) => warn!("Unable to load ROM, keeping the current one: {}", e),
+            },
+            Control::Reload => self.reload_rom(),
+            Control::Quit => self.quitting = true,
+        }
+    }
+
+    /// Handles the controls received so far, returning whether one of them
+    /// asked the CPU to quit. For waiting on something other than the
+    /// program, such as a debugger connecting.
+    pub fn quit_requested(&mut self) -> bool {
+        self.handle_controls();
+        self.quitting
+    }
+
+    /// Remembers that the program was read from |path|, so that it can be
+    /// reloaded from there.
+    pub fn set_rom_path(&mut self, path: PathBuf) {
+        self.rom_path = Some(path);
+    }
+
+    // Reads the program again from its file and starts it over, keeping the
+    // platform and settings it was started with.
+    fn reload_rom(&mut self) {
+        let path = match &self.rom_path {
+            Some(path) => path.clone(),
+            None => {
+                warn!("Unable to reload the ROM, it wasn't read from a file");
+                return;
+            }
+        };
+        let result = rom::read_file(&path).and_then(|data| self.load_rom(&data));
+        match result {
+            Ok(()) => info!("Reloaded {}", path.display()),
+            Err(e) => warn!(
+                "Unable to reload {}, keeping the current ROM: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Puts the machine back the way it was when the program was loaded:
+    /// the registers, stack and timers are cleared, memory holds only the
+    /// digits and the program, and the display is blank. The platform,
+    /// quirks, speed and anything attached, like a script or tracer, stay as
+    /// they are.
+    pub fn reset(&mut self) {
+        self.v = [0; 16];
+        self.i = 0;
+        self.pc = PROGRAM_START;
+        self.stack.iter_mut().for_each(|addr| *addr = 0);
+        self.sp = 0;
+        self.sound_timer = 0;
+        self.delay_timer = 0;
+        self.waiting_for_vblank = false;
+        self.display.copy_from(&Display::default());
+        self.memory = initial_memory(self.platform);
+        self.memory[PROGRAM_START..PROGRAM_START + self.rom.len()].copy_from_slice(&self.rom);
+        // Anything decoded or translated from the old memory is stale.
+        if !self.decoded.is_empty() {
+            self.set_instruction_cache(true);
+        }
+        if self.blocks.is_some() {
+            self.set_recompiler(true);
+        }
+    }
+
+    /// Replaces the program with |data| and resets the machine to run it.
+    /// If |data| isn't a valid program for the platform, the current one
+    /// carries on.
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomError> {
+        rom::validate(data, self.platform)?;
+        self.rom = data.to_vec();
+        self.reset();
+        Ok(())
+    }
+}
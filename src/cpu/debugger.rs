@@ -0,0 +1,95 @@
+use super::CPU;
+use crate::cheat::{Cheat, Command, MemorySearch, Target, DEBUGGER_HELP, MAX_LISTED_CANDIDATES};
+
+impl CPU {
+    /// Holds the targets of |cheats| at their values, replacing any cheats
+    /// already active.
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    // Sets every frozen address and register back to its value. Run at the
+    // start of every frame.
+    pub(super) fn apply_cheats(&mut self) {
+        for index in 0..self.cheats.len() {
+            let Cheat { target, value, .. } = self.cheats[index];
+            match target {
+                Target::Memory { address } => {
+                    // Writing an unchanged byte would needlessly throw away
+                    // translated code.
+                    if self.memory[self.address(address)] != value {
+                        self.write_memory(address, value);
+                    }
+                }
+                Target::Register { register } => self.v[register] = value,
+            }
+        }
+    }
+
+    /// Runs a debugger |command| and returns its output.
+    pub fn run_command(&mut self, command: Command) -> String {
+        match command {
+            Command::Help => DEBUGGER_HELP.to_string(),
+            Command::Registers => self.debug_view().trim_end().to_string(),
+            Command::Search => {
+                self.search = Some(MemorySearch::new(&self.memory));
+                format!("{} candidates", self.memory.len())
+            }
+            Command::Filter(filter) => match &mut self.search {
+                Some(search) => {
+                    search.filter(&self.memory, filter);
+                    format!("{} candidates", search.candidates().len())
+                }
+                None => String::from("no search in progress, start one with 'search'"),
+            },
+            Command::List => match &self.search {
+                Some(search) => {
+                    let candidates = search.candidates();
+                    let mut output: Vec<String> = candidates
+                        .iter()
+                        .take(MAX_LISTED_CANDIDATES)
+                        .map(|addr| format!("[{:03X}] = {:02X}", addr, self.memory[*addr]))
+                        .collect();
+                    if candidates.len() > MAX_LISTED_CANDIDATES {
+                        output.push(format!(
+                            "... and {} more",
+                            candidates.len() - MAX_LISTED_CANDIDATES
+                        ));
+                    }
+                    output.join("\n")
+                }
+                None => String::from("no search in progress, start one with 'search'"),
+            },
+            Command::Freeze(cheat) => {
+                self.cheats.retain(|active| active.target != cheat.target);
+                let output = format!("froze {}", cheat);
+                self.cheats.push(cheat);
+                output
+            }
+            Command::Unfreeze(target) => {
+                let count = self.cheats.len();
+                self.cheats.retain(|active| active.target != target);
+                if self.cheats.len() < count {
+                    format!("unfroze {}", target)
+                } else {
+                    format!("{} isn't frozen", target)
+                }
+            }
+            Command::Cheats => {
+                if self.cheats.is_empty() {
+                    String::from("nothing is frozen")
+                } else {
+                    self.cheats
+                        .iter()
+                        .map(|cheat| cheat.to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                }
+            }
+        }
+    }
+}
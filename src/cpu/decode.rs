@@ -1,34 +1,34 @@
 use crate::cpu::*;
 use piston_window::*;
 
-pub(in crate::cpu) fn decode_instruction(code: &[u8]) -> OpCode {
+pub(in crate::cpu) fn decode_instruction(code: &[u8; 2]) -> OpCode {
     match code {
         [0x00, 0xE0] => Clear,
         [0x00, 0xEE] => Ret,
-        [0x00...0x0F, _] => Sys,
-        [msb @ 0x10...0x1F, lsb] => Jump {
+        [0x00..=0x0F, _] => Sys,
+        [msb @ 0x10..=0x1F, lsb] => Jump {
             addr: extract_addr(*msb, *lsb),
         },
-        [msb @ 0x20...0x2F, lsb] => Call {
+        [msb @ 0x20..=0x2F, lsb] => Call {
             addr: extract_addr(*msb, *lsb),
         },
-        [msb @ 0x30...0x3F, lsb] => SkipEqRegBytes {
+        [msb @ 0x30..=0x3F, lsb] => SkipEqRegBytes {
             reg: extract_lower_nibble(*msb),
             val: *lsb,
         },
-        [msb @ 0x40...0x4F, lsb] => SkipNEqRegBytes {
+        [msb @ 0x40..=0x4F, lsb] => SkipNEqRegBytes {
             reg: extract_lower_nibble(*msb),
             val: *lsb,
         },
-        [msb @ 0x60...0x6F, lsb] => LdRegByte {
+        [msb @ 0x60..=0x6F, lsb] => LdRegByte {
             reg: extract_lower_nibble(*msb),
             val: *lsb,
         },
-        [msb @ 0x70...0x7F, lsb] => AddRegByte {
+        [msb @ 0x70..=0x7F, lsb] => AddRegByte {
             reg: extract_lower_nibble(*msb) & 0xF,
             val: *lsb,
         },
-        [msb @ 0x80...0x8F, lsb] => match lsb & 0xF {
+        [msb @ 0x80..=0x8F, lsb] => match lsb & 0xF {
             0x0 => LdRegReg {
                 reg_x: extract_lower_nibble(*msb),
                 reg_y: extract_upper_nibble(*lsb),
@@ -59,64 +59,61 @@ pub(in crate::cpu) fn decode_instruction(code: &[u8]) -> OpCode {
             0xE => ShiftLeftReg {
                 reg: extract_lower_nibble(*msb),
             },
-            _ => panic!(
-                "Unknown op code {:x}",
-                *lsb as usize | ((*msb as usize) << 8)
-            ),
+            _ => Unknown {
+                opcode: u16::from_be_bytes([*msb, *lsb]),
+            },
         },
-        [msb @ 0x90...0x9F, lsb] => SkipNEqRegs {
+        [msb @ 0x90..=0x9F, lsb] => SkipNEqRegs {
             reg_x: extract_lower_nibble(*msb),
             reg_y: extract_upper_nibble(*lsb),
         },
-        [msb @ 0xA0...0xAF, lsb] => LdIAddr {
+        [msb @ 0xA0..=0xAF, lsb] => LdIAddr {
             addr: extract_addr(*msb, *lsb),
         },
-        [msb @ 0xC0...0xCF, lsb] => RandRegByte {
+        [msb @ 0xC0..=0xCF, lsb] => RandRegByte {
             reg: extract_lower_nibble(*msb),
             val: *lsb,
         },
-        [msb @ 0xD0...0xDF, lsb] => Draw {
+        [msb @ 0xD0..=0xDF, lsb] => Draw {
             reg_x: extract_lower_nibble(*msb),
             reg_y: extract_upper_nibble(*lsb),
             sprite_bytes: lsb & 0xF,
         },
-        [msb @ 0xE0...0xEF, 0x9E] => SkipRegKeyPressed {
+        [msb @ 0xE0..=0xEF, 0x9E] => SkipRegKeyPressed {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xE0...0xEF, 0xA1] => SkipRegKeyNPressed {
+        [msb @ 0xE0..=0xEF, 0xA1] => SkipRegKeyNPressed {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x07] => LdRegDt {
+        [msb @ 0xF0..=0xFF, 0x07] => LdRegDt {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x0A] => LdRegKey {
+        [msb @ 0xF0..=0xFF, 0x0A] => LdRegKey {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x15] => LdDtReg {
+        [msb @ 0xF0..=0xFF, 0x15] => LdDtReg {
             reg: extract_lower_nibble(*msb),
         },
         // TODO: implement the sound timer
-        [0xF0...0xFF, 0x18] => Sys,
-        [msb @ 0xF0...0xFF, 0x1E] => AddIReg {
+        [0xF0..=0xFF, 0x18] => Sys,
+        [msb @ 0xF0..=0xFF, 0x1E] => AddIReg {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x29] => LdIDigitReg {
+        [msb @ 0xF0..=0xFF, 0x29] => LdIDigitReg {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x33] => LdMemIBcdReg {
+        [msb @ 0xF0..=0xFF, 0x33] => LdMemIBcdReg {
             reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x55] => LdMemIRegs {
+        [msb @ 0xF0..=0xFF, 0x55] => LdMemIRegs {
             last_reg: extract_lower_nibble(*msb),
         },
-        [msb @ 0xF0...0xFF, 0x65] => LdRegsMemI {
+        [msb @ 0xF0..=0xFF, 0x65] => LdRegsMemI {
             last_reg: extract_lower_nibble(*msb),
         },
-        [msb, lsb] => panic!(
-            "Unknown op code {:x}",
-            *lsb as usize | ((*msb as usize) << 8)
-        ),
-        _ => panic!("Invalid program counter"),
+        [msb, lsb] => Unknown {
+            opcode: u16::from_be_bytes([*msb, *lsb]),
+        },
     }
 }
 
@@ -0,0 +1,450 @@
+// Differential tests: run the CPU and the independent reference interpreter in
+// lockstep, comparing their full state after every instruction. When they
+// diverge, the program is shrunk to the shortest instruction sequence that
+// still diverges before being reported.
+
+use crate::cpu::reference::{self, Reference, HEIGHT, WIDTH};
+use crate::cpu::*;
+use piston_window::{ButtonArgs, ButtonState, Input};
+use std::panic;
+use std::sync::mpsc::{channel, Sender};
+
+// Host keys for keypad keys 0 - F, the inverse of decode_key.
+const HOST_KEYS: [Key; 16] = [
+    Key::X,
+    Key::D1,
+    Key::D2,
+    Key::D3,
+    Key::Q,
+    Key::W,
+    Key::E,
+    Key::A,
+    Key::S,
+    Key::D,
+    Key::Z,
+    Key::C,
+    Key::D4,
+    Key::R,
+    Key::F,
+    Key::V,
+];
+
+// How often the delay timer is decremented, in instructions.
+const TIMER_PERIOD: usize = 11;
+
+// Instructions the reference runs but the CPU doesn't decode yet, as masks and
+// the patterns they select. Programs that reach one stop with a known
+// divergence, and differential_unimplemented_opcodes checks that each still
+// diverges, so it comes off this list once the CPU implements it.
+const UNIMPLEMENTED: [(u16, u16); 3] = [
+    (0xF00F, 0x5000), // SE Vx, Vy
+    (0xF00F, 0x8007), // SUBN Vx, Vy
+    (0xF000, 0xB000), // JP V0, addr
+];
+
+fn unimplemented(opcode: u16) -> bool {
+    UNIMPLEMENTED
+        .iter()
+        .any(|(mask, pattern)| opcode & mask == *pattern)
+}
+
+// Returns whether the CPU's |fault| is the one the reference |expected|.
+fn same_fault(fault: &Fault, expected: &reference::Fault) -> bool {
+    match (fault, expected) {
+        (Fault::UnknownOpcode { opcode, .. }, reference::Fault::UnknownOpcode(expected)) => {
+            opcode == expected
+        }
+        (Fault::StackOverflow { .. }, reference::Fault::StackOverflow)
+        | (Fault::StackUnderflow { .. }, reference::Fault::StackUnderflow) => true,
+        _ => false,
+    }
+}
+
+// A small xorshift generator for test inputs, so runs are reproducible from
+// their seed alone.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new(seed: u64) -> Xorshift {
+        Xorshift(seed.wrapping_mul(0x2545_F491_4F6C_DD1D) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // Returns a number in [0, n).
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct State {
+    v: [u8; 16],
+    i: usize,
+    pc: usize,
+    return_stack: Vec<usize>,
+    delay_timer: u8,
+    keys: [bool; 16],
+    memory: Vec<u8>,
+    pixels: Vec<bool>,
+}
+
+impl State {
+    fn of_cpu(cpu: &CPU) -> State {
+        let pixels = (0..WIDTH * HEIGHT)
+            .map(|p| {
+                let (x, y) = (p % WIDTH, p / WIDTH);
+                cpu.display.pixel(x, y)
+            })
+            .collect();
+        State {
+            v: cpu.v,
+            i: cpu.i,
+            pc: cpu.pc,
+            // The CPU pushes the address of the call, the reference pushes the
+            // address to return to.
+            return_stack: cpu.stack[..cpu.sp]
+                .iter()
+                .map(|addr| *addr as usize + 2)
+                .collect(),
+            delay_timer: cpu.delay_timer,
+            keys: cpu.key_state,
+            memory: cpu.memory.clone(),
+            pixels,
+        }
+    }
+
+    fn of_reference(reference: &Reference) -> State {
+        State {
+            v: reference.v,
+            i: reference.i,
+            pc: reference.pc,
+            return_stack: reference.stack.clone(),
+            delay_timer: reference.delay_timer,
+            keys: reference.keys,
+            memory: reference.memory.clone(),
+            pixels: reference.pixels.clone(),
+        }
+    }
+
+    // Describes how |self| (the CPU) differs from |expected| (the reference),
+    // without dumping all of memory and the display.
+    fn difference(&self, expected: &State) -> String {
+        let mut differences = Vec::new();
+        if self.v != expected.v {
+            differences.push(format!("V: {:02X?}, expected {:02X?}", self.v, expected.v));
+        }
+        if self.i != expected.i {
+            differences.push(format!("I: {:X}, expected {:X}", self.i, expected.i));
+        }
+        if self.pc != expected.pc {
+            differences.push(format!("PC: {:X}, expected {:X}", self.pc, expected.pc));
+        }
+        if self.return_stack != expected.return_stack {
+            differences.push(format!(
+                "stack: {:X?}, expected {:X?}",
+                self.return_stack, expected.return_stack
+            ));
+        }
+        if self.delay_timer != expected.delay_timer {
+            differences.push(format!(
+                "DT: {}, expected {}",
+                self.delay_timer, expected.delay_timer
+            ));
+        }
+        if self.keys != expected.keys {
+            differences.push(format!(
+                "keys: {:?}, expected {:?}",
+                self.keys, expected.keys
+            ));
+        }
+        for (addr, (actual, expected)) in self.memory.iter().zip(&expected.memory).enumerate() {
+            if actual != expected {
+                differences.push(format!(
+                    "memory[{:X}]: {:02X}, expected {:02X}",
+                    addr, actual, expected
+                ));
+            }
+        }
+        for (p, (actual, expected)) in self.pixels.iter().zip(&expected.pixels).enumerate() {
+            if actual != expected {
+                differences.push(format!(
+                    "pixel ({}, {}): {}, expected {}",
+                    p % WIDTH,
+                    p / WIDTH,
+                    actual,
+                    expected
+                ));
+            }
+        }
+        differences.join("\n")
+    }
+}
+
+#[derive(Debug)]
+struct Divergence {
+    // Instructions executed up to and including the diverging one
+    executed: Vec<u16>,
+    difference: String,
+    // Whether the CPU faulted on one of the UNIMPLEMENTED instructions
+    known: bool,
+}
+
+// Runs |rom| on both interpreters for up to |steps| instructions, returning the
+// first divergence. Runs end early without a divergence when both interpreters
+// fault the same way on the same instruction.
+fn run_lockstep(rom: &[u8], steps: usize, seed: u64) -> Option<Divergence> {
+    let (key_sender, key_receiver) = channel();
+    let mut cpu = CPU::new(key_receiver);
+    cpu.rng = WrappedRng::Mock(StepRng::new(seed, 0x9E37_79B9));
+    cpu.load_game_data(rom).unwrap();
+    let mut reference = Reference::new(rom, seed, 0x9E37_79B9);
+    let mut input = Xorshift::new(seed);
+    let mut executed = Vec::new();
+
+    for step in 0..steps {
+        if step % TIMER_PERIOD == 0 {
+            cpu.delay_timer = cpu.delay_timer.saturating_sub(1);
+            reference.delay_timer = reference.delay_timer.saturating_sub(1);
+        }
+        if input.below(8) == 0 {
+            let key = input.below(16) as usize;
+            let pressed = !reference.keys[key];
+            reference.keys[key] = pressed;
+            cpu.key_state[key] = pressed;
+        }
+
+        let opcode = reference.fetch();
+        let key = if reference.waits_for_key() {
+            let key = input.below(16) as usize;
+            send_key_press(&key_sender, key);
+            Some(key as u8)
+        } else {
+            None
+        };
+        let expected_fault = reference.step(key).err();
+        executed.push(opcode);
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| cpu.step()));
+        match (&result, expected_fault) {
+            (Ok(Err(fault)), Some(expected)) if same_fault(fault, &expected) => return None,
+            (Ok(Err(fault)), Some(expected)) => {
+                return Some(Divergence {
+                    executed,
+                    difference: format!("CPU faulted: {}, expected {:?}", fault, expected),
+                    known: false,
+                })
+            }
+            (Ok(Err(fault)), None) => {
+                let known = match fault {
+                    Fault::UnknownOpcode { opcode, .. } => unimplemented(*opcode),
+                    _ => false,
+                };
+                return Some(Divergence {
+                    executed,
+                    difference: format!("CPU faulted: {}", fault),
+                    known,
+                });
+            }
+            (Ok(Ok(())), Some(fault)) => {
+                return Some(Divergence {
+                    executed,
+                    difference: format!("CPU did not fault, expected {:?}", fault),
+                    known: false,
+                })
+            }
+            _ => {}
+        }
+        if let Err(e) = result {
+            let message = e
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            return Some(Divergence {
+                executed,
+                difference: format!("CPU panicked: {}", message),
+                known: false,
+            });
+        }
+
+        let actual = State::of_cpu(&cpu);
+        let expected = State::of_reference(&reference);
+        if actual != expected {
+            return Some(Divergence {
+                executed,
+                difference: actual.difference(&expected),
+                known: false,
+            });
+        }
+    }
+    None
+}
+
+fn send_key_press(key_sender: &Sender<Control>, key: usize) {
+    let event = Event::Input(Input::Button(ButtonArgs {
+        state: ButtonState::Press,
+        button: Button::Keyboard(HOST_KEYS[key]),
+        scancode: None,
+    }));
+    key_sender.send(Control::Input(event)).unwrap();
+}
+
+fn to_rom(instructions: &[u16]) -> Vec<u8> {
+    instructions
+        .iter()
+        .flat_map(|instr| vec![(instr >> 8) as u8, *instr as u8])
+        .collect()
+}
+
+fn to_instructions(rom: &[u8]) -> Vec<u16> {
+    rom.chunks(2)
+        .map(|pair| (u16::from(pair[0]) << 8) | u16::from(*pair.get(1).unwrap_or(&0)))
+        .collect()
+}
+
+// Shrinks |instructions| to a shorter sequence for which |fails| still holds,
+// by removing ever smaller chunks of instructions (delta debugging).
+fn minimize<F: Fn(&[u16]) -> bool>(instructions: &[u16], fails: F) -> Vec<u16> {
+    let mut current = instructions.to_vec();
+    let mut chunk = current.len() / 2;
+    while chunk > 0 {
+        let mut removed = false;
+        let mut start = 0;
+        while start < current.len() {
+            let end = (start + chunk).min(current.len());
+            let candidate: Vec<u16> = current[..start]
+                .iter()
+                .chain(&current[end..])
+                .cloned()
+                .collect();
+            if !candidate.is_empty() && fails(&candidate) {
+                current = candidate;
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+        if !removed {
+            chunk /= 2;
+        }
+    }
+    current
+}
+
+fn disassemble(instructions: &[u16]) -> String {
+    instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instr)| {
+            let code = [(instr >> 8) as u8, *instr as u8];
+            let mnemonic = panic::catch_unwind(|| decode_instruction(&code).to_string())
+                .unwrap_or_else(|_| String::from("???"));
+            format!("{:03X}: {:04X}  {}", 0x200 + 2 * index, instr, mnemonic)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Runs |rom| in lockstep and panics with a minimized report if the
+// interpreters diverge other than on an UNIMPLEMENTED instruction.
+fn check(name: &str, rom: &[u8], steps: usize, seed: u64) {
+    let unexpected = |rom: &[u8]| run_lockstep(rom, steps, seed).filter(|d| !d.known);
+    if let Some(divergence) = unexpected(rom) {
+        let fails = |instructions: &[u16]| unexpected(&to_rom(instructions)).is_some();
+        let minimized = minimize(&to_instructions(rom), fails);
+        let minimized_divergence = unexpected(&to_rom(&minimized)).unwrap();
+        panic!(
+            "{} (seed {}) diverged after {} instructions:\n{}\n\nMinimized program:\n{}\n\nExecuted:\n{}\n\n{}",
+            name,
+            seed,
+            divergence.executed.len(),
+            divergence.difference,
+            disassemble(&minimized),
+            disassemble(&minimized_divergence.executed),
+            minimized_divergence.difference
+        );
+    }
+}
+
+fn random_instruction(rng: &mut Xorshift, program_len: u64) -> u16 {
+    let x = (rng.below(16) << 8) as u16;
+    let y = (rng.below(16) << 4) as u16;
+    let nn = rng.below(0x100) as u16;
+    let addr = (0x200 + 2 * rng.below(program_len)) as u16;
+    match rng.below(21) {
+        0 => 0x00E0,
+        1 => 0x00EE,
+        2 => 0x1000 | addr,
+        3 => 0x2000 | addr,
+        4 => 0x3000 | x | nn,
+        5 => 0x4000 | x | nn,
+        6 => 0x6000 | x | nn,
+        7 => 0x7000 | x | nn,
+        8..=11 => {
+            let op = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0xE][rng.below(8) as usize];
+            0x8000 | x | y | op
+        }
+        12 => 0x9000 | x | y,
+        13 => 0xA000 | rng.below(0x1000) as u16,
+        14 => 0xC000 | x | nn,
+        15 => 0xD000 | x | y | rng.below(16) as u16,
+        16 => 0xE000 | x | [0x9E, 0xA1][rng.below(2) as usize],
+        17 => rng.next() as u16,
+        _ => {
+            let op = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65][rng.below(9) as usize];
+            0xF000 | x | op
+        }
+    }
+}
+
+#[test]
+fn differential_random_programs() {
+    for seed in 0..500 {
+        let mut rng = Xorshift::new(seed);
+        let program: Vec<u16> = (0..64).map(|_| random_instruction(&mut rng, 64)).collect();
+        check("random program", &to_rom(&program), 256, seed);
+    }
+}
+
+#[test]
+fn differential_bundled_roms() {
+    let roms: [(&str, &[u8]); 4] = [
+        ("15PUZZLE", include_bytes!("../../15PUZZLE")),
+        ("BLINKY", include_bytes!("../../BLINKY")),
+        ("BLITZ", include_bytes!("../../BLITZ")),
+        ("BRIX", include_bytes!("../../BRIX")),
+    ];
+    for (name, rom) in roms.iter() {
+        for seed in 0..2 {
+            check(name, rom, 10_000, seed);
+        }
+    }
+}
+
+#[test]
+fn differential_unimplemented_opcodes() {
+    for (_, opcode) in UNIMPLEMENTED.iter() {
+        match run_lockstep(&to_rom(&[*opcode]), 1, 0) {
+            Some(divergence) => assert!(divergence.known, "{}", divergence.difference),
+            None => panic!(
+                "{:04X} no longer diverges, take it off UNIMPLEMENTED",
+                opcode
+            ),
+        }
+    }
+}
+
+#[test]
+fn minimize_keeps_only_failing_instructions() {
+    let instructions = [0x6001, 0x6102, 0x8014, 0x00E0, 0x8015, 0x1200];
+    let minimized = minimize(&instructions, |candidate| {
+        candidate.contains(&0x8014) && candidate.contains(&0x8015)
+    });
+    assert_eq!(vec![0x8014, 0x8015], minimized);
+}
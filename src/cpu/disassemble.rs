@@ -0,0 +1,92 @@
+use crate::cpu::*;
+use std::fmt;
+
+/// Returns the mnemonic for the instruction encoded by |code|.
+pub fn disassemble(code: &[u8; 2]) -> String {
+    decode_instruction(code).to_string()
+}
+
+// Formats instructions using the mnemonics from Cowgod's CHIP-8 technical
+// reference.
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddIReg { reg } => write!(f, "ADD I, V{:X}", reg),
+            AddRegByte { reg, val } => write!(f, "ADD V{:X}, 0x{:02X}", reg, val),
+            AddRegs { reg_x, reg_y } => write!(f, "ADD V{:X}, V{:X}", reg_x, reg_y),
+            AndRegs { reg_x, reg_y } => write!(f, "AND V{:X}, V{:X}", reg_x, reg_y),
+            Call { addr } => write!(f, "CALL 0x{:03X}", addr),
+            Clear => write!(f, "CLS"),
+            Draw {
+                reg_x,
+                reg_y,
+                sprite_bytes,
+            } => write!(f, "DRW V{:X}, V{:X}, {}", reg_x, reg_y, sprite_bytes),
+            LdDtReg { reg } => write!(f, "LD DT, V{:X}", reg),
+            LdIAddr { addr } => write!(f, "LD I, 0x{:03X}", addr),
+            LdIDigitReg { reg } => write!(f, "LD F, V{:X}", reg),
+            LdMemIBcdReg { reg } => write!(f, "LD B, V{:X}", reg),
+            LdMemIRegs { last_reg } => write!(f, "LD [I], V{:X}", last_reg),
+            LdRegByte { reg, val } => write!(f, "LD V{:X}, 0x{:02X}", reg, val),
+            LdRegDt { reg } => write!(f, "LD V{:X}, DT", reg),
+            LdRegKey { reg } => write!(f, "LD V{:X}, K", reg),
+            LdRegsMemI { last_reg } => write!(f, "LD V{:X}, [I]", last_reg),
+            LdRegReg { reg_x, reg_y } => write!(f, "LD V{:X}, V{:X}", reg_x, reg_y),
+            Jump { addr } => write!(f, "JP 0x{:03X}", addr),
+            OrRegs { reg_x, reg_y } => write!(f, "OR V{:X}, V{:X}", reg_x, reg_y),
+            RandRegByte { reg, val } => write!(f, "RND V{:X}, 0x{:02X}", reg, val),
+            Ret => write!(f, "RET"),
+            ShiftLeftReg { reg } => write!(f, "SHL V{:X}", reg),
+            ShiftRightReg { reg } => write!(f, "SHR V{:X}", reg),
+            SkipEqRegBytes { reg, val } => write!(f, "SE V{:X}, 0x{:02X}", reg, val),
+            SkipNEqRegBytes { reg, val } => write!(f, "SNE V{:X}, 0x{:02X}", reg, val),
+            SkipNEqRegs { reg_x, reg_y } => write!(f, "SNE V{:X}, V{:X}", reg_x, reg_y),
+            SkipRegKeyPressed { reg } => write!(f, "SKP V{:X}", reg),
+            SkipRegKeyNPressed { reg } => write!(f, "SKNP V{:X}", reg),
+            SubRegs { reg_x, reg_y } => write!(f, "SUB V{:X}, V{:X}", reg_x, reg_y),
+            Sys => write!(f, "SYS"),
+            Unknown { opcode } => write!(f, "DW 0x{:04X}", opcode),
+            XorRegs { reg_x, reg_y } => write!(f, "XOR V{:X}, V{:X}", reg_x, reg_y),
+        }
+    }
+}
+
+impl OpCode {
+    /// Returns the name of the instruction, without its operands.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AddIReg { .. } => "AddIReg",
+            AddRegByte { .. } => "AddRegByte",
+            AddRegs { .. } => "AddRegs",
+            AndRegs { .. } => "AndRegs",
+            Call { .. } => "Call",
+            Clear => "Clear",
+            Draw { .. } => "Draw",
+            LdDtReg { .. } => "LdDtReg",
+            LdIAddr { .. } => "LdIAddr",
+            LdIDigitReg { .. } => "LdIDigitReg",
+            LdMemIBcdReg { .. } => "LdMemIBcdReg",
+            LdMemIRegs { .. } => "LdMemIRegs",
+            LdRegByte { .. } => "LdRegByte",
+            LdRegDt { .. } => "LdRegDt",
+            LdRegKey { .. } => "LdRegKey",
+            LdRegsMemI { .. } => "LdRegsMemI",
+            LdRegReg { .. } => "LdRegReg",
+            Jump { .. } => "Jump",
+            OrRegs { .. } => "OrRegs",
+            RandRegByte { .. } => "RandRegByte",
+            Ret => "Ret",
+            ShiftLeftReg { .. } => "ShiftLeftReg",
+            ShiftRightReg { .. } => "ShiftRightReg",
+            SkipEqRegBytes { .. } => "SkipEqRegBytes",
+            SkipNEqRegBytes { .. } => "SkipNEqRegBytes",
+            SkipNEqRegs { .. } => "SkipNEqRegs",
+            SkipRegKeyPressed { .. } => "SkipRegKeyPressed",
+            SkipRegKeyNPressed { .. } => "SkipRegKeyNPressed",
+            SubRegs { .. } => "SubRegs",
+            Sys => "Sys",
+            Unknown { .. } => "Unknown",
+            XorRegs { .. } => "XorRegs",
+        }
+    }
+}
@@ -0,0 +1,868 @@
+// Instruction tests shared by the interpreter and the recompiler. Each case
+// takes the function that executes a single instruction, and `execute_tests!`
+// declares a test for every case in the module that invokes it.
+
+use super::tests::create_cpu;
+use super::*;
+use crate::display::Display;
+use crate::quirks::Quirks;
+use rand::rngs::mock::StepRng;
+
+// Executes one instruction on a CPU.
+type Execute = fn(&mut CPU, OpCode) -> Result<(), Fault>;
+
+macro_rules! execute_tests {
+    ($execute:path) => {
+        execute_tests!(
+            $execute;
+        execute_add_i_reg,
+        execute_add_reg_byte,
+        execute_add_regs_no_overflow,
+        execute_add_regs_overflow,
+        execute_and_regs,
+        execute_call,
+        execute_call_stack_overflow,
+        execute_call_deep_stack,
+        execute_draw_no_collision,
+        execute_draw_collision,
+        execute_draw_wraparound_horizontal,
+        execute_draw_wraparound_vertical,
+        execute_draw_offscreen_start,
+        execute_ld_dt_reg,
+        execute_ld_i_addr,
+        execute_ld_i_digit_reg,
+        execute_ld_mem_i_bcd_reg,
+        execute_ld_mem_i_regs,
+        execute_ld_mem_i_regs_wraps,
+        execute_ld_mem_i_regs_quirks,
+        execute_ld_reg_byte,
+        execute_ld_reg_dt,
+        execute_ld_regs_mem_i,
+        execute_ld_reg_reg,
+        execute_jump,
+        execute_logic_quirk,
+        execute_or_regs,
+        execute_rand_reg_byte,
+        execute_rand_reg_byte_seeded,
+        execute_rand_reg_byte_vip,
+        execute_ld_reg_key_polls_keys,
+        execute_ret,
+        execute_ret_stack_underflow,
+        execute_shift_left_reg_set_vf,
+        execute_shift_left_reg_unset_vf,
+        execute_shift_left_reg_vf_operand,
+        execute_shift_right_reg_set_vf,
+        execute_shift_right_reg_unset_vf,
+        execute_skip_eq_reg_bytes,
+        execute_skip_neq_reg_bytes,
+        execute_skip_neq_regs,
+        execute_skip_reg_key_pressed,
+        execute_skip_reg_key_pressed_masks_key,
+        execute_skip_reg_key_npressed,
+        execute_sub_regs_underflow,
+        execute_sub_regs_equal,
+        execute_sub_regs_vf_operand,
+        execute_sub_regs_no_underflow,
+        execute_xor_regs,
+        execute_draw_wraparound_corner,
+        execute_draw_wraparound_last_column,
+        execute_draw_clips_right_edge,
+        execute_draw_clips_bottom_edge,
+        execute_draw_clips_corner,
+        execute_draw_clipping_wraps_start,
+        execute_draw_clipping_aligned_to_last_byte,
+        execute_draw_clipped_pixels_dont_collide,
+        execute_draw_clipping_collides_on_screen
+        );
+    };
+    ($execute:path; $($case:ident),*) => {
+        $(
+            #[test]
+            fn $case() {
+                $crate::cpu::execute_tests::$case($execute);
+            }
+        )*
+    };
+}
+
+pub(super) fn execute_add_i_reg(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 1;
+    cpu.i = 5;
+    execute(&mut cpu, AddIReg { reg: 0 }).unwrap();
+    assert_eq!(6, cpu.i);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_add_reg_byte(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0;
+    execute(&mut cpu, AddRegByte { reg: 0, val: 16 }).unwrap();
+    assert_eq!(16, cpu.v[0]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_add_regs_no_overflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 1;
+    cpu.v[5] = 5;
+    execute(&mut cpu, AddRegs { reg_x: 0, reg_y: 5 }).unwrap();
+    assert_eq!(6, cpu.v[0]);
+    assert_eq!(0, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_add_regs_overflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[3] = 1;
+    cpu.v[7] = 0xFF;
+    execute(&mut cpu, AddRegs { reg_x: 3, reg_y: 7 }).unwrap();
+    assert_eq!(0, cpu.v[3]);
+    assert_eq!(1, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_and_regs(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0b111;
+    cpu.v[1] = 0b101;
+    execute(&mut cpu, AndRegs { reg_x: 0, reg_y: 1 }).unwrap();
+    assert_eq!(0b101, cpu.v[0]);
+    assert_eq!(0b101, cpu.v[1]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_call(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.sp = 1;
+    cpu.pc = 0x112;
+    execute(&mut cpu, Call { addr: 0x114 }).unwrap();
+    assert_eq!(2, cpu.sp);
+    assert_eq!(0x112, cpu.stack[1]);
+    assert_eq!(0x114, cpu.pc);
+}
+
+pub(super) fn execute_call_stack_overflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.set_stack_depth(2);
+    cpu.pc = 0x200;
+    execute(&mut cpu, Call { addr: 0x220 }).unwrap();
+    execute(&mut cpu, Call { addr: 0x240 }).unwrap();
+    cpu.pc = 0x300;
+    assert_eq!(
+        Err(Fault::StackOverflow {
+            pc: 0x300,
+            call_chain: vec![0x200, 0x220],
+        }),
+        execute(&mut cpu, Call { addr: 0x400 })
+    );
+    assert_eq!(2, cpu.sp);
+    assert_eq!(0x300, cpu.pc);
+}
+
+pub(super) fn execute_call_deep_stack(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.set_stack_depth(32);
+    for _ in 0..32 {
+        execute(&mut cpu, Call { addr: 0x200 }).unwrap();
+    }
+    assert_eq!(32, cpu.sp);
+    assert!(execute(&mut cpu, Call { addr: 0x200 }).is_err());
+}
+
+pub(super) fn execute_draw_no_collision(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0;
+    cpu.v[0] = 4;
+    cpu.v[1] = 0;
+    cpu.v[0xF] = 1;
+    cpu.memory[0] = 0b01110111;
+    {
+        let fb = &mut cpu.display;
+        set_byte(fb, 0, 0b1000);
+        set_byte(fb, 1, 0b10000000);
+    }
+
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 1,
+        },
+    )
+    .unwrap();
+
+    {
+        let fb = &cpu.display;
+        assert_eq!(0xF, fb.as_bytes()[0]);
+        assert_eq!(0xF0, fb.as_bytes()[1]);
+        assert_eq!(0, cpu.v[0xF]);
+        assert_eq!(0x202, cpu.pc);
+    }
+}
+
+pub(super) fn execute_draw_collision(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0;
+    cpu.v[0] = 4;
+    cpu.v[1] = 1;
+    cpu.v[0xF] = 0;
+    cpu.memory[0] = 0b01110111;
+
+    {
+        let fb = &mut cpu.display;
+        set_byte(fb, 8, 0xF);
+        set_byte(fb, 9, 0xF0);
+    }
+
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 1,
+        },
+    )
+    .unwrap();
+
+    {
+        let fb = &cpu.display;
+        assert_eq!(0x8, fb.as_bytes()[8]);
+        assert_eq!(0x80, fb.as_bytes()[9]);
+        assert_eq!(1, cpu.v[0xF]);
+        assert_eq!(0x202, cpu.pc);
+    }
+}
+
+pub(super) fn execute_draw_wraparound_horizontal(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0;
+    cpu.v[0] = 60;
+    cpu.v[1] = 0;
+    cpu.v[0xF] = 0;
+    cpu.memory[0] = 0xFF;
+
+    {
+        let fb = &mut cpu.display;
+        set_byte(fb, 0, 0x80);
+        set_byte(fb, 7, 0x1);
+    }
+
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 1,
+        },
+    )
+    .unwrap();
+
+    {
+        let fb = &cpu.display;
+        assert_eq!(0b1110, fb.as_bytes()[7]);
+        assert_eq!(0b01110000, fb.as_bytes()[0]);
+        assert_eq!(1, cpu.v[0xF]);
+        assert_eq!(0x202, cpu.pc);
+    }
+}
+
+pub(super) fn execute_draw_wraparound_vertical(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0;
+    cpu.v[0] = 0;
+    cpu.v[1] = 31;
+    cpu.memory[0] = 0xFF;
+    cpu.memory[1] = 0xFF;
+
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 2,
+        },
+    )
+    .unwrap();
+
+    {
+        let fb = &cpu.display;
+        assert_eq!(0xFF, fb.as_bytes()[0]);
+        assert_eq!(0xFF, fb.as_bytes()[248]); // beginning of last row, 31*8
+        assert_eq!(0, cpu.v[0xF]);
+        assert_eq!(0x202, cpu.pc);
+    }
+}
+
+pub(super) fn execute_draw_offscreen_start(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0;
+    cpu.v[0] = 64 + 8;
+    cpu.v[1] = 32 + 31;
+    cpu.memory[0] = 0xFF;
+
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 1,
+        },
+    )
+    .unwrap();
+
+    {
+        let fb = &cpu.display;
+        assert_eq!(0xFF, fb.as_bytes()[249]); // second byte of the last row, 31*8+1
+        assert_eq!(0, cpu.v[0xF]);
+        assert_eq!(0x202, cpu.pc);
+    }
+}
+
+pub(super) fn execute_ld_dt_reg(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[3] = 25;
+    execute(&mut cpu, LdDtReg { reg: 3 }).unwrap();
+    {
+        assert_eq!(25, cpu.delay_timer);
+    }
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_i_addr(execute: Execute) {
+    let mut cpu = create_cpu();
+    execute(&mut cpu, LdIAddr { addr: 0x123 }).unwrap();
+    assert_eq!(0x123, cpu.i);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_i_digit_reg(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[1] = 2;
+    execute(&mut cpu, LdIDigitReg { reg: 1 }).unwrap();
+    assert_eq!(10, cpu.i);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_mem_i_bcd_reg(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[5] = 254;
+    cpu.i = 200;
+    execute(&mut cpu, LdMemIBcdReg { reg: 5 }).unwrap();
+    assert_eq!(2, cpu.memory[200]);
+    assert_eq!(5, cpu.memory[201]);
+    assert_eq!(4, cpu.memory[202]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_mem_i_regs(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0x100;
+    cpu.v[0] = 5;
+    cpu.v[1] = 6;
+    execute(&mut cpu, LdMemIRegs { last_reg: 1 }).unwrap();
+    assert_eq!(5, cpu.memory[0x100]);
+    assert_eq!(6, cpu.memory[0x101]);
+    assert_eq!(0x102, cpu.i);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_mem_i_regs_wraps(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0xFFF;
+    cpu.v[0] = 5;
+    cpu.v[1] = 6;
+    execute(&mut cpu, LdMemIRegs { last_reg: 1 }).unwrap();
+    assert_eq!(5, cpu.memory[0xFFF]);
+    assert_eq!(6, cpu.memory[0]);
+}
+
+pub(super) fn execute_ld_mem_i_regs_quirks(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.set_quirks(Quirks {
+        memory_increment_by_x: true,
+        ..Quirks::default()
+    });
+    cpu.i = 0x100;
+    execute(&mut cpu, LdMemIRegs { last_reg: 1 }).unwrap();
+    assert_eq!(0x101, cpu.i);
+
+    let mut cpu = create_cpu();
+    cpu.set_quirks(Quirks {
+        memory_leave_i_unchanged: true,
+        ..Quirks::default()
+    });
+    cpu.i = 0x100;
+    execute(&mut cpu, LdMemIRegs { last_reg: 1 }).unwrap();
+    assert_eq!(0x100, cpu.i);
+}
+
+pub(super) fn execute_ld_reg_byte(execute: Execute) {
+    let mut cpu = create_cpu();
+    execute(&mut cpu, LdRegByte { reg: 1, val: 0xFF }).unwrap();
+    assert_eq!(0xFF, cpu.v[1]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_reg_dt(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.delay_timer = 25;
+    cpu.v[3] = 16;
+    execute(&mut cpu, LdRegDt { reg: 3 }).unwrap();
+    assert_eq!(25, cpu.v[3]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_regs_mem_i(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.i = 0x100;
+    cpu.memory[0x100] = 1;
+    cpu.memory[0x101] = 2;
+    cpu.memory[0x102] = 3;
+    execute(&mut cpu, LdRegsMemI { last_reg: 2 }).unwrap();
+    assert_eq!(1, cpu.v[0]);
+    assert_eq!(2, cpu.v[1]);
+    assert_eq!(3, cpu.v[2]);
+    assert_eq!(0x103, cpu.i);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ld_reg_reg(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 5;
+    cpu.v[1] = 6;
+    execute(&mut cpu, LdRegReg { reg_x: 0, reg_y: 1 }).unwrap();
+    assert_eq!(6, cpu.v[0]);
+    assert_eq!(6, cpu.v[1]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_jump(execute: Execute) {
+    let mut cpu = create_cpu();
+    execute(&mut cpu, Jump { addr: 0x2e8 }).unwrap();
+    assert_eq!(0x2e8, cpu.pc);
+}
+
+pub(super) fn execute_logic_quirk(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0xF] = 1;
+    execute(&mut cpu, OrRegs { reg_x: 1, reg_y: 2 }).unwrap();
+    assert_eq!(1, cpu.v[0xF]);
+
+    cpu.set_quirks(Quirks {
+        logic: true,
+        ..Quirks::default()
+    });
+    execute(&mut cpu, OrRegs { reg_x: 1, reg_y: 2 }).unwrap();
+    assert_eq!(0, cpu.v[0xF]);
+}
+
+pub(super) fn execute_or_regs(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[5] = 0b1100;
+    cpu.v[7] = 1;
+    execute(&mut cpu, OrRegs { reg_x: 5, reg_y: 7 }).unwrap();
+    assert_eq!(cpu.v[5], 0b1101);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_rand_reg_byte(execute: Execute) {
+    let mut cpu = create_cpu();
+    // The CPU's RNG will always yield 0xF
+    cpu.rng = WrappedRng::Mock(StepRng::new(0xF, 0));
+    execute(&mut cpu, RandRegByte { reg: 3, val: 0xAB }).unwrap();
+    assert_eq!(0xB, cpu.v[3]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_rand_reg_byte_seeded(execute: Execute) {
+    let random_bytes = |seed| {
+        let mut cpu = create_cpu();
+        cpu.set_seed(seed);
+        (0..8)
+            .map(|_| {
+                execute(&mut cpu, RandRegByte { reg: 0, val: 0xFF }).unwrap();
+                cpu.v[0]
+            })
+            .collect::<Vec<u8>>()
+    };
+    assert_eq!(random_bytes(1), random_bytes(1));
+    assert_ne!(random_bytes(1), random_bytes(2));
+}
+
+pub(super) fn execute_rand_reg_byte_vip(execute: Execute) {
+    let mut cpu = create_cpu();
+    // A stand-in interpreter, with varied bytes in its second page
+    let interpreter: Vec<u8> = (0..0x200).map(|addr| (addr * 3) as u8).collect();
+    let code_page = |low: usize| interpreter[0x100 + low];
+    cpu.set_vip_interpreter(&interpreter).unwrap();
+    cpu.set_random(RandomRoutine::Vip, 0x1234);
+    execute(&mut cpu, RandRegByte { reg: 0, val: 0xFF }).unwrap();
+    let first = 0x12u8.wrapping_add(code_page(0x35));
+    assert_eq!(first, cpu.v[0]);
+    execute(&mut cpu, RandRegByte { reg: 0, val: 0x0F }).unwrap();
+    assert_eq!(first.wrapping_add(code_page(0x36)) & 0x0F, cpu.v[0]);
+
+    // Every frame advances the seed as well.
+    cpu.set_random(RandomRoutine::Vip, 0x1234);
+    cpu.tick_timers();
+    execute(&mut cpu, RandRegByte { reg: 0, val: 0xFF }).unwrap();
+    assert_eq!(0x12u8.wrapping_add(code_page(0x36)), cpu.v[0]);
+}
+
+pub(super) fn execute_ld_reg_key_polls_keys(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.poll_keys = true;
+    execute(&mut cpu, LdRegKey { reg: 2 }).unwrap();
+    assert_eq!(0x200, cpu.pc);
+    cpu.key_state[0xB] = true;
+    execute(&mut cpu, LdRegKey { reg: 2 }).unwrap();
+    assert_eq!(0xB, cpu.v[2]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_ret(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.sp = 1;
+    cpu.stack[0] = 0x200;
+    execute(&mut cpu, Ret).unwrap();
+    assert_eq!(0x202, cpu.pc);
+    assert_eq!(0, cpu.sp);
+}
+
+pub(super) fn execute_ret_stack_underflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    assert_eq!(
+        Err(Fault::StackUnderflow { pc: 0x200 }),
+        execute(&mut cpu, Ret)
+    );
+    assert_eq!(0, cpu.sp);
+}
+
+pub(super) fn execute_shift_left_reg_set_vf(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0b10000010;
+    execute(&mut cpu, ShiftLeftReg { reg: 0 }).unwrap();
+    assert_eq!(0b100, cpu.v[0]);
+    assert_eq!(1, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_shift_left_reg_unset_vf(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0b110;
+    cpu.v[0xF] = 1;
+    execute(&mut cpu, ShiftLeftReg { reg: 0 }).unwrap();
+    assert_eq!(0b1100, cpu.v[0]);
+    assert_eq!(0, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_shift_left_reg_vf_operand(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0xF] = 0b01000000;
+    execute(&mut cpu, ShiftLeftReg { reg: 0xF }).unwrap();
+    assert_eq!(0, cpu.v[0xF]);
+}
+
+pub(super) fn execute_shift_right_reg_set_vf(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0b1111;
+    execute(&mut cpu, ShiftRightReg { reg: 0 }).unwrap();
+    assert_eq!(0b111, cpu.v[0]);
+    assert_eq!(1, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_shift_right_reg_unset_vf(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0] = 0b110;
+    cpu.v[0xF] = 1;
+    execute(&mut cpu, ShiftRightReg { reg: 0 }).unwrap();
+    assert_eq!(0b11, cpu.v[0]);
+    assert_eq!(0, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_skip_eq_reg_bytes(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 16;
+    execute(&mut cpu, SkipEqRegBytes { reg: 4, val: 16 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+
+    let mut cpu = create_cpu();
+    cpu.v[4] = 14;
+    execute(&mut cpu, SkipEqRegBytes { reg: 4, val: 16 }).unwrap();
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_skip_neq_reg_bytes(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 16;
+    execute(&mut cpu, SkipNEqRegBytes { reg: 4, val: 16 }).unwrap();
+    assert_eq!(0x202, cpu.pc);
+
+    let mut cpu = create_cpu();
+    cpu.v[4] = 14;
+    execute(&mut cpu, SkipNEqRegBytes { reg: 4, val: 16 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+}
+
+pub(super) fn execute_skip_neq_regs(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 17;
+    cpu.v[5] = 17;
+    execute(&mut cpu, SkipNEqRegs { reg_x: 4, reg_y: 5 }).unwrap();
+    assert_eq!(0x202, cpu.pc);
+
+    let mut cpu = create_cpu();
+    cpu.v[4] = 14;
+    cpu.v[5] = 18;
+    execute(&mut cpu, SkipNEqRegs { reg_x: 4, reg_y: 5 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+}
+
+pub(super) fn execute_skip_reg_key_pressed(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 7;
+    cpu.key_state[7] = true;
+    execute(&mut cpu, SkipRegKeyPressed { reg: 4 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+
+    let mut cpu = create_cpu();
+    cpu.v[5] = 6;
+    cpu.key_state[6] = false;
+    execute(&mut cpu, SkipRegKeyPressed { reg: 5 }).unwrap();
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_skip_reg_key_pressed_masks_key(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 0x17;
+    cpu.key_state[7] = true;
+    execute(&mut cpu, SkipRegKeyPressed { reg: 4 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+}
+
+pub(super) fn execute_skip_reg_key_npressed(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 7;
+    cpu.key_state[7] = false;
+    execute(&mut cpu, SkipRegKeyNPressed { reg: 4 }).unwrap();
+    assert_eq!(0x204, cpu.pc);
+
+    let mut cpu = create_cpu();
+    cpu.v[5] = 6;
+    cpu.key_state[6] = true;
+    execute(&mut cpu, SkipRegKeyNPressed { reg: 5 }).unwrap();
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_sub_regs_underflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 3;
+    cpu.v[6] = 2;
+    execute(&mut cpu, SubRegs { reg_x: 4, reg_y: 6 }).unwrap();
+    assert_eq!(1, cpu.v[4]);
+    assert_eq!(1, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_sub_regs_equal(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[4] = 3;
+    cpu.v[6] = 3;
+    execute(&mut cpu, SubRegs { reg_x: 4, reg_y: 6 }).unwrap();
+    assert_eq!(0, cpu.v[4]);
+    assert_eq!(1, cpu.v[0xF]);
+}
+
+pub(super) fn execute_sub_regs_vf_operand(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0xF] = 5;
+    cpu.v[1] = 2;
+    execute(
+        &mut cpu,
+        SubRegs {
+            reg_x: 0xF,
+            reg_y: 1,
+        },
+    )
+    .unwrap();
+    assert_eq!(1, cpu.v[0xF]);
+}
+
+pub(super) fn execute_sub_regs_no_underflow(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[0xA] = 1;
+    cpu.v[0xD] = 2;
+    cpu.v[0xF] = 1;
+    execute(
+        &mut cpu,
+        SubRegs {
+            reg_x: 0xA,
+            reg_y: 0xD,
+        },
+    )
+    .unwrap();
+    assert_eq!(0xFF, cpu.v[0xA]);
+    assert_eq!(0, cpu.v[0xF]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+pub(super) fn execute_xor_regs(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.v[1] = 0b1111;
+    cpu.v[2] = 0b1010;
+    execute(&mut cpu, XorRegs { reg_x: 1, reg_y: 2 }).unwrap();
+    assert_eq!(cpu.v[1], 0b0101);
+    assert_eq!(0x202, cpu.pc);
+}
+
+// Sets the eight pixels packed in byte |index| of the low resolution display
+// to |byte|.
+fn set_byte(display: &mut Display, index: usize, byte: u8) {
+    for bit in 0..8 {
+        display.set_pixel(index % 8 * 8 + bit, index / 8, byte & (0x80 >> bit) != 0);
+    }
+}
+
+// Draws |sprite| at |x|, |y| and returns the CPU.
+fn draw_at(execute: Execute, wrap: bool, x: u8, y: u8, sprite: &[u8]) -> CPU {
+    let mut cpu = create_cpu();
+    cpu.set_quirks(Quirks {
+        wrap,
+        ..Quirks::default()
+    });
+    cpu.i = 0x300;
+    cpu.memory[0x300..0x300 + sprite.len()].copy_from_slice(sprite);
+    cpu.v[0] = x;
+    cpu.v[1] = y;
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: sprite.len() as u8,
+        },
+    )
+    .unwrap();
+    cpu
+}
+
+// Returns the rows of the frame buffer that have any pixels lit, with their
+// numbers.
+fn lit_rows(cpu: &CPU) -> Vec<(usize, [u8; 8])> {
+    let fb = &cpu.display;
+    (0..32)
+        .filter_map(|row| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&fb.as_bytes()[row * 8..row * 8 + 8]);
+            if bytes.iter().any(|byte| *byte != 0) {
+                Some((row, bytes))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+pub(super) fn execute_draw_wraparound_corner(execute: Execute) {
+    let cpu = draw_at(execute, true, 62, 31, &[0xF0, 0xF0]);
+    assert_eq!(
+        vec![
+            (0, [0xC0, 0, 0, 0, 0, 0, 0, 0x03]),
+            (31, [0xC0, 0, 0, 0, 0, 0, 0, 0x03]),
+        ],
+        lit_rows(&cpu)
+    );
+}
+
+pub(super) fn execute_draw_wraparound_last_column(execute: Execute) {
+    let cpu = draw_at(execute, true, 63, 0, &[0xFF]);
+    assert_eq!(vec![(0, [0xFE, 0, 0, 0, 0, 0, 0, 0x01])], lit_rows(&cpu));
+}
+
+pub(super) fn execute_draw_clips_right_edge(execute: Execute) {
+    let cpu = draw_at(execute, false, 60, 0, &[0xFF]);
+    assert_eq!(vec![(0, [0, 0, 0, 0, 0, 0, 0, 0x0F])], lit_rows(&cpu));
+    let cpu = draw_at(execute, false, 63, 0, &[0xFF]);
+    assert_eq!(vec![(0, [0, 0, 0, 0, 0, 0, 0, 0x01])], lit_rows(&cpu));
+}
+
+pub(super) fn execute_draw_clips_bottom_edge(execute: Execute) {
+    let cpu = draw_at(execute, false, 0, 30, &[0x80, 0x40, 0x20, 0x10]);
+    assert_eq!(
+        vec![
+            (30, [0x80, 0, 0, 0, 0, 0, 0, 0]),
+            (31, [0x40, 0, 0, 0, 0, 0, 0, 0])
+        ],
+        lit_rows(&cpu)
+    );
+}
+
+pub(super) fn execute_draw_clips_corner(execute: Execute) {
+    let cpu = draw_at(execute, false, 62, 31, &[0xF0, 0xF0]);
+    assert_eq!(vec![(31, [0, 0, 0, 0, 0, 0, 0, 0x03])], lit_rows(&cpu));
+}
+
+pub(super) fn execute_draw_clipping_wraps_start(execute: Execute) {
+    // 124 and 63 wrap around to 60 and 31 before clipping.
+    let cpu = draw_at(execute, false, 124, 63, &[0xFF, 0xFF]);
+    assert_eq!(vec![(31, [0, 0, 0, 0, 0, 0, 0, 0x0F])], lit_rows(&cpu));
+}
+
+pub(super) fn execute_draw_clipping_aligned_to_last_byte(execute: Execute) {
+    // Nothing to clip horizontally when the sprite fits in the last byte.
+    let cpu = draw_at(execute, false, 56, 31, &[0xFF]);
+    assert_eq!(vec![(31, [0, 0, 0, 0, 0, 0, 0, 0xFF])], lit_rows(&cpu));
+}
+
+pub(super) fn execute_draw_clipped_pixels_dont_collide(execute: Execute) {
+    let mut cpu = create_cpu();
+    cpu.set_quirks(Quirks {
+        wrap: false,
+        ..Quirks::default()
+    });
+    {
+        let fb = &mut cpu.display;
+        // Where the sprite would wrap to on the left and the top.
+        set_byte(fb, 0, 0xFF);
+        set_byte(fb, 248, 0xFF);
+    }
+    cpu.i = 0x300;
+    cpu.memory[0x300] = 0xFF;
+    cpu.memory[0x301] = 0xFF;
+    cpu.v[0] = 60;
+    cpu.v[1] = 31;
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 2,
+        },
+    )
+    .unwrap();
+    assert_eq!(0, cpu.v[0xF]);
+    let fb = &cpu.display;
+    assert_eq!(0xFF, fb.as_bytes()[0]);
+    assert_eq!(0xFF, fb.as_bytes()[248]);
+    assert_eq!(0x0F, fb.as_bytes()[255]);
+}
+
+pub(super) fn execute_draw_clipping_collides_on_screen(execute: Execute) {
+    let mut cpu = draw_at(execute, false, 60, 31, &[0xFF]);
+    execute(
+        &mut cpu,
+        Draw {
+            reg_x: 0,
+            reg_y: 1,
+            sprite_bytes: 1,
+        },
+    )
+    .unwrap();
+    assert_eq!(1, cpu.v[0xF]);
+    assert!(lit_rows(&cpu).is_empty());
+}
@@ -0,0 +1,291 @@
+#[cfg(test)]
+mod tests;
+
+use super::{Fault, CPU, FRAME_DURATION};
+use crate::gdb::*;
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Instant;
+
+/// Why a debugging session ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SessionEnd {
+    // The debugger let the program carry on without it
+    Detached,
+    Killed,
+    Disconnected,
+    // The CPU was told to quit, as when the window is closed, or the script
+    // exited
+    Exited,
+}
+
+// Why the program stopped running.
+enum Stop {
+    Trap,
+    Interrupted,
+    Fault(Fault),
+    Exited,
+    Disconnected,
+}
+
+#[derive(Default)]
+struct Session {
+    breakpoints: HashSet<usize>,
+    no_ack: bool,
+    // Instructions run so far in the current frame
+    executed: usize,
+    // The reply to the last resume, repeated for "?"
+    last_stop: Option<String>,
+}
+
+impl CPU {
+    /// Lets a debugger control the program through the requests in
+    /// |incoming|, writing replies to |out|, until the debugger detaches or
+    /// disconnects, or the CPU is told to quit. Instructions run one at a time
+    /// through the interpreter, with the timers ticking every |tick_rate|
+    /// instructions as usual.
+    pub fn serve_gdb(
+        &mut self,
+        incoming: &Receiver<Incoming>,
+        out: &mut dyn Write,
+    ) -> io::Result<SessionEnd> {
+        let mut session = Session::default();
+        // Waiting for a key event would block the debugger.
+        self.poll_keys = true;
+        loop {
+            let packet = match incoming.recv_timeout(FRAME_DURATION) {
+                Ok(Incoming::Packet(packet)) => packet,
+                Ok(Incoming::Corrupt) => {
+                    if !session.no_ack {
+                        out.write_all(b"-")?;
+                        out.flush()?;
+                    }
+                    continue;
+                }
+                // The program isn't running.
+                Ok(Incoming::Interrupt) => continue,
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.quit_requested() {
+                        return Ok(SessionEnd::Exited);
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(SessionEnd::Disconnected),
+            };
+            if !session.no_ack {
+                out.write_all(b"+")?;
+            }
+            let (reply, end) = self.handle_request(&mut session, &packet, incoming);
+            if let Some(reply) = reply {
+                out.write_all(encode_packet(&reply).as_bytes())?;
+            }
+            out.flush()?;
+            if let Some(end) = end {
+                return Ok(end);
+            }
+        }
+    }
+
+    // Returns the reply to |packet|, if any, and whether the session is over.
+    fn handle_request(
+        &mut self,
+        session: &mut Session,
+        packet: &str,
+        incoming: &Receiver<Incoming>,
+    ) -> (Option<String>, Option<SessionEnd>) {
+        let request = match Request::parse(packet) {
+            Some(request) => request,
+            None => return (Some(String::from("E01")), None),
+        };
+        let reply = match request {
+            Request::StopReason => session
+                .last_stop
+                .clone()
+                .unwrap_or_else(|| String::from("S05")),
+            Request::ReadRegisters => (0..REGISTER_SIZES.len())
+                .map(|register| encode_hex(&self.gdb_register(register)))
+                .collect(),
+            Request::WriteRegisters(bytes) => {
+                if bytes.len() < REGISTER_SIZES.iter().sum() {
+                    return (Some(String::from("E01")), None);
+                }
+                let mut offset = 0;
+                for (register, size) in REGISTER_SIZES.iter().enumerate() {
+                    self.set_gdb_register(register, &bytes[offset..offset + size]);
+                    offset += size;
+                }
+                String::from("OK")
+            }
+            Request::ReadRegister(register) if register < REGISTER_SIZES.len() => {
+                encode_hex(&self.gdb_register(register))
+            }
+            Request::WriteRegister(register, bytes)
+                if register < REGISTER_SIZES.len() && bytes.len() == REGISTER_SIZES[register] =>
+            {
+                self.set_gdb_register(register, &bytes);
+                String::from("OK")
+            }
+            Request::ReadRegister(_) | Request::WriteRegister(..) => String::from("E01"),
+            Request::ReadMemory { addr, len } => match self.memory.get(addr..) {
+                Some(memory) if addr < self.memory.len() => {
+                    encode_hex(&memory[..len.min(memory.len())])
+                }
+                _ => String::from("E01"),
+            },
+            Request::WriteMemory { addr, data } => {
+                if addr
+                    .checked_add(data.len())
+                    .is_none_or(|end| end > self.memory.len())
+                {
+                    return (Some(String::from("E01")), None);
+                }
+                for (offset, byte) in data.iter().enumerate() {
+                    self.write_memory(addr + offset, *byte);
+                }
+                String::from("OK")
+            }
+            Request::Continue(addr) | Request::Step(addr) => {
+                if let Some(addr) = addr {
+                    self.pc = self.address(addr);
+                }
+                let stop = match request {
+                    Request::Step(_) => match self.gdb_step(session) {
+                        Ok(()) => Stop::Trap,
+                        Err(fault) => Stop::Fault(fault),
+                    },
+                    _ => self.gdb_continue(session, incoming),
+                };
+                let end = match stop {
+                    Stop::Exited => Some(SessionEnd::Exited),
+                    Stop::Disconnected => return (None, Some(SessionEnd::Disconnected)),
+                    _ => None,
+                };
+                let reply = stop_reply(&stop);
+                session.last_stop = Some(reply.clone());
+                return (Some(reply), end);
+            }
+            Request::InsertBreakpoint(addr) => {
+                session.breakpoints.insert(addr);
+                String::from("OK")
+            }
+            Request::RemoveBreakpoint(addr) => {
+                session.breakpoints.remove(&addr);
+                String::from("OK")
+            }
+            Request::Supported => {
+                String::from("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+")
+            }
+            Request::ReadFeatures { offset, len } => {
+                let xml = target_xml();
+                match xml.get(offset..) {
+                    Some(rest) if rest.len() > len => format!("m{}", &rest[..len]),
+                    Some(rest) => format!("l{}", rest),
+                    None => String::from("l"),
+                }
+            }
+            Request::StartNoAckMode => {
+                session.no_ack = true;
+                String::from("OK")
+            }
+            Request::Attached => String::from("1"),
+            Request::CurrentThread => String::from("QC1"),
+            Request::FirstThreadInfo => String::from("m1"),
+            Request::NextThreadInfo => String::from("l"),
+            Request::SetThread => String::from("OK"),
+            Request::Kill => return (None, Some(SessionEnd::Killed)),
+            Request::Detach => return (Some(String::from("OK")), Some(SessionEnd::Detached)),
+            Request::Unsupported => String::new(),
+        };
+        (Some(reply), None)
+    }
+
+    // Runs a single instruction, starting and ending frames around it.
+    fn gdb_step(&mut self, session: &mut Session) -> Result<(), Fault> {
+        if session.executed == 0 {
+            self.begin_frame()?;
+        }
+        self.step()?;
+        session.executed += 1;
+        if session.executed >= self.tick_rate as usize || self.waiting_for_vblank {
+            self.end_frame();
+            session.executed = 0;
+        }
+        Ok(())
+    }
+
+    // Runs at the usual speed until a breakpoint, a fault or an interrupt.
+    fn gdb_continue(&mut self, session: &mut Session, incoming: &Receiver<Incoming>) -> Stop {
+        let mut next_frame = Instant::now();
+        loop {
+            if let Err(fault) = self.gdb_step(session) {
+                return Stop::Fault(fault);
+            }
+            if session.breakpoints.contains(&self.pc) {
+                return Stop::Trap;
+            }
+            if session.executed != 0 {
+                continue;
+            }
+            // Between frames, check for input and keep to 60Hz.
+            match incoming.try_recv() {
+                Ok(Incoming::Interrupt) => return Stop::Interrupted,
+                Err(TryRecvError::Disconnected) => return Stop::Disconnected,
+                _ => {}
+            }
+            self.handle_controls();
+            if self.quitting || self.script_exited() {
+                return Stop::Exited;
+            }
+            self.update_memory_view();
+            next_frame += FRAME_DURATION;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+    }
+
+    // Returns the value of |register|, as GDB numbers them, in little-endian
+    // order.
+    fn gdb_register(&self, register: usize) -> Vec<u8> {
+        let value = match register {
+            I_REGISTER => self.i,
+            PC_REGISTER => self.pc,
+            SP_REGISTER => self.sp,
+            DT_REGISTER => usize::from(self.delay_timer),
+            _ => usize::from(self.v[register]),
+        };
+        value.to_le_bytes()[..REGISTER_SIZES[register]].to_vec()
+    }
+
+    fn set_gdb_register(&mut self, register: usize, bytes: &[u8]) {
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| value << 8 | usize::from(*byte));
+        match register {
+            I_REGISTER => self.i = value,
+            PC_REGISTER => self.pc = self.address(value),
+            SP_REGISTER => self.sp = value.min(self.stack.len()),
+            DT_REGISTER => self.delay_timer = value as u8,
+            _ => self.v[register] = value as u8,
+        }
+    }
+}
+
+fn stop_reply(stop: &Stop) -> String {
+    let signal = match stop {
+        Stop::Trap => 5,
+        Stop::Interrupted => 2,
+        Stop::Fault(Fault::UnknownOpcode { .. }) => 4,
+        Stop::Fault(Fault::StackOverflow { .. }) | Stop::Fault(Fault::StackUnderflow { .. }) => 11,
+        Stop::Fault(Fault::Script { .. }) => 6,
+        Stop::Exited | Stop::Disconnected => return String::from("W00"),
+    };
+    format!("S{:02x}", signal)
+}
@@ -0,0 +1,145 @@
+use super::SessionEnd;
+use crate::cpu::tests::{create_cpu, DRAW_LOOP_PROGRAM};
+use crate::cpu::CPU;
+use crate::gdb::{encode_packet, Incoming};
+use crate::quirks::Quirks;
+use std::sync::mpsc::channel;
+
+// Sends |packets| to a GDB session, and returns the replies without their
+// framing, along with how the session ended.
+fn serve_gdb(cpu: &mut CPU, packets: &[&str]) -> (Vec<String>, SessionEnd) {
+    let (sender, receiver) = channel();
+    for packet in packets {
+        sender.send(Incoming::Packet(packet.to_string())).unwrap();
+    }
+    drop(sender);
+    let mut out = Vec::new();
+    let end = cpu.serve_gdb(&receiver, &mut out).unwrap();
+    let mut out = String::from_utf8(out).unwrap();
+    let mut replies = Vec::new();
+    // Each reply is framed as $data#xx, and acknowledged packets are preceded
+    // by a +.
+    while let Some(start) = out.find('$') {
+        let end = out.find('#').unwrap() + 3;
+        let data = out[start + 1..end - 3].to_string();
+        assert_eq!(encode_packet(&data), out[start..end]);
+        replies.push(data);
+        out = out[end..].to_string();
+    }
+    (replies, end)
+}
+
+#[test]
+fn registers_and_memory() {
+    let mut cpu = create_cpu();
+    cpu.v[0xA] = 0x5A;
+    cpu.i = 0x1234;
+    cpu.delay_timer = 9;
+    cpu.load_game_data(&[0x60, 0x07]).unwrap();
+    let (replies, end) = serve_gdb(
+        &mut cpu,
+        &[
+            "g",
+            "p11",
+            "P3=42",
+            "P10=0003",
+            "m200,2",
+            "M300,2:abcd",
+            "mfff,4",
+            "m1000,1",
+            "qfThreadInfo",
+            "vCont?",
+            "D",
+        ],
+    );
+    assert_eq!(
+        vec![
+            "000000000000000000005a0000000000341200020009",
+            "0002",
+            "OK",
+            "OK",
+            "6007",
+            "OK",
+            "00",
+            "E01",
+            "m1",
+            "",
+            "OK",
+        ],
+        replies
+    );
+    assert_eq!(SessionEnd::Detached, end);
+    assert_eq!(0x42, cpu.v[3]);
+    assert_eq!(0x300, cpu.i);
+    assert_eq!([0xAB, 0xCD], cpu.memory[0x300..0x302]);
+}
+
+#[test]
+fn write_memory_out_of_range() {
+    let mut cpu = create_cpu();
+    let (replies, _) = serve_gdb(
+        &mut cpu,
+        &["Mffffffffffffffff,1:00", "Mfff,2:0102", "Mfff,1:01", "D"],
+    );
+    assert_eq!(vec!["E01", "E01", "OK", "OK"], replies);
+    assert_eq!(1, cpu.memory[0xFFF]);
+}
+
+#[test]
+fn step_and_continue() {
+    let mut cpu = create_cpu();
+    cpu.set_tick_rate(2);
+    cpu.load_game_data(&[
+        0x60, 0x01, // 0x200: LD V0, 0x01
+        0x70, 0x01, // 0x202: ADD V0, 0x01
+        0x12, 0x02, // 0x204: JP 0x202
+    ])
+    .unwrap();
+    let (replies, end) = serve_gdb(
+        &mut cpu,
+        &[
+            "s", "p11", "Z0,204,2", "c", "p0", "c", "p0", "z0,204,2", "?", "k",
+        ],
+    );
+    assert_eq!(
+        vec!["S05", "0202", "OK", "S05", "02", "S05", "03", "OK", "S05"],
+        replies
+    );
+    assert_eq!(SessionEnd::Killed, end);
+    // Four instructions make two frames.
+    assert_eq!(2, cpu.frames);
+}
+
+#[test]
+fn fault() {
+    let mut cpu = create_cpu();
+    cpu.load_game_data(&[0x00, 0xEE]).unwrap();
+    let (replies, end) = serve_gdb(&mut cpu, &["QStartNoAckMode", "c", "p11"]);
+    assert_eq!(vec!["OK", "S0b", "0002"], replies);
+    assert_eq!(SessionEnd::Disconnected, end);
+}
+
+#[test]
+fn quits_while_waiting() {
+    // Nothing can send controls to the CPU, so it quits instead of waiting on
+    // a debugger that never says anything.
+    let mut cpu = create_cpu();
+    let (_sender, receiver) = channel();
+    let end = cpu.serve_gdb(&receiver, &mut Vec::new()).unwrap();
+    assert_eq!(SessionEnd::Exited, end);
+}
+
+#[test]
+fn step_vblank_quirk_ends_frame() {
+    let mut cpu = create_cpu();
+    cpu.set_tick_rate(9);
+    cpu.set_quirks(Quirks {
+        vblank: true,
+        ..Quirks::default()
+    });
+    cpu.load_game_data(&DRAW_LOOP_PROGRAM).unwrap();
+    let (replies, _) = serve_gdb(&mut cpu, &["s", "s", "s", "s", "k"]);
+    assert_eq!(vec!["S05"; 4], replies);
+    // Each draw ends a frame.
+    assert_eq!(2, cpu.frames);
+}
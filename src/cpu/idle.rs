@@ -0,0 +1,69 @@
+// Many programs wait for the delay timer in a loop like:
+//
+//   loop: LD V0, DT
+//         SE V0, 0x00
+//         JP loop
+//
+// The timer only changes between frames, so once such a loop has started it
+// keeps spinning for the rest of the frame, with nothing changing but the
+// program counter. Rather than interpreting every iteration, the CPU can jump
+// straight to the state the loop would reach by the end of the frame.
+
+use super::decode::decode_instruction;
+use super::OpCode::*;
+use super::{OpCode, CPU};
+use crate::script::Hook;
+
+impl CPU {
+    /// Enables or disables fast-forwarding through loops waiting on the
+    /// delay timer. This doesn't change what the program sees, only how much
+    /// work it takes to emulate. It's enabled by default, but never applies
+    /// while tracing, profiling, recording coverage or calling a script on
+    /// every instruction.
+    pub fn set_idle_loop_skipping(&mut self, enabled: bool) {
+        self.skip_idle_loops = enabled;
+    }
+
+    // If the program is at the start of a loop that waits for the delay timer
+    // and won't exit this frame, runs the |remaining| instructions of the
+    // frame at once. Returns how many instructions were run, which is zero if
+    // the program isn't idle.
+    pub(super) fn skip_idle_loop(&mut self, remaining: usize) -> usize {
+        // Cheaply rule out anything but Fx07 before decoding.
+        if self.memory[self.pc] & 0xF0 != 0xF0
+            || self.memory[self.address(self.pc + 1)] != 0x07
+            || !self.skip_idle_loops
+            || self.tracer.is_some()
+            || self.profiler.is_some()
+            || self.coverage.is_some()
+            || self.has_hook(Hook::Instruction(self.pc))
+        {
+            return 0;
+        }
+        let reg = match self.instruction_at(self.pc) {
+            LdRegDt { reg } => reg,
+            _ => return 0,
+        };
+        let delay_timer = self.delay_timer;
+        let keeps_waiting = match self.instruction_at(self.pc + 2) {
+            SkipEqRegBytes { reg: r, val } if r == reg => delay_timer != val,
+            SkipNEqRegBytes { reg: r, val } if r == reg => delay_timer == val,
+            _ => false,
+        };
+        if !keeps_waiting || self.instruction_at(self.pc + 4) != (Jump { addr: self.pc }) {
+            return 0;
+        }
+        // Each iteration reloads the register and runs three instructions.
+        self.v[reg] = delay_timer;
+        self.pc = self.address(self.pc + 2 * (remaining % 3));
+        self.cycles += remaining as u64;
+        remaining
+    }
+
+    fn instruction_at(&self, addr: usize) -> OpCode {
+        decode_instruction(&[
+            self.memory[self.address(addr)],
+            self.memory[self.address(addr + 1)],
+        ])
+    }
+}
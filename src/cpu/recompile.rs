@@ -0,0 +1,339 @@
+// An alternative to interpreting one instruction at a time. Straight-line
+// runs of instructions are translated once into chains of closures, with
+// common sequences fused, and the translated blocks are reused until the
+// program writes over them.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::decode::decode_instruction;
+use super::OpCode::*;
+use super::{Fault, OpCode, CPU};
+use crate::script::Hook;
+
+// Bounds how long the program can run without checking for input.
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+type Compiled = Box<dyn Fn(&mut CPU) + Send + Sync>;
+
+/// A run of instructions translated for execution as a unit.
+pub struct Block {
+    start: usize,
+    // Translated instructions before the terminator. They never change PC or
+    // fault.
+    body: Vec<Compiled>,
+    // Number of instructions translated into |body|
+    body_instructions: usize,
+    // The instruction that ends the block, run by the interpreter
+    terminator: Option<OpCode>,
+}
+
+impl Block {
+    // Translates |instrs|, which start at |start|. Only the last instruction
+    // may end a block.
+    fn translate(start: usize, instrs: &[OpCode]) -> Block {
+        let (body, terminator) = match instrs.split_last() {
+            Some((last, body)) if ends_block(*last) => (body, Some(*last)),
+            _ => (instrs, None),
+        };
+        let mut compiled = Vec::new();
+        let mut rest = body;
+        while !rest.is_empty() {
+            let (op, used) = compile(rest);
+            compiled.push(op);
+            rest = &rest[used..];
+        }
+        Block {
+            start,
+            body: compiled,
+            body_instructions: body.len(),
+            terminator,
+        }
+    }
+
+    /// Number of instructions in the block, including the terminator.
+    pub fn len(&self) -> usize {
+        self.body_instructions + self.terminator.map_or(0, |_| 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        (self.start..self.start + 2 * self.len()).contains(&addr)
+    }
+
+    // Runs the block on |cpu|, whose PC must be at the start of the block.
+    fn run(&self, cpu: &mut CPU) -> Result<(), Fault> {
+        for op in &self.body {
+            op(cpu);
+        }
+        cpu.pc = cpu.address(self.start + 2 * self.body_instructions);
+        cpu.cycles += self.body_instructions as u64;
+        if let Some(terminator) = self.terminator {
+            cpu.execute(terminator)?;
+            cpu.cycles += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Translated blocks by start address.
+pub struct Blocks {
+    blocks: HashMap<usize, Arc<Block>>,
+    // Whether each address may belong to a translated block
+    translated: Vec<bool>,
+}
+
+impl Blocks {
+    pub fn new(memory_size: usize) -> Blocks {
+        Blocks {
+            blocks: HashMap::new(),
+            translated: vec![false; memory_size],
+        }
+    }
+
+    fn get(&self, start: usize) -> Option<Arc<Block>> {
+        self.blocks.get(&start).cloned()
+    }
+
+    fn insert(&mut self, block: Block) -> Arc<Block> {
+        for addr in block.start..block.start + 2 * block.len() {
+            self.translated[addr] = true;
+        }
+        let block = Arc::new(block);
+        self.blocks.insert(block.start, block.clone());
+        block
+    }
+
+    /// Drops every block that includes |addr|, after it has been written to.
+    pub fn invalidate(&mut self, addr: usize) {
+        if self.translated[addr] {
+            self.blocks.retain(|_, block| !block.contains(addr));
+            self.translated[addr] = false;
+        }
+    }
+}
+
+impl CPU {
+    /// Enables or disables translating blocks of instructions instead of
+    /// interpreting them one at a time. Translation is disabled by default,
+    /// and bypassed while tracing.
+    pub fn set_recompiler(&mut self, enabled: bool) {
+        self.blocks = if enabled {
+            Some(Blocks::new(self.memory.len()))
+        } else {
+            None
+        };
+    }
+
+    /// Executes instructions up to the end of the current block, translating
+    /// it first if needed, and returns how many were executed. Falls back to a
+    /// single step when translation is disabled.
+    pub fn run_block(&mut self) -> Result<usize, Fault> {
+        self.run_block_within(MAX_BLOCK_INSTRUCTIONS)
+    }
+
+    // Like |run_block|, but interprets a single instruction instead of
+    // running a block longer than |limit|. This keeps frames the same length
+    // whether or not the recompiler is used.
+    pub(super) fn run_block_within(&mut self, limit: usize) -> Result<usize, Fault> {
+        if self.tracer.is_some()
+            || self.profiler.is_some()
+            || self.coverage.is_some()
+            || self.has_hook(Hook::Instruction(self.pc))
+        {
+            self.step()?;
+            return Ok(1);
+        }
+        let block = match &self.blocks {
+            None => None,
+            Some(blocks) => match blocks.get(self.pc) {
+                Some(block) => Some(block),
+                None => {
+                    let block = self.translate_block(self.pc);
+                    self.blocks.as_mut().map(|blocks| blocks.insert(block))
+                }
+            },
+        };
+        match block {
+            Some(ref block) if !block.is_empty() && block.len() <= limit => {
+                block.run(self)?;
+                Ok(block.len())
+            }
+            // The instruction wraps around the end of memory, the block is too
+            // long or translation is disabled.
+            _ => {
+                self.step()?;
+                Ok(1)
+            }
+        }
+    }
+
+    fn translate_block(&self, start: usize) -> Block {
+        let mut instrs = Vec::new();
+        let mut addr = start;
+        while addr + 2 <= self.memory.len() && instrs.len() < MAX_BLOCK_INSTRUCTIONS {
+            let instr = decode_instruction(&[self.memory[addr], self.memory[addr + 1]]);
+            instrs.push(instr);
+            addr += 2;
+            if ends_block(instr) {
+                break;
+            }
+        }
+        Block::translate(start, &instrs)
+    }
+}
+
+// Whether |op| may change the flow of control, write memory, wait or fault.
+// Draws end blocks as well, so the display is updated promptly.
+fn ends_block(op: OpCode) -> bool {
+    match op {
+        Call { .. }
+        | Draw { .. }
+        | Jump { .. }
+        | LdMemIBcdReg { .. }
+        | LdMemIRegs { .. }
+        | LdRegKey { .. }
+        | Ret
+        | SkipEqRegBytes { .. }
+        | SkipNEqRegBytes { .. }
+        | SkipNEqRegs { .. }
+        | SkipRegKeyPressed { .. }
+        | SkipRegKeyNPressed { .. }
+        | Unknown { .. } => true,
+        AddIReg { .. }
+        | AddRegByte { .. }
+        | AddRegs { .. }
+        | AndRegs { .. }
+        | Clear
+        | LdDtReg { .. }
+        | LdIAddr { .. }
+        | LdIDigitReg { .. }
+        | LdRegByte { .. }
+        | LdRegDt { .. }
+        | LdRegsMemI { .. }
+        | LdRegReg { .. }
+        | OrRegs { .. }
+        | RandRegByte { .. }
+        | ShiftLeftReg { .. }
+        | ShiftRightReg { .. }
+        | SubRegs { .. }
+        | Sys
+        | XorRegs { .. } => false,
+    }
+}
+
+// Compiles the instructions at the start of |instrs|, none of which end a
+// block, and returns how many were used.
+fn compile(instrs: &[OpCode]) -> (Compiled, usize) {
+    match instrs[0] {
+        // Consecutive loads are fused into one.
+        LdRegByte { .. } => {
+            let loads: Vec<(usize, u8)> = instrs
+                .iter()
+                .map_while(|instr| match *instr {
+                    LdRegByte { reg, val } => Some((reg, val)),
+                    _ => None,
+                })
+                .collect();
+            let used = loads.len();
+            let op = move |cpu: &mut CPU| {
+                for &(reg, val) in &loads {
+                    cpu.v[reg] = val;
+                }
+            };
+            (Box::new(op), used)
+        }
+        // So are consecutive additions to the same register.
+        AddRegByte { reg, .. } => {
+            let (sum, used) = instrs
+                .iter()
+                .map_while(|instr| match *instr {
+                    AddRegByte { reg: r, val } if r == reg => Some(val),
+                    _ => None,
+                })
+                .fold((0u8, 0), |(sum, used), val| {
+                    (sum.wrapping_add(val), used + 1)
+                });
+            let op = move |cpu: &mut CPU| cpu.v[reg] = cpu.v[reg].wrapping_add(sum);
+            (Box::new(op), used)
+        }
+        AddIReg { reg } => (Box::new(move |cpu| cpu.i += cpu.v[reg] as usize), 1),
+        AddRegs { reg_x, reg_y } => (
+            Box::new(move |cpu| {
+                let (sum, did_overflow) = cpu.v[reg_x].overflowing_add(cpu.v[reg_y]);
+                cpu.v[reg_x] = sum;
+                cpu.v[0xF] = did_overflow as u8;
+            }),
+            1,
+        ),
+        AndRegs { reg_x, reg_y } => (
+            Box::new(move |cpu| {
+                cpu.v[reg_x] &= cpu.v[reg_y];
+                cpu.reset_vf_after_logic();
+            }),
+            1,
+        ),
+        LdIAddr { addr } => (Box::new(move |cpu| cpu.i = addr), 1),
+        LdIDigitReg { reg } => (Box::new(move |cpu| cpu.i = 5 * cpu.v[reg] as usize), 1),
+        LdRegReg { reg_x, reg_y } => (Box::new(move |cpu| cpu.v[reg_x] = cpu.v[reg_y]), 1),
+        OrRegs { reg_x, reg_y } => (
+            Box::new(move |cpu| {
+                cpu.v[reg_x] |= cpu.v[reg_y];
+                cpu.reset_vf_after_logic();
+            }),
+            1,
+        ),
+        ShiftLeftReg { reg } => (
+            Box::new(move |cpu| {
+                let shifted_out = cpu.v[reg] >> 7;
+                cpu.v[reg] <<= 1;
+                cpu.v[0xF] = shifted_out;
+            }),
+            1,
+        ),
+        ShiftRightReg { reg } => (
+            Box::new(move |cpu| {
+                let shifted_out = cpu.v[reg] & 1;
+                cpu.v[reg] >>= 1;
+                cpu.v[0xF] = shifted_out;
+            }),
+            1,
+        ),
+        SubRegs { reg_x, reg_y } => (
+            Box::new(move |cpu| {
+                let no_borrow = cpu.v[reg_x] >= cpu.v[reg_y];
+                cpu.v[reg_x] = cpu.v[reg_x].wrapping_sub(cpu.v[reg_y]);
+                cpu.v[0xF] = no_borrow as u8;
+            }),
+            1,
+        ),
+        XorRegs { reg_x, reg_y } => (
+            Box::new(move |cpu| {
+                cpu.v[reg_x] ^= cpu.v[reg_y];
+                cpu.reset_vf_after_logic();
+            }),
+            1,
+        ),
+        // Everything else is left to the interpreter. The block sets PC once
+        // it's done.
+        op => (
+            Box::new(move |cpu| {
+                cpu.execute(op)
+                    .expect("instructions that don't end a block can't fault")
+            }),
+            1,
+        ),
+    }
+}
+
+// Lets the shared instruction tests run each instruction as a translated block.
+#[cfg(test)]
+fn execute_for_test(cpu: &mut CPU, op: OpCode) -> Result<(), Fault> {
+    Block::translate(cpu.pc, &[op]).run(cpu)
+}
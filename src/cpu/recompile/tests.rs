@@ -0,0 +1,197 @@
+use super::*;
+use crate::cpu::WrappedRng;
+use crate::database;
+use crate::movie::Movie;
+use crate::platform::Platform;
+use rand::rngs::mock::StepRng;
+use std::sync::mpsc::channel;
+
+execute_tests!(super::execute_for_test);
+
+#[test]
+fn translate_fuses_loads_and_additions() {
+    let block = Block::translate(
+        0x200,
+        &[
+            LdRegByte { reg: 0, val: 1 },
+            LdRegByte { reg: 1, val: 2 },
+            LdRegByte { reg: 2, val: 3 },
+            AddRegByte { reg: 2, val: 0xFF },
+            AddRegByte { reg: 2, val: 2 },
+            AddRegByte { reg: 3, val: 1 },
+            Jump { addr: 0x200 },
+        ],
+    );
+    assert_eq!(3, block.body.len());
+    assert_eq!(6, block.body_instructions);
+    assert_eq!(Some(Jump { addr: 0x200 }), block.terminator);
+    assert_eq!(7, block.len());
+
+    let mut cpu = create_cpu();
+    block.run(&mut cpu).unwrap();
+    assert_eq!([1, 2, 4, 1], cpu.v[..4]);
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(7, cpu.cycles);
+}
+
+#[test]
+fn run_block_ends_at_terminator() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.load_game_data(&[0x60, 0x01, 0x70, 0x01, 0x30, 0x02, 0x61, 0x01, 0x62, 0x01])
+        .unwrap();
+    assert_eq!(Ok(3), cpu.run_block());
+    assert_eq!(2, cpu.v[0]);
+    assert_eq!(0, cpu.v[1]);
+    assert_eq!(0x208, cpu.pc);
+}
+
+#[test]
+fn run_block_limits_block_length() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.load_game_data(&[0x60, 0x01].repeat(100)).unwrap();
+    assert_eq!(Ok(MAX_BLOCK_INSTRUCTIONS), cpu.run_block());
+    assert_eq!(0x200 + 2 * MAX_BLOCK_INSTRUCTIONS, cpu.pc);
+}
+
+#[test]
+fn run_block_within_limit() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.load_game_data(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x00])
+        .unwrap();
+    assert_eq!(Ok(1), cpu.run_block_within(2));
+    assert_eq!(Ok(2), cpu.run_block_within(2));
+    assert_eq!(0x200, cpu.pc);
+}
+
+#[test]
+fn run_frame_stops_at_frame_boundary() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.set_tick_rate(4);
+    cpu.load_game_data(&[0x60, 0x01, 0x70, 0x01, 0x70, 0x01, 0x12, 0x00])
+        .unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(4, cpu.cycles);
+    cpu.run_frame().unwrap();
+    assert_eq!(8, cpu.cycles);
+}
+
+#[test]
+fn run_block_wraps_around_memory() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.memory[0xFFC] = 0x61;
+    cpu.memory[0xFFD] = 0x12;
+    cpu.memory[0xFFE] = 0x62;
+    cpu.memory[0xFFF] = 0x34;
+    cpu.pc = 0xFFC;
+    assert_eq!(Ok(2), cpu.run_block());
+    assert_eq!([0x12, 0x34], cpu.v[1..3]);
+    assert_eq!(0, cpu.pc);
+
+    // An instruction split across the end of memory is interpreted.
+    cpu.memory[0xFFF] = 0x64;
+    cpu.memory[0] = 0x56;
+    cpu.pc = 0xFFF;
+    assert_eq!(Ok(1), cpu.run_block());
+    assert_eq!(0x56, cpu.v[4]);
+}
+
+#[test]
+fn run_block_fault() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    cpu.load_game_data(&[0x60, 0x01, 0x00, 0xEE]).unwrap();
+    assert_eq!(Err(Fault::StackUnderflow { pc: 0x202 }), cpu.run_block());
+    assert_eq!(1, cpu.v[0]);
+    assert_eq!(0x202, cpu.pc);
+}
+
+#[test]
+fn run_block_invalidated_by_write() {
+    let mut cpu = create_cpu();
+    cpu.set_recompiler(true);
+    // Runs LD V5, 0x01 once, then overwrites it with LD V5, 0x02 and jumps
+    // back to it.
+    cpu.load_game_data(&[
+        0x65, 0x01, // 0x200: LD V5, 0x01
+        0x60, 0x65, // 0x202: LD V0, 0x65
+        0x61, 0x02, // 0x204: LD V1, 0x02
+        0xA2, 0x00, // 0x206: LD I, 0x200
+        0xF1, 0x55, // 0x208: LD [I], V1
+        0x12, 0x00, // 0x20A: JP 0x200
+    ])
+    .unwrap();
+    assert_eq!(Ok(5), cpu.run_block());
+    assert_eq!(Ok(1), cpu.run_block());
+    assert_eq!(0x01, cpu.v[5]);
+    assert_eq!(Ok(5), cpu.run_block());
+    assert_eq!(0x02, cpu.v[5]);
+}
+
+#[test]
+fn run_block_disabled() {
+    let mut cpu = create_cpu();
+    cpu.load_game_data(&[0x60, 0x01, 0x61, 0x01]).unwrap();
+    assert_eq!(Ok(1), cpu.run_block());
+    assert_eq!(0x202, cpu.pc);
+}
+
+// Runs bundled ROMs block by block and checks the state against stepping
+// through the same number of instructions with the interpreter.
+#[test]
+fn run_block_matches_interpreter() {
+    let roms: [&[u8]; 4] = [
+        include_bytes!("../../../15PUZZLE"),
+        include_bytes!("../../../BLINKY"),
+        include_bytes!("../../../BLITZ"),
+        include_bytes!("../../../BRIX"),
+    ];
+    for rom in roms.iter() {
+        let mut recompiled = create_cpu();
+        recompiled.set_recompiler(true);
+        recompiled.load_game_data(rom).unwrap();
+        let mut interpreted = create_cpu();
+        interpreted.load_game_data(rom).unwrap();
+
+        for _ in 0..2000 {
+            let executed = recompiled.run_block().unwrap();
+            for _ in 0..executed {
+                interpreted.step().unwrap();
+            }
+            assert_eq!(interpreted.pc, recompiled.pc);
+            assert_eq!(interpreted.v, recompiled.v);
+            assert_eq!(interpreted.i, recompiled.i);
+            assert_eq!(interpreted.sp, recompiled.sp);
+            assert_eq!(interpreted.cycles, recompiled.cycles);
+            assert_eq!(interpreted.memory, recompiled.memory);
+            assert_eq!(interpreted.display, recompiled.display);
+        }
+    }
+}
+
+// Movies replay frame by frame, so they must end the same way with either
+// backend.
+#[test]
+fn play_movie_matches_interpreter() {
+    let rom = include_bytes!("../../../BRIX");
+    let mut movie = Movie::new(database::hash(rom), Platform::Chip8, 11, 7);
+    movie.frames = [vec![0; 300], vec![1 << 4; 100]].concat();
+    let replay = |recompile| {
+        let mut cpu = create_cpu();
+        cpu.set_recompiler(recompile);
+        cpu.load_game_data(rom).unwrap();
+        cpu.play_movie(&movie).unwrap();
+        cpu.frame_sha1()
+    };
+    assert_eq!(replay(false), replay(true));
+}
+
+fn create_cpu() -> CPU {
+    let mut cpu = CPU::new(channel().1);
+    cpu.rng = WrappedRng::Mock(StepRng::new(7, 13));
+    cpu
+}
@@ -0,0 +1,206 @@
+// A deliberately simple CHIP-8 interpreter, written from the spec without
+// reusing any of the CPU's decoding or execution code. The differential tests
+// run it in lockstep with the CPU and compare the two after every instruction.
+
+use crate::digits::DIGITS;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+const MEMORY_SIZE: usize = 4096;
+const STACK_DEPTH: usize = 16;
+
+/// Why the reference interpreter could not execute an instruction. The
+/// differential tests stop comparing once this happens.
+#[derive(Debug, PartialEq)]
+pub enum Fault {
+    UnknownOpcode(u16),
+    StackOverflow,
+    StackUnderflow,
+}
+
+pub struct Reference {
+    pub v: [u8; 16],
+    pub i: usize,
+    pub pc: usize,
+    pub stack: Vec<usize>,
+    pub memory: Vec<u8>,
+    pub pixels: Vec<bool>,
+    pub delay_timer: u8,
+    pub keys: [bool; 16],
+    // Mirrors rand's StepRng, so both interpreters see the same random bytes.
+    rand_state: u64,
+    rand_increment: u64,
+}
+
+impl Reference {
+    pub fn new(rom: &[u8], rand_state: u64, rand_increment: u64) -> Reference {
+        let mut memory = vec![0; MEMORY_SIZE];
+        memory[..DIGITS.len()].copy_from_slice(&DIGITS);
+        memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        Reference {
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            memory,
+            pixels: vec![false; WIDTH * HEIGHT],
+            delay_timer: 0,
+            keys: [false; 16],
+            rand_state,
+            rand_increment,
+        }
+    }
+
+    /// Returns the instruction at PC.
+    pub fn fetch(&self) -> u16 {
+        (u16::from(self.read(self.pc)) << 8) | u16::from(self.read(self.pc + 1))
+    }
+
+    /// Returns whether the instruction at PC waits for a key press.
+    pub fn waits_for_key(&self) -> bool {
+        self.fetch() & 0xF0FF == 0xF00A
+    }
+
+    // Memory accesses wrap around the 4 KB address space.
+    fn read(&self, addr: usize) -> u8 {
+        self.memory[addr % MEMORY_SIZE]
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        self.memory[addr % MEMORY_SIZE] = value;
+    }
+
+    /// Executes one instruction. |key| is the key pressed if the instruction
+    /// waits for one. Nothing is modified if a fault is returned.
+    pub fn step(&mut self, key: Option<u8>) -> Result<(), Fault> {
+        let opcode = self.fetch();
+        let x = usize::from((opcode >> 8) & 0xF);
+        let y = usize::from((opcode >> 4) & 0xF);
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = usize::from(opcode & 0xFFF);
+        let mut next = self.pc + 2;
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => self.pixels = vec![false; WIDTH * HEIGHT],
+            0x0 if opcode == 0x00EE => match self.stack.pop() {
+                Some(addr) => next = addr,
+                None => return Err(Fault::StackUnderflow),
+            },
+            // Machine code routines are ignored.
+            0x0 => {}
+            0x1 => next = nnn,
+            0x2 => {
+                if self.stack.len() == STACK_DEPTH {
+                    return Err(Fault::StackOverflow);
+                }
+                self.stack.push(next);
+                next = nnn;
+            }
+            0x3 if self.v[x] == nn => next += 2,
+            0x3 => {}
+            0x4 if self.v[x] != nn => next += 2,
+            0x4 => {}
+            0x5 if n == 0 && self.v[x] == self.v[y] => next += 2,
+            0x5 if n == 0 => {}
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = self.v[x].wrapping_add(nn),
+            0x8 => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, None),
+                    0x2 => (vx & vy, None),
+                    0x3 => (vx ^ vy, None),
+                    0x4 => (
+                        vx.wrapping_add(vy),
+                        Some((u16::from(vx) + u16::from(vy) > 0xFF) as u8),
+                    ),
+                    0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+                    0x6 => (vx >> 1, Some(vx & 1)),
+                    0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+                    0xE => (vx << 1, Some(vx >> 7)),
+                    _ => return Err(Fault::UnknownOpcode(opcode)),
+                };
+                self.v[x] = result;
+                if let Some(flag) = flag {
+                    self.v[0xF] = flag;
+                }
+            }
+            0x9 if self.v[x] != self.v[y] => next += 2,
+            0x9 => {}
+            0xA => self.i = nnn,
+            0xB => next = nnn + usize::from(self.v[0]),
+            0xC => {
+                let byte = self.rand_state as u8;
+                self.rand_state = self.rand_state.wrapping_add(self.rand_increment);
+                self.v[x] = byte & nn;
+            }
+            0xD => self.draw(self.v[x], self.v[y], usize::from(n)),
+            0xE if nn == 0x9E || nn == 0xA1 => {
+                // Only the low nibble selects the key.
+                let key = usize::from(self.v[x] & 0xF);
+                if self.keys[key] == (nn == 0x9E) {
+                    next += 2;
+                }
+            }
+            0xF => match nn {
+                0x07 => self.v[x] = self.delay_timer,
+                0x0A => {
+                    let key = key.expect("a key is required to execute Fx0A");
+                    self.keys[usize::from(key)] = true;
+                    self.v[x] = key;
+                }
+                0x15 => self.delay_timer = self.v[x],
+                // The sound timer is not emulated.
+                0x18 => {}
+                0x1E => self.i += usize::from(self.v[x]),
+                0x29 => self.i = usize::from(self.v[x]) * 5,
+                0x33 => {
+                    let vx = self.v[x];
+                    self.write(self.i, vx / 100);
+                    self.write(self.i + 1, vx / 10 % 10);
+                    self.write(self.i + 2, vx % 10);
+                }
+                0x55 => {
+                    for r in 0..=x {
+                        self.write(self.i + r, self.v[r]);
+                    }
+                    self.i += x + 1;
+                }
+                0x65 => {
+                    for r in 0..=x {
+                        self.v[r] = self.read(self.i + r);
+                    }
+                    self.i += x + 1;
+                }
+                _ => return Err(Fault::UnknownOpcode(opcode)),
+            },
+            _ => return Err(Fault::UnknownOpcode(opcode)),
+        }
+
+        self.pc = next % MEMORY_SIZE;
+        Ok(())
+    }
+
+    // Draws pixel by pixel, wrapping every pixel around the screen edges.
+    fn draw(&mut self, x: u8, y: u8, rows: usize) {
+        let mut collision = 0;
+        for row in 0..rows {
+            let byte = self.read(self.i + row);
+            for col in 0..8 {
+                if byte & (0x80 >> col) == 0 {
+                    continue;
+                }
+                let px = (usize::from(x) + col) % WIDTH;
+                let py = (usize::from(y) + row) % HEIGHT;
+                let pixel = &mut self.pixels[py * WIDTH + px];
+                if *pixel {
+                    collision = 1;
+                }
+                *pixel = !*pixel;
+            }
+        }
+        self.v[0xF] = collision;
+    }
+}
@@ -0,0 +1,81 @@
+use super::{Fault, CPU};
+use crate::script::{Hook, Script};
+use std::mem;
+
+impl CPU {
+    /// Calls the hooks of |script| as the program runs. Scripts that watch
+    /// every instruction run with the interpreter.
+    pub fn set_script(&mut self, script: Script) {
+        self.script = Some(script);
+    }
+
+    /// Returns whether the script has asked to end the run.
+    pub fn script_exited(&self) -> bool {
+        self.script.as_ref().is_some_and(|script| script.exited())
+    }
+
+    pub(super) fn has_hook(&self, hook: Hook) -> bool {
+        self.script
+            .as_ref()
+            .is_some_and(|script| script.has_hook(hook))
+    }
+
+    // Calls |hook| with the machine state lent to the script, then takes back
+    // whatever the script changed.
+    pub(super) fn run_hook(&mut self, hook: Hook) -> Result<(), Fault> {
+        let mut script = match self.script.take() {
+            Some(script) if script.has_hook(hook) => script,
+            script => {
+                self.script = script;
+                return Ok(());
+            }
+        };
+        {
+            let mut machine = script.machine();
+            machine.v = self.v;
+            machine.i = self.i;
+            machine.pc = self.pc;
+            machine.sp = self.sp;
+            machine.delay_timer = self.delay_timer;
+            machine.memory = mem::take(&mut self.memory);
+            mem::swap(&mut machine.display, &mut self.display);
+            machine.keys = self.key_state;
+            machine.frame = self.frames;
+        }
+        let result = script.call(hook);
+        let written = {
+            let mut machine = script.machine();
+            self.v = machine.v;
+            self.i = machine.i;
+            self.pc = machine.pc;
+            self.delay_timer = machine.delay_timer;
+            self.memory = mem::take(&mut machine.memory);
+            mem::swap(&mut self.display, &mut machine.display);
+            self.key_state = machine.keys;
+            mem::take(&mut machine.written)
+        };
+        self.script = Some(script);
+        // Writing the bytes again drops any translations of them.
+        for addr in written {
+            self.write_memory(addr, self.memory[addr]);
+        }
+        result.map_err(|e| Fault::Script {
+            pc: self.pc,
+            message: e.to_string(),
+        })
+    }
+
+    /// Runs up to |frames| frames as fast as possible without a window, until
+    /// the script exits. Fx0A checks the keypad once per frame, so scripts can
+    /// press keys for it.
+    pub fn run_headless(&mut self, frames: u64) -> Result<(), Fault> {
+        self.poll_keys = true;
+        for _ in 0..frames {
+            if self.script_exited() {
+                break;
+            }
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+}
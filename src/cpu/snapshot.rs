@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests;
+
+use super::{WrappedRng, CPU};
+use crate::display::Display;
+use crate::platform::Platform;
+
+/// The machine state at one point in time, which can be restored later.
+/// This includes the random number generator, so a restored program sees the
+/// same random numbers again.
+#[derive(Clone)]
+pub struct Snapshot {
+    v: [u8; 16],
+    i: usize,
+    delay_timer: u8,
+    pc: usize,
+    stack: Vec<u16>,
+    sp: usize,
+    platform: Platform,
+    memory: Vec<u8>,
+    display: Display,
+    rng: WrappedRng,
+    cycles: u64,
+}
+
+impl Snapshot {
+    /// Number of instructions the CPU had executed when the snapshot was taken.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+impl CPU {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            v: self.v,
+            i: self.i,
+            delay_timer: self.delay_timer,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            sp: self.sp,
+            platform: self.platform,
+            memory: self.memory.clone(),
+            display: self.display.clone(),
+            rng: self.rng.clone(),
+            cycles: self.cycles,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.delay_timer = snapshot.delay_timer;
+        self.pc = snapshot.pc;
+        self.stack = snapshot.stack.clone();
+        self.sp = snapshot.sp;
+        self.platform = snapshot.platform;
+        self.memory = snapshot.memory.clone();
+        self.display.copy_from(&snapshot.display);
+        self.rng = snapshot.rng.clone();
+        self.cycles = snapshot.cycles;
+        // Anything translated from the old memory is stale.
+        if !self.decoded.is_empty() {
+            self.set_instruction_cache(true);
+        }
+        if self.blocks.is_some() {
+            self.set_recompiler(true);
+        }
+    }
+}
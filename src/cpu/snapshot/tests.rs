@@ -0,0 +1,34 @@
+use crate::cpu::tests::create_cpu;
+use crate::cpu::CPU;
+
+#[test]
+fn restore() {
+    let mut cpu = create_cpu();
+    cpu.set_seed(3);
+    // Draws random sprites at random positions and stores random bytes.
+    cpu.load_game_data(&[
+        0xC0, 0xFF, // 0x200: RND V0, 0xFF
+        0xC1, 0x3F, // 0x202: RND V1, 0x3F
+        0xF0, 0x29, // 0x204: LD F, V0
+        0xD1, 0x15, // 0x206: DRW V1, V1, 5
+        0xA3, 0x00, // 0x208: LD I, 0x300
+        0xF0, 0x33, // 0x20A: LD B, V0
+        0x12, 0x00, // 0x20C: JP 0x200
+    ])
+    .unwrap();
+    let run = |cpu: &mut CPU| {
+        for _ in 0..50 {
+            cpu.step().unwrap();
+        }
+        (cpu.v, cpu.memory.clone(), cpu.frame_sha1(), cpu.cycles)
+    };
+    run(&mut cpu);
+    cpu.delay_timer = 9;
+    let snapshot = cpu.snapshot();
+    let expected = run(&mut cpu);
+    cpu.delay_timer = 0;
+    cpu.restore(&snapshot);
+    assert_eq!(9, cpu.delay_timer);
+    assert_eq!(50, snapshot.cycles());
+    assert_eq!(expected, run(&mut cpu));
+}
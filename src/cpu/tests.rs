@@ -1,10 +1,15 @@
+use crate::cheat::{Cheat, Command, Filter, Target};
 use crate::cpu::*;
-use crate::FRAME_BUFFER_BYTES;
-use rand::rngs::mock::StepRng;
-use std::sync::mpsc::channel;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::sync::RwLock;
+use crate::database;
+use crate::display::Display;
+use crate::movie::Movie;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::script::Script;
+use std::fs;
+use std::sync::mpsc::{channel, sync_channel};
+use std::thread;
+use std::time::Duration;
 
 //
 // DECODE tests
@@ -207,6 +212,18 @@ fn decode_sub_regs() {
     );
 }
 
+#[test]
+fn decode_unknown() {
+    assert_eq!(
+        Unknown { opcode: 0x5121 },
+        decode_instruction(&[0x51, 0x21])
+    );
+    assert_eq!(
+        Unknown { opcode: 0x8127 },
+        decode_instruction(&[0x81, 0x27])
+    );
+}
+
 #[test]
 fn decode_xor_regs() {
     assert_eq!(
@@ -216,471 +233,830 @@ fn decode_xor_regs() {
 }
 
 //
-// EXECUTE tests
+// DISASSEMBLE tests
 //
 #[test]
-fn execute_add_i_reg() {
-    let mut cpu = create_cpu();
-    cpu.v[0] = 1;
-    cpu.i = 5;
-    cpu.execute(AddIReg { reg: 0 });
-    assert_eq!(6, cpu.i);
-    assert_eq!(0x202, cpu.pc);
+fn disassemble() {
+    assert_eq!("CLS", Clear.to_string());
+    assert_eq!("JP 0x200", Jump { addr: 0x200 }.to_string());
+    assert_eq!("LD VA, 0x0F", LdRegByte { reg: 0xA, val: 0xF }.to_string());
+    assert_eq!(
+        "DRW V1, V2, 5",
+        Draw {
+            reg_x: 1,
+            reg_y: 2,
+            sprite_bytes: 5
+        }
+        .to_string()
+    );
+    assert_eq!("LD [I], V3", LdMemIRegs { last_reg: 3 }.to_string());
 }
 
+//
+// LOAD tests
+//
 #[test]
-fn execute_add_reg_byte() {
+fn load_game_data() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0;
-    cpu.execute(AddRegByte { reg: 0, val: 16 });
-    assert_eq!(16, cpu.v[0]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0x12, 0x34, 0x56]).unwrap();
+    assert_eq!([0x12, 0x34, 0x56], cpu.memory[0x200..0x203]);
+    assert_eq!(0xF0, cpu.memory[0]);
 }
 
 #[test]
-fn execute_add_regs_no_overflow() {
+fn load_game_data_too_large() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 1;
-    cpu.v[5] = 5;
-    cpu.execute(AddRegs { reg_x: 0, reg_y: 5 });
-    assert_eq!(6, cpu.v[0]);
-    assert_eq!(0, cpu.v[0xF]);
-    assert_eq!(0x202, cpu.pc);
+    assert!(cpu.load_game_data(&[0; 0xE01]).is_err());
+
+    cpu.set_platform(Platform::XoChip);
+    cpu.load_game_data(&[0xAB; 0xE01]).unwrap();
+    assert_eq!(0x10000, cpu.memory.len());
+    assert_eq!(0xAB, cpu.memory[0x1000]);
 }
 
+//
+// EXECUTE tests
+//
+execute_tests!(super::execute_for_test);
+
 #[test]
-fn execute_add_regs_overflow() {
+fn step() {
     let mut cpu = create_cpu();
-    cpu.v[3] = 1;
-    cpu.v[7] = 0xFF;
-    cpu.execute(AddRegs { reg_x: 3, reg_y: 7 });
-    assert_eq!(0, cpu.v[3]);
-    assert_eq!(1, cpu.v[0xF]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0x61, 0x12, 0x71, 0x01]).unwrap();
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(0x13, cpu.v[1]);
+    assert_eq!(0x204, cpu.pc);
+    assert_eq!(2, cpu.cycles);
 }
 
 #[test]
-fn execute_and_regs() {
+fn step_unknown_opcode() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0b111;
-    cpu.v[1] = 0b101;
-    cpu.execute(AndRegs { reg_x: 0, reg_y: 1 });
-    assert_eq!(0b101, cpu.v[0]);
-    assert_eq!(0b101, cpu.v[1]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0xB1, 0x23]).unwrap();
+    assert_eq!(
+        Err(Fault::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0xB123
+        }),
+        cpu.step()
+    );
+    assert_eq!(0x200, cpu.pc);
 }
 
 #[test]
-fn execute_call() {
+fn step_wraps_pc() {
     let mut cpu = create_cpu();
-    cpu.sp = 1;
-    cpu.pc = 0x112;
-    cpu.execute(Call { addr: 0x114 });
-    assert_eq!(2, cpu.sp);
-    assert_eq!(0x112, cpu.stack[1]);
-    assert_eq!(0x114, cpu.pc);
+    cpu.memory[0xFFE] = 0x61;
+    cpu.memory[0xFFF] = 0x12;
+    cpu.pc = 0xFFE;
+    cpu.step().unwrap();
+    assert_eq!(0x12, cpu.v[1]);
+    assert_eq!(0, cpu.pc);
 }
 
 #[test]
-fn execute_draw_no_collision() {
+fn run_frame() {
     let mut cpu = create_cpu();
-    cpu.i = 0;
-    cpu.v[0] = 4;
-    cpu.v[1] = 0;
-    cpu.v[0xF] = 1;
-    cpu.memory[0] = 0b01110111;
-    {
-        let mut fb = cpu.frame_buffer.write().unwrap();
-        fb[0] = 0b1000;
-        fb[1] = 0b10000000;
-    }
-
-    cpu.execute(Draw {
-        reg_x: 0,
-        reg_y: 1,
-        sprite_bytes: 1,
-    });
+    cpu.set_tick_rate(3);
+    cpu.delay_timer = 2;
+    cpu.load_game_data(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(3, cpu.cycles);
+    assert_eq!(2, cpu.v[0]);
+    assert_eq!(1, cpu.delay_timer);
+    cpu.run_frame().unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(9, cpu.cycles);
+    assert_eq!(0, cpu.delay_timer);
+}
+
+#[test]
+fn update_key_state_speed_controls() {
+    let (key_sender, key_receiver) = channel();
+    let mut cpu = CPU::new(key_receiver);
+    let send = |key, state| {
+        let event = Event::Input(Input::Button(ButtonArgs {
+            state,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }));
+        key_sender.send(Control::Input(event)).unwrap();
+    };
+
+    // Frame advance only works while paused.
+    send(Key::F3, ButtonState::Press);
+    cpu.handle_controls();
+    assert_eq!(Speed::default(), cpu.speed());
+
+    send(Key::F2, ButtonState::Press);
+    send(Key::F2, ButtonState::Release);
+    send(Key::F3, ButtonState::Press);
+    send(Key::F4, ButtonState::Press);
+    send(Key::Tab, ButtonState::Press);
+    cpu.handle_controls();
+    assert_eq!(
+        Speed {
+            paused: true,
+            frame_advance: true,
+            slow_motion: true,
+            fast_forward: true,
+        },
+        cpu.speed()
+    );
 
-    {
-        let fb = cpu.frame_buffer.read().unwrap();
-        assert_eq!(0xF, fb[0]);
-        assert_eq!(0xF0, fb[1]);
-        assert_eq!(0, cpu.v[0xF]);
-        assert_eq!(0x202, cpu.pc);
-    }
+    send(Key::Tab, ButtonState::Release);
+    send(Key::F2, ButtonState::Press);
+    send(Key::F4, ButtonState::Press);
+    cpu.handle_controls();
+    assert!(!cpu.speed().paused);
+    assert!(!cpu.speed().slow_motion);
+    assert!(!cpu.speed().fast_forward);
+    assert_eq!([false; 16], cpu.key_state);
+}
+
+#[test]
+fn play_movie() {
+    // Waits for a key, then draws the digit for it at a random position.
+    let rom = [
+        0xF0, 0x0A, // 0x200: LD V0, K
+        0xF0, 0x29, // 0x202: LD F, V0
+        0xC1, 0x3F, // 0x204: RND V1, 0x3F
+        0xC2, 0x1F, // 0x206: RND V2, 0x1F
+        0xD1, 0x25, // 0x208: DRW V1, V2, 5
+        0x12, 0x00, // 0x20A: JP 0x200
+    ];
+    let mut movie = Movie::new(database::hash(&rom), Platform::Chip8, 4, 99);
+    movie.frames = vec![0, 0, 1 << 3, 0, 1 << 7, 1 << 7, 0];
+    let replay = |movie: &Movie| {
+        let mut cpu = create_cpu();
+        cpu.set_tick_rate(movie.tick_rate);
+        cpu.load_game_data(&rom).unwrap();
+        cpu.play_movie(movie).unwrap();
+        cpu.frame_sha1()
+    };
+    let frame = replay(&movie);
+    assert_eq!(frame, replay(&movie));
+    assert_ne!(database::hash(Display::default().as_bytes()), frame);
+
+    movie.seed = 100;
+    assert_ne!(frame, replay(&movie));
+}
+
+#[test]
+fn record_movie() {
+    let mut cpu = create_cpu();
+    let movie = Movie::new(String::from("abc"), Platform::Chip8, 4, 1);
+    cpu.record_movie(movie.clone());
+    assert!(cpu.poll_keys);
+    let recorded = cpu.finish_movie().unwrap();
+    assert_eq!(
+        Some(database::hash(Display::default().as_bytes())),
+        recorded.final_frame_sha1
+    );
+    assert_eq!(movie.frames, recorded.frames);
+    assert_eq!(None, cpu.finish_movie());
 }
 
 #[test]
-fn execute_draw_collision() {
-    let mut cpu = create_cpu();
-    cpu.i = 0;
-    cpu.v[0] = 4;
-    cpu.v[1] = 1;
-    cpu.v[0xF] = 0;
-    cpu.memory[0] = 0b01110111;
+fn update_key_state_save_and_load_state() {
+    let (key_sender, key_receiver) = channel();
+    let mut cpu = CPU::new(key_receiver);
+    let press = |key| {
+        let event = Event::Input(Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }));
+        key_sender.send(Control::Input(event)).unwrap();
+    };
 
-    {
-        let mut fb = cpu.frame_buffer.write().unwrap();
-        fb[8] = 0xF;
-        fb[9] = 0xF0;
-    }
+    // Loading does nothing until a state is saved.
+    cpu.v[0] = 1;
+    press(Key::F9);
+    cpu.handle_controls();
+    assert_eq!(1, cpu.v[0]);
 
-    cpu.execute(Draw {
-        reg_x: 0,
-        reg_y: 1,
-        sprite_bytes: 1,
-    });
+    press(Key::F5);
+    cpu.handle_controls();
+    cpu.v[0] = 2;
+    press(Key::F9);
+    cpu.handle_controls();
+    assert_eq!(1, cpu.v[0]);
 
-    {
-        let fb = cpu.frame_buffer.read().unwrap();
-        assert_eq!(0x8, fb[8]);
-        assert_eq!(0x80, fb[9]);
-        assert_eq!(1, cpu.v[0xF]);
-        assert_eq!(0x202, cpu.pc);
-    }
+    // The saved state can be loaded again.
+    cpu.v[0] = 3;
+    press(Key::F9);
+    cpu.handle_controls();
+    assert_eq!(1, cpu.v[0]);
 }
 
 #[test]
-fn execute_draw_wraparound_horizontal() {
+fn run_frame_applies_cheats() {
     let mut cpu = create_cpu();
-    cpu.i = 0;
-    cpu.v[0] = 60;
-    cpu.v[1] = 0;
-    cpu.v[0xF] = 0;
-    cpu.memory[0] = 0xFF;
-
-    {
-        let mut fb = cpu.frame_buffer.write().unwrap();
-        fb[0] = 0x80;
-        fb[7] = 0x1;
-    }
-
-    cpu.execute(Draw {
-        reg_x: 0,
-        reg_y: 1,
-        sprite_bytes: 1,
-    });
-
-    {
-        let fb = cpu.frame_buffer.read().unwrap();
-        assert_eq!(0b1110, fb[7]);
-        assert_eq!(0b01110000, fb[0]);
-        assert_eq!(1, cpu.v[0xF]);
-        assert_eq!(0x202, cpu.pc);
-    }
+    // Counts down V1 and the byte at 0x300.
+    cpu.load_game_data(&[
+        0x71, 0xFF, // 0x200: ADD V1, 0xFF
+        0xA3, 0x00, // 0x202: LD I, 0x300
+        0xF0, 0x65, // 0x204: LD V0, [I]
+        0x70, 0xFF, // 0x206: ADD V0, 0xFF
+        0xA3, 0x00, // 0x208: LD I, 0x300
+        0xF0, 0x55, // 0x20A: LD [I], V0
+        0x12, 0x00, // 0x20C: JP 0x200
+    ])
+    .unwrap();
+    cpu.set_tick_rate(7);
+    cpu.set_cheats(vec![
+        Cheat {
+            name: String::new(),
+            target: Target::Memory { address: 0x300 },
+            value: 9,
+        },
+        Cheat {
+            name: String::new(),
+            target: Target::Register { register: 1 },
+            value: 5,
+        },
+    ]);
+    cpu.run_frame().unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(8, cpu.memory[0x300]);
+    assert_eq!(4, cpu.v[1]);
 }
 
 #[test]
-fn execute_draw_wraparound_vertical() {
+fn run_command_searches_and_freezes() {
     let mut cpu = create_cpu();
-    cpu.i = 0;
-    cpu.v[0] = 0;
-    cpu.v[1] = 31;
-    cpu.memory[0] = 0xFF;
-    cpu.memory[1] = 0xFF;
-
-    cpu.execute(Draw {
-        reg_x: 0,
-        reg_y: 1,
-        sprite_bytes: 2,
-    });
+    cpu.load_game_data(&[0x60, 0x07]).unwrap();
+    assert_eq!("4096 candidates", cpu.run_command(Command::Search));
+    cpu.memory[0x300] = 1;
+    cpu.memory[0x301] = 2;
+    cpu.run_command(Command::Filter(Filter::Changed));
+    assert_eq!(
+        "1 candidates",
+        cpu.run_command(Command::Filter(Filter::Equal(2)))
+    );
+    assert_eq!("[301] = 02", cpu.run_command(Command::List));
 
-    {
-        let fb = cpu.frame_buffer.read().unwrap();
-        assert_eq!(0xFF, fb[0]);
-        assert_eq!(0xFF, fb[248]); // beginning of last row, 31*8
-        assert_eq!(0, cpu.v[0xF]);
-        assert_eq!(0x202, cpu.pc);
-    }
+    let freeze = Command::parse("freeze 301 63").unwrap();
+    assert_eq!("froze [301] = 63", cpu.run_command(freeze));
+    cpu.run_frame().unwrap();
+    assert_eq!(0x63, cpu.memory[0x301]);
+    assert_eq!("[301] = 63", cpu.run_command(Command::Cheats));
+    let unfreeze = Command::Unfreeze(Target::Memory { address: 0x301 });
+    assert_eq!("unfroze [301]", cpu.run_command(unfreeze.clone()));
+    assert_eq!("[301] isn't frozen", cpu.run_command(unfreeze));
+    assert!(cpu.cheats().is_empty());
 }
 
 #[test]
-fn execute_ld_dt_reg() {
+fn run_frame_calls_script_hooks() {
+    let mut cpu = create_cpu();
+    // Waits for a key, then draws digit 0 and loops.
+    cpu.load_game_data(&[
+        0xF1, 0x0A, // 0x200: LD V1, K
+        0xD0, 0x05, // 0x202: DRW V0, V0, 5
+        0x12, 0x04, // 0x204: JP 0x204
+    ])
+    .unwrap();
+    let script = Script::new(
+        "fn on_frame() { if frame() == 2 { press(7); } }
+        fn on_instruction(pc) {
+            if this.count == () { this.count = 0; }
+            this.count += 1;
+            set_v(2, this.count);
+        }
+        fn on_draw() {
+            assert(pixel(0, 0), \"digit not drawn\");
+            assert(v(1) == 7, \"wrong key\");
+            exit();
+        }",
+    )
+    .unwrap();
+    cpu.set_script(script);
+    cpu.set_tick_rate(2);
+    cpu.run_headless(10).unwrap();
+    assert!(cpu.script_exited());
+    assert_eq!(3, cpu.frames);
+    // Two instructions per frame for three frames, the last one not yet run.
+    assert_eq!(6, cpu.v[2]);
+    assert_eq!(7, cpu.v[1]);
+}
+
+#[test]
+fn run_frame_script_assertion_faults() {
     let mut cpu = create_cpu();
-    cpu.v[3] = 25;
-    cpu.execute(LdDtReg { reg: 3 });
-    {
-        let delay_timer = cpu.delay_timer.lock().unwrap();
-        assert_eq!(25, *delay_timer);
+    cpu.load_game_data(&[0x12, 0x00]).unwrap();
+    let script =
+        Script::new("fn on_frame() { poke(0x201, 0x02); assert(v(0) == 1, \"V0 isn't 1\"); }")
+            .unwrap();
+    cpu.set_script(script);
+    match cpu.run_frame() {
+        Err(Fault::Script { pc: 0x200, message }) => assert!(message.contains("V0 isn't 1")),
+        other => panic!("expected a script fault, got {:?}", other),
     }
-    assert_eq!(0x202, cpu.pc);
+    // Memory is handed back even though the hook failed.
+    assert_eq!(0x02, cpu.memory[0x201]);
 }
 
 #[test]
-fn execute_ld_i_addr() {
+fn step_self_modifying_code() {
     let mut cpu = create_cpu();
-    cpu.execute(LdIAddr { addr: 0x123 });
-    assert_eq!(0x123, cpu.i);
+    // Runs LD V5, 0x01 once, then overwrites it with LD V5, 0x02 and jumps
+    // back to it.
+    cpu.load_game_data(&[
+        0x65, 0x01, // 0x200: LD V5, 0x01
+        0x60, 0x65, // 0x202: LD V0, 0x65
+        0x61, 0x02, // 0x204: LD V1, 0x02
+        0xA2, 0x00, // 0x206: LD I, 0x200
+        0xF1, 0x55, // 0x208: LD [I], V1
+        0x12, 0x00, // 0x20A: JP 0x200
+    ])
+    .unwrap();
+    for _ in 0..7 {
+        cpu.step().unwrap();
+    }
     assert_eq!(0x202, cpu.pc);
+    assert_eq!(0x02, cpu.v[5]);
 }
 
 #[test]
-fn execute_ld_i_digit_reg() {
+fn step_self_modifying_code_bcd() {
     let mut cpu = create_cpu();
-    cpu.v[1] = 2;
-    cpu.execute(LdIDigitReg { reg: 1 });
-    assert_eq!(10, cpu.i);
+    // Runs LD V1, 0x00 once, then overwrites its second byte with the hundreds
+    // digit of V0 and jumps back to it.
+    cpu.load_game_data(&[
+        0x61, 0x00, // 0x200: LD V1, 0x00
+        0x60, 0xFF, // 0x202: LD V0, 0xFF
+        0xA2, 0x01, // 0x204: LD I, 0x201
+        0xF0, 0x33, // 0x206: LD B, V0
+        0x12, 0x00, // 0x208: JP 0x200
+    ])
+    .unwrap();
+    for _ in 0..6 {
+        cpu.step().unwrap();
+    }
     assert_eq!(0x202, cpu.pc);
+    assert_eq!(2, cpu.v[1]);
 }
 
 #[test]
-fn execute_ld_mem_i_bcd_reg() {
+fn step_without_instruction_cache() {
     let mut cpu = create_cpu();
-    cpu.v[5] = 254;
-    cpu.i = 200;
-    cpu.execute(LdMemIBcdReg { reg: 5 });
-    assert_eq!(2, cpu.memory[200]);
-    assert_eq!(5, cpu.memory[201]);
-    assert_eq!(4, cpu.memory[202]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.set_instruction_cache(false);
+    cpu.load_game_data(&[0x61, 0x12, 0x12, 0x00]).unwrap();
+    cpu.step().unwrap();
+    cpu.memory[0x201] = 0x34;
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(0x34, cpu.v[1]);
 }
 
 #[test]
-fn execute_ld_mem_i_regs() {
+fn load_game_data_clears_instruction_cache() {
     let mut cpu = create_cpu();
-    cpu.i = 0x100;
-    cpu.v[0] = 5;
-    cpu.v[1] = 6;
-    cpu.execute(LdMemIRegs { last_reg: 1 });
-    assert_eq!(5, cpu.memory[0x100]);
-    assert_eq!(6, cpu.memory[0x101]);
-    assert_eq!(0x102, cpu.i);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0x61, 0x12]).unwrap();
+    cpu.step().unwrap();
+    cpu.pc = PROGRAM_START;
+    cpu.load_game_data(&[0x61, 0x34]).unwrap();
+    cpu.step().unwrap();
+    assert_eq!(0x34, cpu.v[1]);
 }
 
 #[test]
-fn execute_ld_reg_byte() {
+fn set_stack_depth_is_limited() {
     let mut cpu = create_cpu();
-    cpu.execute(LdRegByte { reg: 1, val: 0xFF });
-    assert_eq!(0xFF, cpu.v[1]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.set_stack_depth(usize::MAX);
+    assert_eq!(MAX_STACK_DEPTH, cpu.stack.len());
+    cpu.set_stack_depth(0);
+    assert_eq!(1, cpu.stack.len());
 }
 
 #[test]
-fn execute_ld_reg_dt() {
+fn call_stack() {
     let mut cpu = create_cpu();
-    *cpu.delay_timer.lock().unwrap() = 25;
-    cpu.v[3] = 16;
-    cpu.execute(LdRegDt { reg: 3 });
-    assert_eq!(25, cpu.v[3]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xE0])
+        .unwrap();
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(0x208, cpu.pc);
+    assert_eq!(
+        vec![
+            CallFrame {
+                call_site: 0x200,
+                target: Some(0x204)
+            },
+            CallFrame {
+                call_site: 0x204,
+                target: Some(0x208)
+            },
+        ],
+        cpu.call_stack()
+    );
 }
 
 #[test]
-fn execute_ld_regs_mem_i() {
+fn set_vip_interpreter_checks_size() {
     let mut cpu = create_cpu();
-    cpu.i = 0x100;
-    cpu.memory[0x100] = 1;
-    cpu.memory[0x101] = 2;
-    cpu.memory[0x102] = 3;
-    cpu.execute(LdRegsMemI { last_reg: 2 });
-    assert_eq!(1, cpu.v[0]);
-    assert_eq!(2, cpu.v[1]);
-    assert_eq!(3, cpu.v[2]);
-    assert_eq!(0x103, cpu.i);
-    assert_eq!(0x202, cpu.pc);
+    assert!(cpu.set_vip_interpreter(&[0; 0x100]).is_err());
+    // Without the interpreter, the VIP routine isn't available.
+    cpu.set_random(RandomRoutine::Vip, 1);
+    assert_eq!(RandomRoutine::Xorshift, cpu.rng.routine());
+    cpu.set_vip_interpreter(&[0; 0x200]).unwrap();
+    cpu.set_random(RandomRoutine::Vip, 1);
+    assert_eq!(RandomRoutine::Vip, cpu.rng.routine());
+}
+
+#[test]
+fn random_routine_from_str() {
+    assert_eq!(Ok(RandomRoutine::Vip), "vip".parse());
+    assert_eq!(Ok(RandomRoutine::Xorshift), "xorshift".parse());
+    assert!("lfsr".parse::<RandomRoutine>().is_err());
+}
+
+// Sets the delay timer, waits for it, then counts up V2 and starts again.
+const TIMER_WAIT_PROGRAM: [u8; 14] = [
+    0x60, 0x05, // 0x200: LD V0, 0x05
+    0xF0, 0x15, // 0x202: LD DT, V0
+    0xF1, 0x07, // 0x204: LD V1, DT
+    0x31, 0x00, // 0x206: SE V1, 0x00
+    0x12, 0x04, // 0x208: JP 0x204
+    0x72, 0x01, // 0x20A: ADD V2, 0x01
+    0x12, 0x00, // 0x20C: JP 0x200
+];
+
+#[test]
+fn skip_idle_loop_is_invisible() {
+    for tick_rate in 1..=12 {
+        let mut skipping = create_cpu();
+        let mut interpreting = create_cpu();
+        interpreting.set_idle_loop_skipping(false);
+        for cpu in [&mut skipping, &mut interpreting].iter_mut() {
+            cpu.set_tick_rate(tick_rate);
+            cpu.load_game_data(&TIMER_WAIT_PROGRAM).unwrap();
+        }
+        for _ in 0..40 {
+            skipping.run_frame().unwrap();
+            interpreting.run_frame().unwrap();
+            assert_eq!(interpreting.pc, skipping.pc, "tick rate {}", tick_rate);
+            assert_eq!(interpreting.v, skipping.v, "tick rate {}", tick_rate);
+            assert_eq!(interpreting.cycles, skipping.cycles);
+            assert_eq!(interpreting.delay_timer, skipping.delay_timer);
+        }
+        assert!(skipping.v[2] > 0);
+    }
 }
 
 #[test]
-fn execute_ld_reg_reg() {
+fn skip_idle_loop_runs_rest_of_frame() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 5;
-    cpu.v[1] = 6;
-    cpu.execute(LdRegReg { reg_x: 0, reg_y: 1 });
-    assert_eq!(6, cpu.v[0]);
-    assert_eq!(6, cpu.v[1]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&TIMER_WAIT_PROGRAM).unwrap();
+    cpu.pc = 0x204;
+    cpu.delay_timer = 3;
+    assert_eq!(7, cpu.skip_idle_loop(7));
+    assert_eq!(0x206, cpu.pc);
+    assert_eq!(3, cpu.v[1]);
+    assert_eq!(7, cpu.cycles);
 }
 
 #[test]
-fn execute_jump() {
+fn skip_idle_loop_stops_when_loop_exits() {
     let mut cpu = create_cpu();
-    cpu.execute(Jump { addr: 0x2e8 });
-    assert_eq!(0x2e8, cpu.pc);
+    cpu.load_game_data(&TIMER_WAIT_PROGRAM).unwrap();
+    cpu.pc = 0x204;
+    assert_eq!(0, cpu.skip_idle_loop(7));
+    assert_eq!(0x204, cpu.pc);
 }
 
 #[test]
-fn execute_or_regs() {
+fn skip_idle_loop_needs_timer_wait() {
     let mut cpu = create_cpu();
-    cpu.v[5] = 0b1100;
-    cpu.v[7] = 1;
-    cpu.execute(OrRegs { reg_x: 5, reg_y: 7 });
-    assert_eq!(cpu.v[5], 0b1101);
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[
+        0xF1, 0x07, // 0x200: LD V1, DT
+        0x32, 0x00, // 0x202: SE V2, 0x00
+        0x12, 0x00, // 0x204: JP 0x200
+    ])
+    .unwrap();
+    cpu.delay_timer = 3;
+    assert_eq!(0, cpu.skip_idle_loop(7));
+    // Waits for the timer to be nonzero, which it is.
+    cpu.load_game_data(&[
+        0xF1, 0x07, // 0x200: LD V1, DT
+        0x41, 0x00, // 0x202: SNE V1, 0x00
+        0x12, 0x00, // 0x204: JP 0x200
+    ])
+    .unwrap();
+    assert_eq!(0, cpu.skip_idle_loop(7));
+    cpu.delay_timer = 0;
+    assert_eq!(7, cpu.skip_idle_loop(7));
+}
+
+#[test]
+fn skip_idle_loop_can_be_disabled() {
+    let mut cpu = create_cpu();
+    cpu.load_game_data(&TIMER_WAIT_PROGRAM).unwrap();
+    cpu.pc = 0x204;
+    cpu.delay_timer = 3;
+    cpu.set_idle_loop_skipping(false);
+    assert_eq!(0, cpu.skip_idle_loop(7));
+    cpu.set_idle_loop_skipping(true);
+    cpu.set_profiling(true);
+    assert_eq!(0, cpu.skip_idle_loop(7));
 }
 
+// Draws a sprite and counts the draws in V2, forever.
+pub(super) const DRAW_LOOP_PROGRAM: [u8; 6] = [
+    0xD0, 0x01, // 0x200: DRW V0, V0, 1
+    0x72, 0x01, // 0x202: ADD V2, 0x01
+    0x12, 0x00, // 0x204: JP 0x200
+];
+
 #[test]
-fn execute_rand_reg_byte() {
+fn run_frame_draws_freely_without_vblank_quirk() {
     let mut cpu = create_cpu();
-    // The CPU's RNG will always yield 0xF
-    cpu.rng = WrappedRng::Mock(StepRng::new(0xF, 0));
-    cpu.execute(RandRegByte { reg: 3, val: 0xAB });
-    assert_eq!(0xB, cpu.v[3]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.set_tick_rate(9);
+    cpu.load_game_data(&DRAW_LOOP_PROGRAM).unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(3, cpu.v[2]);
+    assert_eq!(9, cpu.cycles);
 }
 
 #[test]
-fn execute_ret() {
+fn run_frame_publishes_completed_frames() {
     let mut cpu = create_cpu();
-    cpu.sp = 1;
-    cpu.stack[0] = 0x200;
-    cpu.execute(Ret);
-    assert_eq!(0x202, cpu.pc);
-    assert_eq!(0, cpu.sp);
+    let (frame_sender, frame_receiver) = sync_channel(1);
+    cpu.set_frame_sender(frame_sender);
+    cpu.set_tick_rate(3);
+    cpu.load_game_data(&DRAW_LOOP_PROGRAM).unwrap();
+    cpu.step().unwrap();
+    // Nothing is published mid-frame.
+    assert!(frame_receiver.try_recv().is_err());
+    cpu.run_frame().unwrap();
+    let frames: Vec<Display> = frame_receiver.try_iter().collect();
+    assert_eq!(vec![cpu.display.clone()], frames);
 }
 
 #[test]
-fn execute_shift_left_reg_set_vf() {
+fn run_frame_publishes_only_changed_frames() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0b10000010;
-    cpu.execute(ShiftLeftReg { reg: 0 });
-    assert_eq!(0b100, cpu.v[0]);
-    assert_eq!(1, cpu.v[0xF]);
-    assert_eq!(0x202, cpu.pc);
+    let (frame_sender, frame_receiver) = sync_channel(1);
+    cpu.set_frame_sender(frame_sender);
+    cpu.load_game_data(&[0x12, 0x00]).unwrap();
+    cpu.run_frame().unwrap();
+    // The first frame is always published, so the window has something to
+    // show.
+    assert_eq!(1, frame_receiver.try_iter().count());
+    cpu.run_frame().unwrap();
+    assert_eq!(0, frame_receiver.try_iter().count());
 }
 
 #[test]
-fn execute_shift_left_reg_unset_vf() {
+fn run_frame_holds_frames_until_receiver_catches_up() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0b110;
-    cpu.v[0xF] = 1;
-    cpu.execute(ShiftLeftReg { reg: 0 });
-    assert_eq!(0b1100, cpu.v[0]);
-    assert_eq!(0, cpu.v[0xF]);
-    assert_eq!(0x202, cpu.pc);
+    let (frame_sender, frame_receiver) = sync_channel(1);
+    cpu.set_frame_sender(frame_sender);
+    cpu.set_tick_rate(3);
+    cpu.load_game_data(&DRAW_LOOP_PROGRAM).unwrap();
+    for _ in 0..3 {
+        cpu.run_frame().unwrap();
+    }
+    // Only the first frame fit, the others waited for it to be taken.
+    assert_eq!(1, frame_receiver.try_iter().count());
+    cpu.run_frame().unwrap();
+    let frames: Vec<Display> = frame_receiver.try_iter().collect();
+    assert_eq!(vec![cpu.display.clone()], frames);
 }
 
 #[test]
-fn execute_shift_right_reg_set_vf() {
+fn run_frame_vblank_quirk_stalls_draw_until_next_frame() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0b1111;
-    cpu.execute(ShiftRightReg { reg: 0 });
-    assert_eq!(0b111, cpu.v[0]);
-    assert_eq!(1, cpu.v[0xF]);
+    cpu.set_tick_rate(9);
+    cpu.set_quirks(Quirks {
+        vblank: true,
+        ..Quirks::default()
+    });
+    cpu.load_game_data(&DRAW_LOOP_PROGRAM).unwrap();
+    cpu.run_frame().unwrap();
+    // The draw at the start of the frame ends it.
+    assert_eq!(0x202, cpu.pc);
+    assert_eq!(0, cpu.v[2]);
+    assert_eq!(1, cpu.cycles);
+    assert_eq!(1, cpu.frames);
+    for _ in 0..9 {
+        cpu.run_frame().unwrap();
+    }
+    // One draw per frame.
+    assert_eq!(9, cpu.v[2]);
     assert_eq!(0x202, cpu.pc);
+    assert_eq!(10, cpu.frames);
 }
 
 #[test]
-fn execute_shift_right_reg_unset_vf() {
+fn run_frame_vblank_quirk_ticks_timers_before_next_instruction() {
     let mut cpu = create_cpu();
-    cpu.v[0] = 0b110;
-    cpu.v[0xF] = 1;
-    cpu.execute(ShiftRightReg { reg: 0 });
-    assert_eq!(0b11, cpu.v[0]);
-    assert_eq!(0, cpu.v[0xF]);
-    assert_eq!(0x202, cpu.pc);
+    cpu.set_tick_rate(20);
+    cpu.set_quirks(Quirks {
+        vblank: true,
+        ..Quirks::default()
+    });
+    cpu.load_game_data(&[
+        0x60, 0x05, // 0x200: LD V0, 0x05
+        0xF0, 0x15, // 0x202: LD DT, V0
+        0xD1, 0x01, // 0x204: DRW V1, V1, 1
+        0xF2, 0x07, // 0x206: LD V2, DT
+        0x12, 0x08, // 0x208: JP 0x208
+    ])
+    .unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(0x206, cpu.pc);
+    assert_eq!(4, cpu.delay_timer);
+    cpu.run_frame().unwrap();
+    assert_eq!(4, cpu.v[2]);
+}
+
+#[test]
+fn execute_draw_wraps_by_default() {
+    assert!(Quirks::default().wrap);
+}
+
+#[test]
+fn run_quits_while_waiting_for_key() {
+    let (sender, receiver) = channel();
+    let mut cpu = CPU::new(receiver);
+    cpu.load_game_data(&[0xF0, 0x0A]).unwrap();
+    let quitter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        sender.send(Control::Quit).unwrap();
+        sender
+    });
+    cpu.run();
+    quitter.join().unwrap();
+    // The key wait was interrupted, and would run again.
+    assert_eq!(0x200, cpu.pc);
 }
 
 #[test]
-fn execute_skip_eq_reg_bytes() {
+fn run_quits_when_senders_are_gone() {
     let mut cpu = create_cpu();
-    cpu.v[4] = 16;
-    cpu.execute(SkipEqRegBytes { reg: 4, val: 16 });
-    assert_eq!(0x204, cpu.pc);
-
-    let mut cpu = create_cpu();
-    cpu.v[4] = 14;
-    cpu.execute(SkipEqRegBytes { reg: 4, val: 16 });
-    assert_eq!(0x202, cpu.pc);
+    cpu.load_game_data(&[0x12, 0x00]).unwrap();
+    cpu.run();
+    assert_eq!(0, cpu.frames);
 }
 
 #[test]
-fn execute_skip_neq_reg_bytes() {
+fn key_wait_stops_when_senders_are_gone() {
     let mut cpu = create_cpu();
-    cpu.v[4] = 16;
-    cpu.execute(SkipNEqRegBytes { reg: 4, val: 16 });
+    cpu.load_game_data(&[0x60, 0x05, 0xF1, 0x0A]).unwrap();
+    cpu.run_frame().unwrap();
     assert_eq!(0x202, cpu.pc);
-
-    let mut cpu = create_cpu();
-    cpu.v[4] = 14;
-    cpu.execute(SkipNEqRegBytes { reg: 4, val: 16 });
-    assert_eq!(0x204, cpu.pc);
+    assert_eq!(2, cpu.cycles);
+    assert!(cpu.quitting);
 }
 
 #[test]
-fn execute_skip_neq_regs() {
-    let mut cpu = create_cpu();
-    cpu.v[4] = 17;
-    cpu.v[5] = 17;
-    cpu.execute(SkipNEqRegs { reg_x: 4, reg_y: 5 });
-    assert_eq!(0x202, cpu.pc);
-
-    let mut cpu = create_cpu();
-    cpu.v[4] = 14;
-    cpu.v[5] = 18;
-    cpu.execute(SkipNEqRegs { reg_x: 4, reg_y: 5 });
-    assert_eq!(0x204, cpu.pc);
+fn run_frame_ends_key_wait_for_controls() {
+    let (sender, receiver) = channel();
+    let mut cpu = CPU::new(receiver);
+    cpu.load_game_data(&[0xF0, 0x0A]).unwrap();
+    sender.send(Control::Pause(true)).unwrap();
+    cpu.run_frame().unwrap();
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(1, cpu.cycles);
+    assert!(!cpu.speed.paused);
+    cpu.handle_controls();
+    assert!(cpu.speed.paused);
+    assert!(!cpu.quitting);
 }
 
 #[test]
-fn execute_skip_reg_key_pressed() {
+fn reset_restores_initial_state() {
     let mut cpu = create_cpu();
-    cpu.v[4] = 7;
-    cpu.key_state[7] = true;
-    cpu.execute(SkipRegKeyPressed { reg: 4 });
-    assert_eq!(0x204, cpu.pc);
-
-    let mut cpu = create_cpu();
-    cpu.v[5] = 6;
-    cpu.key_state[6] = false;
-    cpu.execute(SkipRegKeyPressed { reg: 5 });
-    assert_eq!(0x202, cpu.pc);
+    cpu.set_instruction_cache(true);
+    cpu.load_game_data(&[0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x22, 0x00])
+        .unwrap();
+    cpu.delay_timer = 30;
+    cpu.sound_timer = 20;
+    cpu.display.set_pixel(3, 3, true);
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(5, cpu.memory[0x300]);
+    assert_eq!(1, cpu.sp);
+    cpu.reset();
+    assert_eq!([0; 16], cpu.v);
+    assert_eq!(0, cpu.i);
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(0, cpu.sp);
+    assert!(cpu.stack.iter().all(|addr| *addr == 0));
+    assert_eq!(0, cpu.delay_timer);
+    assert_eq!(0, cpu.sound_timer);
+    assert_eq!(Display::default(), cpu.display);
+    assert_eq!(&DIGITS[..], &cpu.memory[..DIGITS.len()]);
+    assert_eq!(0x60, cpu.memory[0x200]);
+    assert_eq!(0, cpu.memory[0x300]);
+    // The program runs from the start again.
+    cpu.step().unwrap();
+    assert_eq!(5, cpu.v[0]);
 }
 
 #[test]
-fn execute_skip_reg_key_npressed() {
+fn load_rom_replaces_program() {
     let mut cpu = create_cpu();
-    cpu.v[4] = 7;
-    cpu.key_state[7] = false;
-    cpu.execute(SkipRegKeyNPressed { reg: 4 });
-    assert_eq!(0x204, cpu.pc);
-
-    let mut cpu = create_cpu();
-    cpu.v[5] = 6;
-    cpu.key_state[6] = true;
-    cpu.execute(SkipRegKeyNPressed { reg: 5 });
+    cpu.load_game_data(&[0x60, 0x05]).unwrap();
+    cpu.step().unwrap();
+    assert!(cpu.load_rom(&[]).is_err());
+    assert_eq!(0x202, cpu.pc);
+    cpu.load_rom(&[0x61, 0x07]).unwrap();
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(0, cpu.v[0]);
+    cpu.step().unwrap();
+    assert_eq!(7, cpu.v[1]);
+    // A reset starts the new program again.
+    cpu.reset();
+    assert_eq!(0x61, cpu.memory[0x200]);
+}
+
+#[test]
+fn reset_key_restarts_program() {
+    let (sender, receiver) = channel();
+    let mut cpu = CPU::new(receiver);
+    cpu.load_game_data(&[0x60, 0x05, 0x12, 0x02]).unwrap();
+    cpu.step().unwrap();
+    let event = Event::Input(Input::Button(ButtonArgs {
+        state: ButtonState::Press,
+        button: Button::Keyboard(Key::F7),
+        scancode: None,
+    }));
+    sender.send(Control::Input(event)).unwrap();
+    cpu.handle_controls();
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(0, cpu.v[0]);
+}
+
+#[test]
+fn reset_key_restarts_program_waiting_for_key() {
+    let (sender, receiver) = channel();
+    let mut cpu = CPU::new(receiver);
+    cpu.load_game_data(&[0x60, 0x05, 0xF1, 0x0A]).unwrap();
+    let event = Event::Input(Input::Button(ButtonArgs {
+        state: ButtonState::Press,
+        button: Button::Keyboard(Key::F7),
+        scancode: None,
+    }));
+    sender.send(Control::Input(event)).unwrap();
+    cpu.run_frame().unwrap();
     assert_eq!(0x202, cpu.pc);
+    cpu.handle_controls();
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(0, cpu.v[0]);
+    assert!(!cpu.quitting);
 }
 
 #[test]
-fn execute_sub_regs_underflow() {
+fn reload_reads_rom_file_again() {
+    let path = std::env::temp_dir().join(format!("chip8-reload-{}.ch8", std::process::id()));
     let mut cpu = create_cpu();
-    cpu.v[4] = 3;
-    cpu.v[6] = 2;
-    cpu.execute(SubRegs { reg_x: 4, reg_y: 6 });
-    assert_eq!(1, cpu.v[4]);
-    assert_eq!(1, cpu.v[0xF]);
+    cpu.load_game_data(&[0x60, 0x05]).unwrap();
+    cpu.step().unwrap();
+    // Without a file, the program carries on.
+    cpu.handle_control(Control::Reload);
     assert_eq!(0x202, cpu.pc);
+    cpu.set_rom_path(path.clone());
+    fs::write(&path, [0x61, 0x07]).unwrap();
+    cpu.handle_control(Control::Reload);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(0x200, cpu.pc);
+    assert_eq!(0, cpu.v[0]);
+    cpu.step().unwrap();
+    assert_eq!(7, cpu.v[1]);
 }
 
 #[test]
-fn execute_sub_regs_no_underflow() {
+fn reset_ignored_while_recording() {
     let mut cpu = create_cpu();
-    cpu.v[0xA] = 1;
-    cpu.v[0xD] = 2;
-    cpu.v[0xF] = 1;
-    cpu.execute(SubRegs {
-        reg_x: 0xA,
-        reg_y: 0xD,
-    });
-    assert_eq!(0xFF, cpu.v[0xA]);
-    assert_eq!(0, cpu.v[0xF]);
+    cpu.load_game_data(&[0x60, 0x05]).unwrap();
+    cpu.record_movie(Movie::new(String::new(), Platform::Chip8, 10, 1));
+    cpu.step().unwrap();
+    cpu.handle_control(Control::Reset);
+    cpu.handle_control(Control::LoadRom(vec![0x61, 0x07]));
     assert_eq!(0x202, cpu.pc);
+    assert_eq!(5, cpu.v[0]);
 }
 
 #[test]
-fn execute_xor_regs() {
+fn save_state_ignored_while_recording() {
     let mut cpu = create_cpu();
-    cpu.v[1] = 0b1111;
-    cpu.v[2] = 0b1010;
-    cpu.execute(XorRegs { reg_x: 1, reg_y: 2 });
-    assert_eq!(cpu.v[1], 0b0101);
+    cpu.load_game_data(&[0x60, 0x05]).unwrap();
+    cpu.record_movie(Movie::new(String::new(), Platform::Chip8, 10, 1));
+    assert!(cpu.handle_control_key(Button::Keyboard(Key::F5), true));
+    assert!(cpu.saved_state.is_none());
+    cpu.saved_state = Some(cpu.snapshot());
+    cpu.step().unwrap();
+    assert!(cpu.handle_control_key(Button::Keyboard(Key::F9), true));
     assert_eq!(0x202, cpu.pc);
+    assert_eq!(5, cpu.v[0]);
 }
 
-#[cfg(test)]
-fn create_cpu() -> CPU {
-    let frame_buffer = Arc::new(RwLock::new([0; FRAME_BUFFER_BYTES]));
-    let delay_timer = Arc::new(Mutex::new(0));
-    CPU::new(delay_timer, frame_buffer, channel().1, channel().1)
+pub(super) fn create_cpu() -> CPU {
+    CPU::new(channel().1)
 }
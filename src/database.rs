@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod tests;
+
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::window::Palette;
+use piston_window::Key;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Built-in entries for the ROMs that ship with this repository. Larger
+// databases, such as the community CHIP-8 database, can be loaded on top.
+const BUILTIN_DATABASE: &str = include_str!("database/builtin.json");
+
+// Host keys for the semantic key names used by the database.
+const SEMANTIC_KEYS: [(&str, Key); 10] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("a", Key::Space),
+    ("b", Key::Return),
+    ("player2Up", Key::I),
+    ("player2Down", Key::K),
+    ("player2Left", Key::J),
+    ("player2Right", Key::L),
+];
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Io(e) => write!(f, "unable to read ROM database: {}", e),
+            DatabaseError::Parse(e) => write!(f, "unable to parse ROM database: {}", e),
+        }
+    }
+}
+
+impl error::Error for DatabaseError {}
+
+impl From<io::Error> for DatabaseError {
+    fn from(e: io::Error) -> DatabaseError {
+        DatabaseError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for DatabaseError {
+    fn from(e: serde_json::Error) -> DatabaseError {
+        DatabaseError::Parse(e)
+    }
+}
+
+/// Settings for a single ROM, resolved from its database entry.
+#[derive(Clone, Debug, Default)]
+pub struct GameSettings {
+    pub title: String,
+    pub platform: Option<Platform>,
+    pub quirks: Quirks,
+    pub tick_rate: Option<u32>,
+    pub palette: Option<Palette>,
+    pub key_bindings: HashMap<Key, usize>,
+}
+
+/// Per-game settings keyed by the SHA-1 of the ROM's bytes.
+#[derive(Debug, Default)]
+pub struct Database {
+    games: HashMap<String, GameSettings>,
+}
+
+// The following mirror the layout of the community database's programs.json.
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, Quirks>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, usize>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl Database {
+    pub fn builtin() -> Database {
+        Database::from_json(BUILTIN_DATABASE).expect("built-in ROM database is invalid")
+    }
+
+    pub fn from_json(json: &str) -> Result<Database, DatabaseError> {
+        let mut database = Database::default();
+        database.merge_json(json)?;
+        Ok(database)
+    }
+
+    /// Adds the entries in the database file at |path|, replacing any existing
+    /// entries for the same ROMs.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), DatabaseError> {
+        let json = fs::read_to_string(path)?;
+        self.merge_json(&json)
+    }
+
+    fn merge_json(&mut self, json: &str) -> Result<(), DatabaseError> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let settings = rom.resolve(&program.title);
+                self.games.insert(hash.to_lowercase(), settings);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, data: &[u8]) -> Option<&GameSettings> {
+        self.games.get(&hash(data))
+    }
+}
+
+/// Returns the hex encoded SHA-1 of |data|.
+pub fn hash(data: &[u8]) -> String {
+    sha1::Sha1::from(data).digest().to_string()
+}
+
+impl RomEntry {
+    fn resolve(self, title: &str) -> GameSettings {
+        // Use the first listed platform this interpreter knows about.
+        let platform = self
+            .platforms
+            .iter()
+            .find_map(|id| id.parse::<Platform>().ok().map(|platform| (id, platform)));
+        let quirks = platform
+            .and_then(|(id, _)| self.quirky_platforms.get(id))
+            .cloned()
+            .unwrap_or_default();
+        let palette = self.colors.as_ref().and_then(|colors| {
+            match (colors.pixels.first(), colors.pixels.get(1)) {
+                (Some(background), Some(foreground)) => Some(Palette {
+                    background: parse_color(background)?,
+                    foreground: parse_color(foreground)?,
+                }),
+                _ => None,
+            }
+        });
+        let key_bindings = SEMANTIC_KEYS
+            .iter()
+            .filter_map(|(name, key)| match self.keys.get(*name) {
+                Some(keycode) if *keycode < 16 => Some((*key, *keycode)),
+                _ => None,
+            })
+            .collect();
+        GameSettings {
+            title: title.to_string(),
+            platform: platform.map(|(_, platform)| platform),
+            quirks,
+            tick_rate: self.tickrate,
+            palette,
+            key_bindings,
+        }
+    }
+}
+
+// Parses a "#rrggbb" color into RGBA components.
+fn parse_color(color: &str) -> Option<[f32; 4]> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let component = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .ok()
+            .map(|c| f32::from(c) / 255.0)
+    };
+    Some([component(0)?, component(2)?, component(4)?, 1.0])
+}
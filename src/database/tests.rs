@@ -0,0 +1,66 @@
+use crate::database::*;
+
+const JSON: &str = r##"[
+  {
+    "title": "Test Game",
+    "roms": {
+      "A9993E364706816ABA3E25717850C26C9CD0D89D": {
+        "file": "test.ch8",
+        "platforms": ["megachip8", "superchip"],
+        "quirkyPlatforms": {
+          "superchip": { "memoryLeaveIUnchanged": true, "logic": true, "vblank": true, "wrap": false }
+        },
+        "tickrate": 30,
+        "colors": { "pixels": ["#000000", "#ff8000"] },
+        "keys": { "left": 4, "right": 6, "a": 17 }
+      }
+    }
+  }
+]"##;
+
+#[test]
+fn hash_rom() {
+    assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", hash(b"abc"));
+}
+
+#[test]
+fn lookup_resolves_settings() {
+    let database = Database::from_json(JSON).unwrap();
+    let settings = database.lookup(b"abc").unwrap();
+    assert_eq!("Test Game", settings.title);
+    assert_eq!(Some(Platform::SuperChip), settings.platform);
+    assert_eq!(
+        Quirks {
+            memory_leave_i_unchanged: true,
+            logic: true,
+            vblank: true,
+            wrap: false,
+            ..Quirks::default()
+        },
+        settings.quirks
+    );
+    assert_eq!(Some(30), settings.tick_rate);
+    assert_eq!(
+        Some(Palette {
+            background: [0.0, 0.0, 0.0, 1.0],
+            foreground: [1.0, 128.0 / 255.0, 0.0, 1.0],
+        }),
+        settings.palette
+    );
+    assert_eq!(2, settings.key_bindings.len());
+    assert_eq!(Some(&4), settings.key_bindings.get(&Key::Left));
+    assert_eq!(Some(&6), settings.key_bindings.get(&Key::Right));
+}
+
+#[test]
+fn lookup_unknown_rom() {
+    let database = Database::from_json(JSON).unwrap();
+    assert!(database.lookup(b"abcd").is_none());
+}
+
+#[test]
+fn builtin_database() {
+    let database = Database::builtin();
+    let brix = include_bytes!("../../BRIX");
+    assert_eq!("Brix", database.lookup(brix).unwrap().title);
+}
@@ -0,0 +1,261 @@
+#[cfg(test)]
+mod tests;
+
+/// The sizes of display the interpreter supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Resolution {
+    // 64x32, as on the original CHIP-8
+    #[default]
+    Low,
+    // 128x64, as on SUPER-CHIP
+    High,
+}
+
+impl Resolution {
+    pub fn width(self) -> usize {
+        match self {
+            Resolution::Low => 64,
+            Resolution::High => 128,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Resolution::Low => 32,
+            Resolution::High => 64,
+        }
+    }
+}
+
+/// A monochrome display with one bit per pixel. Coordinates wrap around the
+/// edges, so out of range pixels can't panic.
+#[derive(Clone, Debug)]
+pub struct Display {
+    resolution: Resolution,
+    // Pixels row by row, eight to a byte with the leftmost in the most
+    // significant bit
+    bits: Vec<u8>,
+    // Incremented by every change, so readers can tell when to redraw
+    generation: u64,
+}
+
+// Displays are equal if they show the same pixels, however they got there.
+impl PartialEq for Display {
+    fn eq(&self, other: &Display) -> bool {
+        self.resolution == other.resolution && self.bits == other.bits
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new(Resolution::default())
+    }
+}
+
+impl Display {
+    /// Creates a display with every pixel unlit.
+    pub fn new(resolution: Resolution) -> Display {
+        Display {
+            resolution,
+            bits: vec![0; resolution.width() / 8 * resolution.height()],
+            generation: 0,
+        }
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn width(&self) -> usize {
+        self.resolution.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.resolution.height()
+    }
+
+    /// Switches to |resolution|, which clears the display.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        let generation = self.generation;
+        *self = Display::new(resolution);
+        self.generation = generation + 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.iter_mut().for_each(|byte| *byte = 0);
+        self.generation += 1;
+    }
+
+    /// Shows the same pixels as |other|, counting as a change.
+    pub fn copy_from(&mut self, other: &Display) {
+        self.resolution = other.resolution;
+        self.bits.clone_from(&other.bits);
+        self.generation += 1;
+    }
+
+    /// Returns a number that changes whenever any pixel may have changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the packed pixels, eight to a byte, row by row. At the low
+    /// resolution this is the layout of the original interpreter's display
+    /// memory.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    // Returns the byte and bit mask of the pixel at |x|, |y|, wrapping them
+    // around the display.
+    fn location(&self, x: usize, y: usize) -> (usize, u8) {
+        let x = x % self.width();
+        let y = y % self.height();
+        (y * self.width() / 8 + x / 8, 0x80 >> (x % 8))
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (index, mask) = self.location(x, y);
+        self.bits[index] & mask != 0
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) {
+        let (index, mask) = self.location(x, y);
+        if lit {
+            self.bits[index] |= mask;
+        } else {
+            self.bits[index] &= !mask;
+        }
+        self.generation += 1;
+    }
+
+    /// Flips the pixel at |x|, |y|, returning whether it was lit.
+    pub fn xor_pixel(&mut self, x: usize, y: usize) -> bool {
+        let (index, mask) = self.location(x, y);
+        let was_lit = self.bits[index] & mask != 0;
+        self.bits[index] ^= mask;
+        self.generation += 1;
+        was_lit
+    }
+
+    /// XORs |sprite| onto the display, one byte per row, with its top left
+    /// corner at |x|, |y|. The corner wraps around the display. With |wrap|,
+    /// so does the rest of the sprite, and otherwise it's clipped at the
+    /// edges. Returns whether any lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], wrap: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let x = x % width;
+        let y = y % height;
+        let mut collision = false;
+        for (row, byte) in sprite.iter().enumerate() {
+            if !wrap && y + row >= height {
+                break;
+            }
+            for column in 0..8 {
+                if byte & (0x80 >> column) == 0 || (!wrap && x + column >= width) {
+                    continue;
+                }
+                collision |= self.xor_pixel(x + column, y + row);
+            }
+        }
+        collision
+    }
+
+    /// Moves every row down by |rows|, leaving blank rows at the top.
+    pub fn scroll_down(&mut self, rows: usize) {
+        let row_bytes = self.width() / 8;
+        let shift = (rows * row_bytes).min(self.bits.len());
+        self.bits.rotate_right(shift);
+        self.bits[..shift].iter_mut().for_each(|byte| *byte = 0);
+        self.generation += 1;
+    }
+
+    /// Moves every row up by |rows|, leaving blank rows at the bottom.
+    pub fn scroll_up(&mut self, rows: usize) {
+        let row_bytes = self.width() / 8;
+        let shift = (rows * row_bytes).min(self.bits.len());
+        self.bits.rotate_left(shift);
+        let len = self.bits.len();
+        self.bits[len - shift..]
+            .iter_mut()
+            .for_each(|byte| *byte = 0);
+        self.generation += 1;
+    }
+
+    /// Moves every pixel left by |columns|, leaving blank columns on the
+    /// right.
+    pub fn scroll_left(&mut self, columns: usize) {
+        let row_bytes = self.width() / 8;
+        let (skip, bits) = (columns / 8, (columns % 8) as u32);
+        for row in self.bits.chunks_mut(row_bytes) {
+            // Each byte takes its pixels from bytes to its right, which
+            // haven't been shifted yet.
+            for index in 0..row_bytes {
+                let byte = |offset: usize| row.get(index + skip + offset).copied().unwrap_or(0);
+                row[index] = shift_left(byte(0), bits) | shift_right(byte(1), 8 - bits);
+            }
+        }
+        self.generation += 1;
+    }
+
+    /// Moves every pixel right by |columns|, leaving blank columns on the
+    /// left.
+    pub fn scroll_right(&mut self, columns: usize) {
+        let row_bytes = self.width() / 8;
+        let (skip, bits) = (columns / 8, (columns % 8) as u32);
+        for row in self.bits.chunks_mut(row_bytes) {
+            // Each byte takes its pixels from bytes to its left, which haven't
+            // been shifted yet.
+            for index in (0..row_bytes).rev() {
+                let byte = |offset: usize| {
+                    index
+                        .checked_sub(skip + offset)
+                        .map_or(0, |source| row[source])
+                };
+                row[index] = shift_right(byte(0), bits) | shift_left(byte(1), 8 - bits);
+            }
+        }
+        self.generation += 1;
+    }
+
+    /// Returns the display as an image with four bytes per pixel, row by row,
+    /// each either |background| or |foreground|.
+    pub fn to_rgba(&self, background: [u8; 4], foreground: [u8; 4]) -> Vec<u8> {
+        let mut image = Vec::with_capacity(self.width() * self.height() * 4);
+        for byte in &self.bits {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    image.extend_from_slice(&foreground);
+                } else {
+                    image.extend_from_slice(&background);
+                }
+            }
+        }
+        image
+    }
+
+    /// Returns the coordinates of every lit pixel, row by row.
+    pub fn lit_pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let row_bytes = self.width() / 8;
+        self.bits
+            .iter()
+            .enumerate()
+            .filter(|(_, byte)| **byte != 0)
+            .flat_map(move |(index, byte)| {
+                let y = index / row_bytes;
+                let left = index % row_bytes * 8;
+                (0..8)
+                    .filter(move |bit| byte & (0x80 >> bit) != 0)
+                    .map(move |bit| (left + bit, y))
+            })
+    }
+}
+
+// Shifts |byte| left by |bits|, which may be 8 or more.
+fn shift_left(byte: u8, bits: u32) -> u8 {
+    byte.checked_shl(bits).unwrap_or(0)
+}
+
+// Shifts |byte| right by |bits|, which may be 8 or more.
+fn shift_right(byte: u8, bits: u32) -> u8 {
+    byte.checked_shr(bits).unwrap_or(0)
+}
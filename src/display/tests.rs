@@ -0,0 +1,187 @@
+use crate::display::*;
+
+#[test]
+fn new_is_blank() {
+    let display = Display::default();
+    assert_eq!(Resolution::Low, display.resolution());
+    assert_eq!((64, 32), (display.width(), display.height()));
+    assert_eq!(256, display.as_bytes().len());
+    assert_eq!(0, display.lit_pixels().count());
+}
+
+#[test]
+fn pixels_pack_into_bytes() {
+    let mut display = Display::default();
+    display.set_pixel(0, 0, true);
+    display.set_pixel(63, 0, true);
+    display.set_pixel(9, 31, true);
+    assert_eq!(0x80, display.as_bytes()[0]);
+    assert_eq!(0x01, display.as_bytes()[7]);
+    assert_eq!(0x40, display.as_bytes()[249]);
+    assert!(display.pixel(9, 31));
+    assert!(!display.pixel(10, 31));
+    display.set_pixel(0, 0, false);
+    assert_eq!(0, display.as_bytes()[0]);
+}
+
+#[test]
+fn pixels_wrap() {
+    let mut display = Display::default();
+    display.set_pixel(64 + 3, 32 + 2, true);
+    assert!(display.pixel(3, 2));
+    assert!(display.pixel(3 + 128, 2 + 64));
+}
+
+#[test]
+fn xor_pixel_returns_previous_state() {
+    let mut display = Display::default();
+    assert!(!display.xor_pixel(5, 5));
+    assert!(display.pixel(5, 5));
+    assert!(display.xor_pixel(5, 5));
+    assert!(!display.pixel(5, 5));
+}
+
+#[test]
+fn draw_sprite_collides() {
+    let mut display = Display::default();
+    assert!(!display.draw_sprite(4, 1, &[0b10100000, 0b01000000], true));
+    assert_eq!(
+        vec![(4, 1), (6, 1), (5, 2)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+    assert!(display.draw_sprite(4, 2, &[0b11000000], true));
+    assert_eq!(
+        vec![(4, 1), (6, 1), (4, 2)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn draw_sprite_wraps_or_clips() {
+    let mut display = Display::default();
+    display.draw_sprite(62, 31, &[0xC0 | 0x20, 0x80], true);
+    assert_eq!(
+        vec![(62, 0), (0, 31), (62, 31), (63, 31)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+    let mut display = Display::default();
+    display.draw_sprite(62 + 64, 31 + 32, &[0xE0, 0x80], false);
+    assert_eq!(
+        vec![(62, 31), (63, 31)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn clear() {
+    let mut display = Display::default();
+    display.draw_sprite(0, 0, &[0xFF; 15], true);
+    display.clear();
+    assert_eq!(Display::default(), display);
+}
+
+#[test]
+fn set_resolution_clears() {
+    let mut display = Display::default();
+    display.set_pixel(1, 1, true);
+    display.set_resolution(Resolution::High);
+    assert_eq!((128, 64), (display.width(), display.height()));
+    assert_eq!(1024, display.as_bytes().len());
+    assert_eq!(0, display.lit_pixels().count());
+    display.set_pixel(127, 63, true);
+    assert_eq!(vec![(127, 63)], display.lit_pixels().collect::<Vec<_>>());
+    assert_eq!(0x01, display.as_bytes()[1023]);
+}
+
+#[test]
+fn scroll_vertically() {
+    let mut display = Display::default();
+    display.set_pixel(3, 0, true);
+    display.set_pixel(4, 30, true);
+    display.scroll_down(2);
+    assert_eq!(vec![(3, 2)], display.lit_pixels().collect::<Vec<_>>());
+    display.scroll_up(1);
+    assert_eq!(vec![(3, 1)], display.lit_pixels().collect::<Vec<_>>());
+    display.scroll_up(40);
+    assert_eq!(0, display.lit_pixels().count());
+}
+
+#[test]
+fn scroll_horizontally() {
+    let mut display = Display::default();
+    display.set_pixel(0, 0, true);
+    display.set_pixel(62, 5, true);
+    display.scroll_right(4);
+    assert_eq!(vec![(4, 0)], display.lit_pixels().collect::<Vec<_>>());
+    display.scroll_left(6);
+    assert_eq!(0, display.lit_pixels().count());
+    display.set_pixel(10, 1, true);
+    display.scroll_left(4);
+    assert_eq!(vec![(6, 1)], display.lit_pixels().collect::<Vec<_>>());
+}
+
+#[test]
+fn scroll_horizontally_across_bytes() {
+    let mut display = Display::new(Resolution::High);
+    let pixels = [(0, 0), (7, 0), (9, 3), (120, 63), (127, 10)];
+    for (x, y) in pixels.iter() {
+        display.set_pixel(*x, *y, true);
+    }
+    let generation = display.generation();
+    display.scroll_right(13);
+    assert_eq!(generation + 1, display.generation());
+    assert_eq!(
+        vec![(13, 0), (20, 0), (22, 3)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+    display.scroll_left(11);
+    assert_eq!(
+        vec![(2, 0), (9, 0), (11, 3)],
+        display.lit_pixels().collect::<Vec<_>>()
+    );
+    display.scroll_left(200);
+    assert_eq!(0, display.lit_pixels().count());
+}
+
+#[test]
+fn changes_advance_generation() {
+    let mut display = Display::default();
+    let start = display.generation();
+    display.draw_sprite(0, 0, &[0x80], true);
+    let drawn = display.generation();
+    assert!(drawn > start);
+    display.pixel(0, 0);
+    display.as_bytes();
+    assert_eq!(drawn, display.generation());
+    display.clear();
+    assert!(display.generation() > drawn);
+}
+
+#[test]
+fn equality_ignores_generation() {
+    let mut display = Display::default();
+    display.set_pixel(1, 1, true);
+    display.set_pixel(1, 1, false);
+    assert_eq!(Display::default(), display);
+    assert!(display.generation() != Display::default().generation());
+}
+
+#[test]
+fn copy_from_is_a_change() {
+    let mut display = Display::default();
+    display.set_pixel(4, 4, true);
+    let other = Display::new(Resolution::High);
+    let before = display.generation();
+    display.copy_from(&other);
+    assert_eq!(other, display);
+    assert!(display.generation() > before);
+}
+
+#[test]
+fn to_rgba() {
+    let mut display = Display::default();
+    display.set_pixel(1, 0, true);
+    let image = display.to_rgba([0, 0, 0, 255], [1, 2, 3, 4]);
+    assert_eq!(64 * 32 * 4, image.len());
+    assert_eq!(&[0, 0, 0, 255, 1, 2, 3, 4, 0, 0, 0, 255], &image[..12]);
+}
@@ -0,0 +1,290 @@
+// The GDB remote serial protocol, for debugging programs from GDB or other
+// debugger frontends:
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+#[cfg(test)]
+mod tests;
+
+use std::io;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// Sent by the debugger to interrupt the running program.
+const INTERRUPT: u8 = 0x03;
+// How often to check whether to stop waiting for a debugger to connect.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Sizes in bytes of the registers as GDB numbers them: V0-VF, I, PC, SP and
+// DT.
+pub const REGISTER_SIZES: [usize; 20] =
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1];
+pub const I_REGISTER: usize = 16;
+pub const PC_REGISTER: usize = 17;
+pub const SP_REGISTER: usize = 18;
+pub const DT_REGISTER: usize = 19;
+
+/// What the debugger sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Incoming {
+    Packet(String),
+    Interrupt,
+    // A packet whose checksum didn't match, which should be sent again
+    Corrupt,
+}
+
+/// A request from the debugger, parsed from a packet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    StopReason,
+    ReadRegisters,
+    WriteRegisters(Vec<u8>),
+    ReadRegister(usize),
+    WriteRegister(usize, Vec<u8>),
+    ReadMemory { addr: usize, len: usize },
+    WriteMemory { addr: usize, data: Vec<u8> },
+    // Resume, optionally from a new address
+    Continue(Option<usize>),
+    Step(Option<usize>),
+    InsertBreakpoint(usize),
+    RemoveBreakpoint(usize),
+    Supported,
+    ReadFeatures { offset: usize, len: usize },
+    StartNoAckMode,
+    Attached,
+    CurrentThread,
+    FirstThreadInfo,
+    NextThreadInfo,
+    SetThread,
+    Kill,
+    Detach,
+    // Anything this server doesn't implement, which gets an empty reply
+    Unsupported,
+}
+
+impl Request {
+    /// Parses the contents of a packet, or returns None if it's malformed.
+    pub fn parse(packet: &str) -> Option<Request> {
+        let request = match packet.as_bytes().first()? {
+            b'?' => Request::StopReason,
+            b'g' => Request::ReadRegisters,
+            b'G' => Request::WriteRegisters(decode_hex(&packet[1..])?),
+            b'p' => Request::ReadRegister(parse_number(&packet[1..])?),
+            b'P' => {
+                let (register, value) = packet[1..].split_once('=')?;
+                Request::WriteRegister(parse_number(register)?, decode_hex(value)?)
+            }
+            b'm' => {
+                let (addr, len) = packet[1..].split_once(',')?;
+                Request::ReadMemory {
+                    addr: parse_number(addr)?,
+                    len: parse_number(len)?,
+                }
+            }
+            b'M' => {
+                let (location, data) = packet[1..].split_once(':')?;
+                let (addr, len) = location.split_once(',')?;
+                let data = decode_hex(data)?;
+                if data.len() != parse_number(len)? {
+                    return None;
+                }
+                Request::WriteMemory {
+                    addr: parse_number(addr)?,
+                    data,
+                }
+            }
+            b'c' => Request::Continue(parse_optional_number(&packet[1..])?),
+            b's' => Request::Step(parse_optional_number(&packet[1..])?),
+            b'Z' | b'z' => {
+                let mut fields = packet[1..].split(',');
+                let kind = fields.next()?;
+                let addr = parse_number(fields.next()?)?;
+                // Software and hardware breakpoints work the same here, but
+                // watchpoints aren't supported.
+                match (packet.as_bytes()[0], kind) {
+                    (b'Z', "0") | (b'Z', "1") => Request::InsertBreakpoint(addr),
+                    (b'z', "0") | (b'z', "1") => Request::RemoveBreakpoint(addr),
+                    _ => Request::Unsupported,
+                }
+            }
+            b'k' => Request::Kill,
+            b'D' => Request::Detach,
+            b'H' => Request::SetThread,
+            _ if packet.starts_with("qSupported") => Request::Supported,
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                let range = &packet["qXfer:features:read:target.xml:".len()..];
+                let (offset, len) = range.split_once(',')?;
+                Request::ReadFeatures {
+                    offset: parse_number(offset)?,
+                    len: parse_number(len)?,
+                }
+            }
+            _ if packet == "QStartNoAckMode" => Request::StartNoAckMode,
+            _ if packet.starts_with("qAttached") => Request::Attached,
+            _ if packet == "qC" => Request::CurrentThread,
+            _ if packet == "qfThreadInfo" => Request::FirstThreadInfo,
+            _ if packet == "qsThreadInfo" => Request::NextThreadInfo,
+            _ => Request::Unsupported,
+        };
+        Some(request)
+    }
+}
+
+/// Splits the bytes received from the debugger into packets.
+#[derive(Debug, Default)]
+pub struct PacketParser {
+    // The packet data received so far, if in a packet
+    data: Option<Vec<u8>>,
+    // The checksum digits received so far, once the data is complete
+    checksum: Option<Vec<u8>>,
+}
+
+impl PacketParser {
+    /// Handles the next byte received, returning anything it completes.
+    pub fn push(&mut self, byte: u8) -> Option<Incoming> {
+        if let Some(checksum) = &mut self.checksum {
+            checksum.push(byte);
+            if checksum.len() < 2 {
+                return None;
+            }
+            let expected = std::str::from_utf8(checksum)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            let data = self.data.take().unwrap_or_default();
+            self.checksum = None;
+            return match String::from_utf8(data) {
+                Ok(data) if expected == Some(checksum_of(data.as_bytes())) => {
+                    Some(Incoming::Packet(data))
+                }
+                _ => Some(Incoming::Corrupt),
+            };
+        }
+        match (&mut self.data, byte) {
+            (_, b'$') => self.data = Some(Vec::new()),
+            (Some(_), b'#') => self.checksum = Some(Vec::new()),
+            (Some(data), _) => data.push(byte),
+            (None, INTERRUPT) => return Some(Incoming::Interrupt),
+            // Acknowledgements, which are only useful over unreliable links.
+            (None, _) => {}
+        }
+        None
+    }
+}
+
+/// Frames |data| as a packet.
+pub fn encode_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum_of(data.as_bytes()))
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_number(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+fn parse_optional_number(hex: &str) -> Option<Option<usize>> {
+    if hex.is_empty() {
+        Some(None)
+    } else {
+        parse_number(hex).map(Some)
+    }
+}
+
+/// Describes the registers to the debugger.
+pub fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+    for register in 0..16 {
+        xml.push_str(&format!(
+            "<reg name=\"v{:x}\" bitsize=\"8\" regnum=\"{}\"/>\n",
+            register, register
+        ));
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\n\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\n\
+         <reg name=\"sp\" bitsize=\"8\"/>\n\
+         <reg name=\"dt\" bitsize=\"8\"/>\n\
+         </feature>\n\
+         </target>\n",
+    );
+    xml
+}
+
+/// Waits for a debugger to connect on |port| on the loopback interface, or
+/// until |cancelled| returns true, which it's asked every so often. Returns
+/// what the debugger sends, read on another thread, along with the stream to
+/// reply on, or None if cancelled.
+pub fn accept(
+    port: u16,
+    cancelled: &mut dyn FnMut() -> bool,
+) -> io::Result<Option<(Receiver<Incoming>, TcpStream)>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    listener.set_nonblocking(true)?;
+    println!("Waiting for a debugger on 127.0.0.1:{}", port);
+    let (stream, address) = loop {
+        match listener.accept() {
+            Ok(connection) => break connection,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if cancelled() {
+                    return Ok(None);
+                }
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    println!("Debugger connected from {}", address);
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    let reader = stream.try_clone()?;
+    Ok(Some((spawn_reader(reader), stream)))
+}
+
+/// Reads from |reader| on another thread until it closes, sending everything
+/// received to the returned channel.
+pub fn spawn_reader<R: Read + Send + 'static>(mut reader: R) -> Receiver<Incoming> {
+    let (sender, receiver) = channel();
+    thread::Builder::new()
+        .name("gdb".to_string())
+        .spawn(move || {
+            let mut parser = PacketParser::default();
+            let mut buffer = [0; 1024];
+            while let Ok(count) = reader.read(&mut buffer) {
+                if count == 0 {
+                    return;
+                }
+                for byte in &buffer[..count] {
+                    if let Some(incoming) = parser.push(*byte) {
+                        if sender.send(incoming).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        })
+        .expect("failed to spawn gdb thread");
+    receiver
+}
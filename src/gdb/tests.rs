@@ -0,0 +1,100 @@
+use crate::gdb::*;
+
+fn parse_all(bytes: &[u8]) -> Vec<Incoming> {
+    let mut parser = PacketParser::default();
+    bytes.iter().filter_map(|byte| parser.push(*byte)).collect()
+}
+
+#[test]
+fn encode_packet_checksum() {
+    assert_eq!("$OK#9a", encode_packet("OK"));
+    assert_eq!("$#00", encode_packet(""));
+}
+
+#[test]
+fn parse_packets() {
+    assert_eq!(
+        vec![
+            Incoming::Packet(String::from("g")),
+            Incoming::Interrupt,
+            Incoming::Packet(String::from("m200,4")),
+        ],
+        parse_all(b"+$g#67\x03+$m200,4#5f")
+    );
+}
+
+#[test]
+fn parse_corrupt_packet() {
+    assert_eq!(vec![Incoming::Corrupt], parse_all(b"$g#00"));
+    assert_eq!(vec![Incoming::Corrupt], parse_all(b"$g#zz"));
+}
+
+#[test]
+fn parse_requests() {
+    assert_eq!(Some(Request::ReadRegisters), Request::parse("g"));
+    assert_eq!(Some(Request::ReadRegister(0x11)), Request::parse("p11"));
+    assert_eq!(
+        Some(Request::WriteRegister(0x10, vec![0x34, 0x12])),
+        Request::parse("P10=3412")
+    );
+    assert_eq!(
+        Some(Request::ReadMemory {
+            addr: 0x200,
+            len: 0x10
+        }),
+        Request::parse("m200,10")
+    );
+    assert_eq!(
+        Some(Request::WriteMemory {
+            addr: 0x300,
+            data: vec![0xAB, 0xCD]
+        }),
+        Request::parse("M300,2:abcd")
+    );
+    assert_eq!(Some(Request::Continue(None)), Request::parse("c"));
+    assert_eq!(Some(Request::Step(Some(0x204))), Request::parse("s204"));
+    assert_eq!(
+        Some(Request::InsertBreakpoint(0x20A)),
+        Request::parse("Z0,20a,2")
+    );
+    assert_eq!(
+        Some(Request::RemoveBreakpoint(0x20A)),
+        Request::parse("z1,20a,2")
+    );
+    assert_eq!(Some(Request::Unsupported), Request::parse("Z2,300,1"));
+    assert_eq!(
+        Some(Request::ReadFeatures {
+            offset: 0,
+            len: 0xFFB
+        }),
+        Request::parse("qXfer:features:read:target.xml:0,ffb")
+    );
+    assert_eq!(
+        Some(Request::Unsupported),
+        Request::parse("vMustReplyEmpty")
+    );
+}
+
+#[test]
+fn parse_malformed_requests() {
+    assert_eq!(None, Request::parse(""));
+    assert_eq!(None, Request::parse("m200"));
+    assert_eq!(None, Request::parse("M300,3:abcd"));
+    assert_eq!(None, Request::parse("G123"));
+    assert_eq!(None, Request::parse("pxyz"));
+}
+
+#[test]
+fn hex() {
+    assert_eq!("00ff1a", encode_hex(&[0x00, 0xFF, 0x1A]));
+    assert_eq!(Some(vec![0x00, 0xFF, 0x1A]), decode_hex("00FF1a"));
+    assert_eq!(None, decode_hex("0"));
+    assert_eq!(None, decode_hex("0g"));
+}
+
+#[test]
+fn target_xml_describes_registers() {
+    let xml = target_xml();
+    assert_eq!(16, xml.matches("bitsize=\"8\" regnum=").count());
+    assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+}
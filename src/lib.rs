@@ -0,0 +1,17 @@
+pub mod cheat;
+pub mod control;
+pub mod coverage;
+pub mod cpu;
+pub mod database;
+mod digits;
+pub mod display;
+pub mod gdb;
+pub mod memory_view;
+pub mod movie;
+pub mod platform;
+pub mod profile;
+pub mod quirks;
+pub mod rom;
+pub mod script;
+pub mod trace;
+pub mod window;
@@ -1,61 +1,333 @@
-mod cpu;
-mod digits;
-mod timers;
-mod window;
+mod options;
 
+use chip8::{
+    cheat, control, coverage, cpu, database, gdb, memory_view, movie, profile, rom, script, trace,
+    window,
+};
+use log::*;
 use std::env;
-use std::sync::mpsc::channel;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::process;
+use std::sync::mpsc::{channel, sync_channel, Sender};
 use std::sync::Arc;
-use std::sync::Mutex;
 use std::sync::RwLock;
 use std::thread;
 
-const FRAME_BUFFER_BYTES: usize = 8 * 32;
-
 fn main() {
     env_logger::init();
-    let args: Vec<String> = env::args().collect();
-    let filename = if args.len() > 1 {
-        args[1].clone()
+    let options = options::Options::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, options::USAGE);
+        process::exit(2);
+    });
+    let game_data = rom::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("{}: {}", options.rom, e);
+        process::exit(1);
+    });
+
+    let mut database = database::Database::builtin();
+    if let Some(path) = &options.database {
+        if let Err(e) = database.load_file(path) {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    let settings = database.lookup(&game_data).cloned().unwrap_or_else(|| {
+        info!("ROM {} is not in the database", database::hash(&game_data));
+        database::GameSettings::default()
+    });
+    let title = if settings.title.is_empty() {
+        String::from("Chip8")
     } else {
-        String::from("BRIX")
+        settings.title.clone()
+    };
+    let palette = settings.palette.unwrap_or_default();
+    let rom_sha1 = database::hash(&game_data);
+    let movie = options.play.as_ref().map(|path| {
+        let movie = movie::Movie::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        if movie.rom_sha1 != rom_sha1 {
+            eprintln!("{}: recorded with a different ROM", path);
+            process::exit(1);
+        }
+        if movie.random == cpu::RandomRoutine::Vip && options.vip_interpreter.is_none() {
+            eprintln!(
+                "{}: recorded with --random vip, which needs --vip-interpreter",
+                path
+            );
+            process::exit(1);
+        }
+        movie
+    });
+    // A movie replays with the settings it was recorded with.
+    let platform = match &movie {
+        Some(movie) => movie.platform,
+        None => options.platform.or(settings.platform).unwrap_or_default(),
+    };
+    let tick_rate = match &movie {
+        Some(movie) => movie.tick_rate,
+        None => settings.tick_rate.unwrap_or(cpu::DEFAULT_TICK_RATE),
     };
+    if let Err(e) = rom::validate(&game_data, platform) {
+        eprintln!("{}: {}", options.rom, e);
+        process::exit(1);
+    }
 
-    let frame_buffer_1 = Arc::new(RwLock::new([0; FRAME_BUFFER_BYTES]));
-    let frame_buffer_2 = frame_buffer_1.clone();
+    let cheats = options.cheats.as_ref().map(|path| {
+        let file = cheat::CheatFile::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        file.lookup(&rom_sha1).to_vec()
+    });
+
+    let tracer = options.trace.as_ref().map(|path| {
+        trace::Tracer::create(options.trace_format, path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        })
+    });
+
+    let script = options.script.as_ref().map(|path| {
+        script::Script::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        })
+    });
 
-    let (window_closed_sender, window_closed_receiver) = channel();
-    let (key_event_sender, key_event_receiver) = channel();
+    let (control_sender, control_receiver) = channel();
+    let mut processor = cpu::CPU::new(control_receiver);
+    processor.set_platform(platform);
+    let mut quirks = settings.quirks;
+    for (name, enabled) in &options.quirks {
+        quirks
+            .set(name, *enabled)
+            .expect("quirk names are checked when parsing the options");
+    }
+    processor.set_quirks(quirks);
+    processor.set_tick_rate(tick_rate);
+    processor.set_key_bindings(settings.key_bindings);
+    if let Some(depth) = options.stack_depth {
+        processor.set_stack_depth(depth);
+    }
+    processor.set_recompiler(options.recompile);
+    processor.set_idle_loop_skipping(!options.no_idle_skip);
+    if let Some(path) = &options.vip_interpreter {
+        let result = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|image| processor.set_vip_interpreter(&image));
+        if let Err(e) = result {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    let seed = options.seed.unwrap_or_else(rand::random);
+    processor.set_random(options.random, seed);
+    if let Some(tracer) = tracer {
+        processor.set_tracer(tracer);
+    }
+    processor.set_profiling(options.profile.is_some());
+    processor.set_coverage(options.coverage.is_some());
+    if let Some(script) = script {
+        processor.set_script(script);
+    }
+    if let Some(cheats) = cheats {
+        info!("Loaded {} cheats", cheats.len());
+        processor.set_cheats(cheats);
+    }
+    processor
+        .load_game_data(&game_data)
+        .expect("ROM was validated before starting the processor");
+
+    let reports = Reports {
+        profile: options.profile.clone(),
+        profile_format: options.profile_format,
+        annotate: options.annotate,
+        coverage: options.coverage.clone(),
+        coverage_format: options.coverage_format,
+    };
+    if let Some(movie) = movie {
+        play(processor, &movie, &reports);
+        return;
+    }
+    if let Some(frames) = options.frames {
+        run_headless(processor, frames, &reports);
+        return;
+    }
+    if options.record.is_some() {
+        let mut movie = movie::Movie::new(rom_sha1, platform, tick_rate, seed);
+        movie.random = options.random;
+        processor.record_movie(movie);
+    }
+
+    let memory_view_1 = Arc::new(RwLock::new(memory_view::MemoryView::default()));
+    let memory_view_2 = memory_view_1.clone();
+    let (poke_sender, poke_receiver) = channel();
+    processor.set_memory_view(memory_view_2, poke_receiver);
+    let (frame_sender, frame_receiver) = sync_channel(1);
+    processor.set_frame_sender(frame_sender);
+
+    if options.rom != "-" {
+        processor.set_rom_path(options.rom.clone().into());
+    }
+    if options.watch {
+        control::watch_rom(options.rom.clone().into(), control_sender.clone());
+    }
+
+    if options.debugger {
+        let command_sender = control_sender.clone();
+        thread::Builder::new()
+            .name("debugger".to_string())
+            .spawn(move || read_commands(command_sender))
+            .expect("failed to spawn debugger thread");
+    }
 
     let window_thread = thread::Builder::new()
         .name("window".to_string())
         .spawn(move || {
             let mut window =
-                window::WindowHandler::new(frame_buffer_1, window_closed_sender, key_event_sender);
+                window::WindowHandler::new(title, palette, frame_receiver, control_sender);
+            window.set_memory_view(memory_view_1, poke_sender);
             window.run();
         })
         .expect("failed to spawn window thread");
 
-    let delay_timer = Arc::new(Mutex::new(0));
-
-    let mut timers = timers::Timers::new(delay_timer.clone());
-    timers.start();
-
+    let record = options.record;
+    let gdb_port = options.gdb;
     let processor_thread = thread::Builder::new()
         .name("processor".to_string())
         .spawn(move || {
-            let mut processor = cpu::CPU::new(
-                delay_timer,
-                frame_buffer_2,
-                window_closed_receiver,
-                key_event_receiver,
-            );
-            processor.load_game_data(&filename).unwrap();
+            if let Some(port) = gdb_port {
+                if !debug_with_gdb(&mut processor, port) {
+                    return;
+                }
+            }
             processor.run();
+            reports.write(&processor);
+            if let (Some(path), Some(movie)) = (record, processor.finish_movie()) {
+                if let Err(e) = movie.save(&path) {
+                    eprintln!("{}: {}", path, e);
+                }
+            }
         })
         .expect("failed to spawn processor thread");
 
-    window_thread.join().unwrap();
-    processor_thread.join().unwrap();
-    timers.stop();
+    // Neither thread outlives the other, even if it panics: the window closes
+    // once the processor stops sending frames, and the processor quits once
+    // the window is gone.
+    let window_result = window_thread.join();
+    let processor_result = processor_thread.join();
+    if window_result.is_err() || processor_result.is_err() {
+        process::exit(1);
+    }
+}
+
+// Hands |processor| over to a debugger connecting on |port|. Returns whether
+// the program should carry on running without it.
+fn debug_with_gdb(processor: &mut cpu::CPU, port: u16) -> bool {
+    let end = match gdb::accept(port, &mut || processor.quit_requested()) {
+        Ok(Some((incoming, mut stream))) => processor.serve_gdb(&incoming, &mut stream),
+        Ok(None) => Ok(cpu::SessionEnd::Exited),
+        Err(e) => Err(e),
+    };
+    match end {
+        Ok(cpu::SessionEnd::Detached) => true,
+        Ok(cpu::SessionEnd::Killed) => process::exit(0),
+        Ok(_) => false,
+        Err(e) => {
+            eprintln!("gdb: {}", e);
+            false
+        }
+    }
+}
+
+// Reads debugger commands from stdin until it's closed or the CPU stops.
+fn read_commands(sender: Sender<control::Control>) {
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match control::Control::parse(&line) {
+            Ok(command) => {
+                if sender.send(command).is_err() {
+                    return;
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+// Replays |movie| without a window, and checks that it ends on the same frame
+// as when it was recorded.
+fn play(mut processor: cpu::CPU, movie: &movie::Movie, reports: &Reports) {
+    let result = processor.play_movie(movie);
+    reports.write(&processor);
+    exit_on_script_failure(&processor, result);
+    let frame_sha1 = processor.frame_sha1();
+    match &movie.final_frame_sha1 {
+        Some(expected) if *expected != frame_sha1 && !processor.script_exited() => {
+            eprintln!(
+                "Replay diverged: final frame is {}, recorded {}",
+                frame_sha1, expected
+            );
+            process::exit(1);
+        }
+        _ => println!(
+            "Replayed {} frames, final frame {}",
+            movie.frames.len(),
+            frame_sha1
+        ),
+    }
+}
+
+// Runs |frames| frames without a window, with input only from the script.
+fn run_headless(mut processor: cpu::CPU, frames: u64, reports: &Reports) {
+    let result = processor.run_headless(frames);
+    reports.write(&processor);
+    exit_on_script_failure(&processor, result);
+    println!("Ran to final frame {}", processor.frame_sha1());
+}
+
+// The reports to write when the program ends.
+struct Reports {
+    profile: Option<String>,
+    profile_format: profile::ProfileFormat,
+    annotate: bool,
+    coverage: Option<String>,
+    coverage_format: coverage::CoverageFormat,
+}
+
+impl Reports {
+    fn write(&self, processor: &cpu::CPU) {
+        if let (Some(path), Some(report)) = (&self.profile, processor.profile_report(self.annotate))
+        {
+            if let Err(e) = report.write(self.profile_format, path) {
+                eprintln!("{}: {}", path, e);
+            }
+        }
+        if let (Some(path), Some(coverage)) = (&self.coverage, processor.coverage()) {
+            if let Err(e) = coverage.save(self.coverage_format, path) {
+                eprintln!("{}: {}", path, e);
+            }
+        }
+    }
+}
+
+// Reports a fault from a headless run. Failed script assertions fail the run,
+// while other faults are part of the program's behavior.
+fn exit_on_script_failure(processor: &cpu::CPU, result: Result<(), cpu::Fault>) {
+    if let Err(fault) = result {
+        eprintln!("Halting: {}\n{}", fault, processor.debug_view());
+        if let cpu::Fault::Script { .. } = fault {
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,210 @@
+#[cfg(test)]
+mod tests;
+
+use crate::digits::DIGITS;
+use piston_window::Key;
+
+pub const BYTES_PER_ROW: usize = 16;
+pub const ROWS_PER_PAGE: usize = 16;
+const PAGE_SIZE: usize = BYTES_PER_ROW * ROWS_PER_PAGE;
+// The built-in digit sprites occupy the start of memory.
+pub const FONT_END: usize = 0x050;
+// Bytes highlighted from I onwards, enough for a full sprite or all registers.
+const I_REGION_BYTES: usize = 16;
+// How long writes stay highlighted.
+const RECENT_WRITE_FRAMES: u64 = 60;
+pub const MAX_SPRITE_ROWS: usize = 15;
+
+/// What the CPU publishes for the memory viewer once per frame, while it's
+/// shown.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryView {
+    // Set by the window while the viewer is shown, since copying all of
+    // memory every frame is wasted otherwise
+    pub shown: bool,
+    pub memory: Vec<u8>,
+    pub pc: usize,
+    pub i: usize,
+    pub paused: bool,
+    // Number of frames run so far
+    pub frame: u64,
+    // One more than the frame each address was last written in, or 0 if it
+    // hasn't been written since the view was enabled
+    pub last_write: Vec<u64>,
+}
+
+/// Why a byte is highlighted, in order of precedence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    Pc,
+    RecentWrite,
+    IRegion,
+    Font,
+    None,
+}
+
+impl MemoryView {
+    /// How many hex digits it takes to show any address in memory.
+    pub fn address_digits(&self) -> usize {
+        let mut digits = 1;
+        while self.memory.len() > 1 << (4 * digits) {
+            digits += 1;
+        }
+        digits.max(3)
+    }
+
+    pub fn highlight(&self, addr: usize) -> Highlight {
+        if addr == self.pc || addr == self.pc + 1 {
+            Highlight::Pc
+        } else if self.last_write.get(addr).is_some_and(|written| {
+            *written > 0 && (self.frame + 1).saturating_sub(*written) < RECENT_WRITE_FRAMES
+        }) {
+            Highlight::RecentWrite
+        } else if (self.i..self.i + I_REGION_BYTES).contains(&addr) {
+            Highlight::IRegion
+        } else if addr < FONT_END {
+            Highlight::Font
+        } else {
+            Highlight::None
+        }
+    }
+}
+
+/// A byte to write into memory, from the memory editor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Poke {
+    pub addr: usize,
+    pub value: u8,
+}
+
+/// The memory viewer's cursor and input state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryEditor {
+    pub cursor: usize,
+    // How many bytes from the cursor are decoded as a sprite
+    pub sprite_rows: usize,
+    // The first hex digit typed for the byte at the cursor
+    pub high_nibble: Option<u8>,
+}
+
+impl Default for MemoryEditor {
+    fn default() -> MemoryEditor {
+        MemoryEditor {
+            cursor: 0,
+            sprite_rows: 8,
+            high_nibble: None,
+        }
+    }
+}
+
+impl MemoryEditor {
+    /// The address of the first byte on the page the cursor is on.
+    pub fn page_start(&self) -> usize {
+        self.cursor - self.cursor % PAGE_SIZE
+    }
+
+    /// Moves the cursor or edits memory in response to |key|. Returns the byte
+    /// to write once two hex digits have been typed. Memory can only be
+    /// edited while the emulation is paused.
+    pub fn handle_key(&mut self, key: Key, view: &MemoryView) -> Option<Poke> {
+        let size = view.memory.len().max(1);
+        let offset = match key {
+            Key::Left => Some(size - 1),
+            Key::Right => Some(1),
+            Key::Up => Some(size - BYTES_PER_ROW % size),
+            Key::Down => Some(BYTES_PER_ROW),
+            Key::PageUp => Some(size - PAGE_SIZE % size),
+            Key::PageDown => Some(PAGE_SIZE),
+            _ => None,
+        };
+        if let Some(offset) = offset {
+            self.cursor = (self.cursor + offset) % size;
+            self.high_nibble = None;
+            return None;
+        }
+        match key {
+            Key::Equals | Key::Plus | Key::NumPadPlus => {
+                self.sprite_rows = (self.sprite_rows + 1).min(MAX_SPRITE_ROWS);
+                None
+            }
+            Key::Minus | Key::NumPadMinus => {
+                self.sprite_rows = self.sprite_rows.saturating_sub(1).max(1);
+                None
+            }
+            Key::Backspace => {
+                self.high_nibble = None;
+                None
+            }
+            _ if view.paused => {
+                let digit = hex_digit(key)?;
+                match self.high_nibble.take() {
+                    None => {
+                        self.high_nibble = Some(digit);
+                        None
+                    }
+                    Some(high) => {
+                        let poke = Poke {
+                            addr: self.cursor,
+                            value: high << 4 | digit,
+                        };
+                        self.cursor = (self.cursor + 1) % size;
+                        Some(poke)
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The bytes from the cursor decoded as sprite rows, most significant bit
+    /// first.
+    pub fn sprite(&self, view: &MemoryView) -> Vec<[bool; 8]> {
+        (self.cursor..self.cursor + self.sprite_rows)
+            .filter_map(|addr| view.memory.get(addr))
+            .map(|byte| sprite_row(*byte))
+            .collect()
+    }
+}
+
+pub fn sprite_row(byte: u8) -> [bool; 8] {
+    let mut row = [false; 8];
+    for (bit, pixel) in row.iter_mut().enumerate() {
+        *pixel = byte & (0x80 >> bit) != 0;
+    }
+    row
+}
+
+/// The 4x5 glyph of hex digit |nibble|, taken from the built-in font.
+pub fn glyph(nibble: u8) -> [[bool; 4]; 5] {
+    let mut glyph = [[false; 4]; 5];
+    for (row, pixels) in glyph.iter_mut().enumerate() {
+        let byte = DIGITS[usize::from(nibble & 0xF) * 5 + row];
+        for (bit, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = byte & (0x80 >> bit) != 0;
+        }
+    }
+    glyph
+}
+
+fn hex_digit(key: Key) -> Option<u8> {
+    let digit = match key {
+        Key::D0 | Key::NumPad0 => 0x0,
+        Key::D1 | Key::NumPad1 => 0x1,
+        Key::D2 | Key::NumPad2 => 0x2,
+        Key::D3 | Key::NumPad3 => 0x3,
+        Key::D4 | Key::NumPad4 => 0x4,
+        Key::D5 | Key::NumPad5 => 0x5,
+        Key::D6 | Key::NumPad6 => 0x6,
+        Key::D7 | Key::NumPad7 => 0x7,
+        Key::D8 | Key::NumPad8 => 0x8,
+        Key::D9 | Key::NumPad9 => 0x9,
+        Key::A => 0xA,
+        Key::B => 0xB,
+        Key::C => 0xC,
+        Key::D => 0xD,
+        Key::E => 0xE,
+        Key::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
@@ -0,0 +1,195 @@
+use crate::control::Control;
+use crate::cpu::CPU;
+use crate::memory_view::*;
+use piston_window::Key;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn create_view() -> MemoryView {
+    MemoryView {
+        shown: true,
+        memory: vec![0; 0x1000],
+        pc: 0x200,
+        i: 0x300,
+        paused: true,
+        frame: 100,
+        last_write: vec![0; 0x1000],
+    }
+}
+
+#[test]
+fn address_digits() {
+    let mut view = create_view();
+    assert_eq!(3, view.address_digits());
+    view.memory = vec![0; 0x10000];
+    assert_eq!(4, view.address_digits());
+    view.memory = vec![0; 0x10001];
+    assert_eq!(5, view.address_digits());
+    view.memory.clear();
+    assert_eq!(3, view.address_digits());
+}
+
+#[test]
+fn highlight() {
+    let mut view = create_view();
+    view.last_write[0x400] = 100;
+    view.last_write[0x401] = 20;
+    view.last_write[0x201] = 101;
+    assert_eq!(Highlight::Pc, view.highlight(0x200));
+    assert_eq!(Highlight::Pc, view.highlight(0x201));
+    assert_eq!(Highlight::IRegion, view.highlight(0x300));
+    assert_eq!(Highlight::IRegion, view.highlight(0x30F));
+    assert_eq!(Highlight::None, view.highlight(0x310));
+    assert_eq!(Highlight::Font, view.highlight(0x000));
+    assert_eq!(Highlight::Font, view.highlight(0x04F));
+    assert_eq!(Highlight::None, view.highlight(0x050));
+    assert_eq!(Highlight::RecentWrite, view.highlight(0x400));
+    // Old writes are no longer highlighted.
+    assert_eq!(Highlight::None, view.highlight(0x401));
+}
+
+#[test]
+fn editor_moves_cursor() {
+    let view = create_view();
+    let mut editor = MemoryEditor::default();
+    editor.handle_key(Key::Left, &view);
+    assert_eq!(0xFFF, editor.cursor);
+    assert_eq!(0xF00, editor.page_start());
+    editor.handle_key(Key::Right, &view);
+    editor.handle_key(Key::Down, &view);
+    assert_eq!(0x010, editor.cursor);
+    editor.handle_key(Key::PageDown, &view);
+    assert_eq!(0x110, editor.cursor);
+    assert_eq!(0x100, editor.page_start());
+    editor.handle_key(Key::Up, &view);
+    editor.handle_key(Key::PageUp, &view);
+    assert_eq!(0x000, editor.cursor);
+}
+
+#[test]
+fn editor_pokes_while_paused() {
+    let mut view = create_view();
+    let mut editor = MemoryEditor {
+        cursor: 0x300,
+        ..MemoryEditor::default()
+    };
+    assert_eq!(None, editor.handle_key(Key::A, &view));
+    assert_eq!(Some(0xA), editor.high_nibble);
+    assert_eq!(
+        Some(Poke {
+            addr: 0x300,
+            value: 0xA7
+        }),
+        editor.handle_key(Key::D7, &view)
+    );
+    assert_eq!(0x301, editor.cursor);
+
+    // Backspace discards the first digit.
+    editor.handle_key(Key::D1, &view);
+    editor.handle_key(Key::Backspace, &view);
+    assert_eq!(None, editor.high_nibble);
+
+    view.paused = false;
+    assert_eq!(None, editor.handle_key(Key::F, &view));
+    assert_eq!(None, editor.handle_key(Key::F, &view));
+    assert_eq!(None, editor.high_nibble);
+}
+
+#[test]
+fn editor_decodes_sprite() {
+    let mut view = create_view();
+    view.memory[0xFFE] = 0x81;
+    view.memory[0xFFF] = 0x3C;
+    let mut editor = MemoryEditor {
+        cursor: 0xFFE,
+        ..MemoryEditor::default()
+    };
+    assert_eq!(
+        vec![
+            [true, false, false, false, false, false, false, true],
+            [false, false, true, true, true, true, false, false],
+        ],
+        editor.sprite(&view)
+    );
+
+    editor.handle_key(Key::Minus, &view);
+    assert_eq!(7, editor.sprite_rows);
+    for _ in 0..20 {
+        editor.handle_key(Key::Equals, &view);
+    }
+    assert_eq!(MAX_SPRITE_ROWS, editor.sprite_rows);
+}
+
+#[test]
+fn glyphs() {
+    assert_eq!(
+        [
+            [true, true, true, true],
+            [true, false, false, true],
+            [true, true, true, true],
+            [true, false, false, true],
+            [true, false, false, true],
+        ],
+        glyph(0xA)
+    );
+}
+
+// Waits until the CPU running on another thread publishes a view that
+// satisfies |done|, and returns a copy of it.
+fn wait_for_view(view: &RwLock<MemoryView>, done: impl Fn(&MemoryView) -> bool) -> MemoryView {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let current = view.read().unwrap().clone();
+        if done(&current) {
+            return current;
+        }
+        assert!(Instant::now() < deadline, "the memory view wasn't updated");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn published_by_cpu() {
+    let (controls, receiver) = channel();
+    let mut cpu = CPU::new(receiver);
+    let view = Arc::new(RwLock::new(MemoryView::default()));
+    let (poke_sender, poke_receiver) = channel();
+    cpu.set_memory_view(view.clone(), poke_receiver);
+    // Stores V0 at 0x300 every frame.
+    let program = [0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00];
+    cpu.load_game_data(&program).unwrap();
+    cpu.set_tick_rate(3);
+    let running = thread::spawn(move || cpu.run());
+
+    // Nothing is published until the viewer is shown.
+    thread::sleep(Duration::from_millis(50));
+    assert!(view.read().unwrap().memory.is_empty());
+    view.write().unwrap().shown = true;
+    let published = wait_for_view(&view, |view| view.frame > 0);
+    assert_eq!(program, published.memory[0x200..0x206]);
+    assert_eq!(0x200, published.pc);
+    assert_eq!(0x301, published.i);
+    assert!(!published.paused);
+    assert_ne!(0, published.last_write[0x300]);
+    assert_eq!(0, published.last_write[0x301]);
+
+    // Bytes are only poked while paused.
+    poke_sender
+        .send(Poke {
+            addr: 0x202,
+            value: 0x12,
+        })
+        .unwrap();
+    let running_frame = published.frame;
+    let published = wait_for_view(&view, |view| view.frame > running_frame + 1);
+    assert_eq!(0xF0, published.memory[0x202]);
+    controls.send(Control::Pause(true)).unwrap();
+    let published = wait_for_view(&view, |view| view.paused);
+    assert_eq!(0x12, published.memory[0x202]);
+    assert_eq!(published.frame + 1, published.last_write[0x202]);
+
+    controls.send(Control::Quit).unwrap();
+    running.join().unwrap();
+}
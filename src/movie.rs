@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests;
+
+use crate::cpu::{RandomRoutine, DEFAULT_STACK_DEPTH};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(e) => write!(f, "unable to access movie: {}", e),
+            MovieError::Parse(e) => write!(f, "unable to parse movie: {}", e),
+        }
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> MovieError {
+        MovieError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for MovieError {
+    fn from(e: serde_json::Error) -> MovieError {
+        MovieError::Parse(e)
+    }
+}
+
+/// A recording of the keypad state in every frame of a run, along with
+/// everything else needed to replay it exactly.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Movie {
+    // SHA-1 of the ROM the movie was recorded with
+    pub rom_sha1: String,
+    pub platform: Platform,
+    pub tick_rate: u32,
+    // Seed of the random number generator
+    pub seed: u64,
+    pub random: RandomRoutine,
+    // Quirks and stack depth the program ran with, including any overrides
+    // from the database or the command line
+    pub quirks: Quirks,
+    pub stack_depth: usize,
+    // Keypad state at the start of each frame, one bit per key
+    pub frames: Vec<u16>,
+    // SHA-1 of the frame buffer at the end of the recording
+    pub final_frame_sha1: Option<String>,
+}
+
+impl Movie {
+    pub fn new(rom_sha1: String, platform: Platform, tick_rate: u32, seed: u64) -> Movie {
+        Movie {
+            rom_sha1,
+            platform,
+            tick_rate,
+            seed,
+            random: RandomRoutine::default(),
+            quirks: Quirks::default(),
+            stack_depth: DEFAULT_STACK_DEPTH,
+            frames: Vec::new(),
+            final_frame_sha1: None,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+/// Packs |keys| into a bit mask, with key 0 in the lowest bit.
+pub fn encode_keys(keys: &[bool; 16]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .filter(|(_, pressed)| **pressed)
+        .fold(0, |mask, (key, _)| mask | 1 << key)
+}
+
+/// Unpacks a bit mask created by |encode_keys|.
+pub fn decode_keys(mask: u16) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (key, pressed) in keys.iter_mut().enumerate() {
+        *pressed = mask & 1 << key != 0;
+    }
+    keys
+}
@@ -0,0 +1,96 @@
+use crate::cpu::{Fault, CPU};
+use crate::movie::*;
+use std::sync::mpsc::channel;
+
+#[test]
+fn encode_decode_keys() {
+    let mut keys = [false; 16];
+    keys[0] = true;
+    keys[5] = true;
+    keys[15] = true;
+    assert_eq!(0x8021, encode_keys(&keys));
+    assert_eq!(keys, decode_keys(0x8021));
+    assert_eq!([false; 16], decode_keys(0));
+}
+
+#[test]
+fn save_and_load() {
+    let mut movie = Movie::new(String::from("abc"), Platform::SuperChip, 30, 42);
+    movie.frames = vec![0, 1, 0x8000];
+    movie.final_frame_sha1 = Some(String::from("def"));
+    let path = std::env::temp_dir().join(format!("chip8-movie-{}.json", std::process::id()));
+    movie.save(&path).unwrap();
+    let loaded = Movie::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(movie, loaded.unwrap());
+}
+
+#[test]
+fn load_invalid() {
+    let path = std::env::temp_dir().join(format!("chip8-bad-movie-{}.json", std::process::id()));
+    std::fs::write(&path, "{").unwrap();
+    let loaded = Movie::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    match loaded {
+        Err(MovieError::Parse(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn load_requires_settings() {
+    let path = std::env::temp_dir().join(format!(
+        "chip8-incomplete-movie-{}.json",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        r#"{"rom_sha1":"abc","platform":"chip8","tick_rate":11,"seed":1,"frames":[],"final_frame_sha1":null}"#,
+    )
+    .unwrap();
+    let loaded = Movie::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    // Replaying without the random routine, quirks and stack depth could
+    // silently go wrong.
+    match loaded {
+        Err(MovieError::Parse(_)) => {}
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn replays_with_recorded_settings() {
+    let quirks = Quirks {
+        vblank: true,
+        wrap: false,
+        ..Quirks::default()
+    };
+    let mut cpu = CPU::new(channel().1);
+    cpu.set_quirks(quirks);
+    cpu.set_stack_depth(2);
+    cpu.record_movie(Movie::new(String::new(), Platform::Chip8, 7, 1));
+    let movie = cpu.finish_movie().unwrap();
+    assert_eq!(quirks, movie.quirks);
+    assert_eq!(2, movie.stack_depth);
+
+    // Calls itself until the stack overflows, which takes three calls with
+    // the recorded depth but more with the default.
+    let mut cpu = CPU::new(channel().1);
+    cpu.load_game_data(&[0x22, 0x00]).unwrap();
+    let replay = Movie {
+        frames: vec![0],
+        ..movie
+    };
+    match cpu.play_movie(&replay) {
+        Err(Fault::StackOverflow { call_chain, .. }) => assert_eq!(2, call_chain.len()),
+        other => panic!("expected a stack overflow, got {:?}", other),
+    }
+    cpu.record_movie(Movie::new(String::new(), Platform::Chip8, 7, 1));
+    assert_eq!(quirks, cpu.finish_movie().unwrap().quirks);
+
+    // Spins in place, running as many instructions as the recorded tick rate.
+    let mut cpu = CPU::new(channel().1);
+    cpu.load_game_data(&[0x12, 0x00]).unwrap();
+    cpu.play_movie(&replay).unwrap();
+    assert_eq!(7, cpu.snapshot().cycles());
+}
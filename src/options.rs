@@ -0,0 +1,229 @@
+use chip8::coverage::CoverageFormat;
+use chip8::cpu::{RandomRoutine, MAX_STACK_DEPTH};
+use chip8::platform::Platform;
+use chip8::profile::ProfileFormat;
+use chip8::quirks::Quirks;
+use chip8::trace::TraceFormat;
+
+const DEFAULT_ROM: &str = "BRIX";
+
+pub const USAGE: &str = "usage: chip8 [--platform chip8|schip|xochip] [--database FILE]
+             [--trace FILE|-] [--trace-format jsonl|csv] [--stack-depth N]
+             [--quirk NAME[=true|false]]...
+             [--recompile] [--record MOVIE | --play MOVIE] [--seed N]
+             [--random xorshift|vip] [--vip-interpreter FILE]
+             [--cheats FILE] [--debugger]
+             [--script FILE] [--frames N] [--gdb PORT]
+             [--profile FILE|-] [--profile-format text|json] [--annotate]
+             [--coverage FILE] [--coverage-format png|json] [--no-idle-skip]
+             [--watch] [ROM | -]";
+
+/// Command line options. The ROM may be a path to a raw, gzipped or zipped
+/// program, or "-" to read it from stdin.
+#[derive(Debug)]
+pub struct Options {
+    pub rom: String,
+    pub platform: Option<Platform>,
+    // Additional ROM database, in the community CHIP-8 database format
+    pub database: Option<String>,
+    // Where to write the execution trace, if anywhere
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+    // Maximum number of nested subroutine calls
+    pub stack_depth: Option<usize>,
+    // Quirks turned on or off over those from the database, by name
+    pub quirks: Vec<(String, bool)>,
+    // Whether to translate blocks of instructions instead of interpreting them
+    pub recompile: bool,
+    // Where to record the keypad input to, if anywhere
+    pub record: Option<String>,
+    // Movie to replay without a window, instead of playing interactively
+    pub play: Option<String>,
+    // Seed for the random number generator, instead of a random one
+    pub seed: Option<u64>,
+    pub random: RandomRoutine,
+    // Dump of the COSMAC VIP's interpreter, needed by the VIP random routine
+    pub vip_interpreter: Option<String>,
+    // Cheat lists keyed by ROM hash
+    pub cheats: Option<String>,
+    // Whether to read debugger commands from stdin
+    pub debugger: bool,
+    // Rhai script to run alongside the program
+    pub script: Option<String>,
+    // Number of frames to run without a window, instead of playing
+    // interactively
+    pub frames: Option<u64>,
+    // Port to wait for a GDB connection on before running
+    pub gdb: Option<u16>,
+    // Where to write the instruction counts on exit, if anywhere
+    pub profile: Option<String>,
+    pub profile_format: ProfileFormat,
+    // Whether the profile includes a disassembly of the program
+    pub annotate: bool,
+    // Where to write the addresses used as code and data on exit, if anywhere
+    pub coverage: Option<String>,
+    pub coverage_format: CoverageFormat,
+    // Whether to interpret every iteration of loops waiting on the delay timer
+    pub no_idle_skip: bool,
+    // Whether to reload the ROM whenever its file changes
+    pub watch: bool,
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut rom = None;
+        let mut platform = None;
+        let mut database = None;
+        let mut trace = None;
+        let mut trace_format = TraceFormat::JsonLines;
+        let mut stack_depth = None;
+        let mut quirks = Vec::new();
+        let mut recompile = false;
+        let mut record = None;
+        let mut play = None;
+        let mut seed = None;
+        let mut random = RandomRoutine::default();
+        let mut vip_interpreter = None;
+        let mut cheats = None;
+        let mut debugger = false;
+        let mut script = None;
+        let mut frames = None;
+        let mut gdb = None;
+        let mut profile = None;
+        let mut profile_format = ProfileFormat::Text;
+        let mut annotate = false;
+        let mut coverage = None;
+        let mut coverage_format = CoverageFormat::Png;
+        let mut no_idle_skip = false;
+        let mut watch = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--platform" => platform = Some(next_value(&mut args, &arg)?.parse()?),
+                "--database" => database = Some(next_value(&mut args, &arg)?),
+                "--trace" => trace = Some(next_value(&mut args, &arg)?),
+                "--trace-format" => trace_format = next_value(&mut args, &arg)?.parse()?,
+                "--stack-depth" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(depth) if (1..=MAX_STACK_DEPTH).contains(&depth) => {
+                            stack_depth = Some(depth)
+                        }
+                        _ => {
+                            return Err(format!(
+                                "invalid stack depth '{}', must be from 1 to {}",
+                                value, MAX_STACK_DEPTH
+                            ))
+                        }
+                    }
+                }
+                "--quirk" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (name, enabled) = match value.split_once('=') {
+                        Some((name, "true")) => (name, true),
+                        Some((name, "false")) => (name, false),
+                        Some(_) => return Err(format!("invalid quirk '{}'", value)),
+                        None => (value.as_str(), true),
+                    };
+                    // Checks the name.
+                    Quirks::default().set(name, enabled)?;
+                    quirks.push((name.to_string(), enabled));
+                }
+                "--recompile" => recompile = true,
+                "--record" => record = Some(next_value(&mut args, &arg)?),
+                "--play" => play = Some(next_value(&mut args, &arg)?),
+                "--seed" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(value) => seed = Some(value),
+                        _ => return Err(format!("invalid seed '{}'", value)),
+                    }
+                }
+                "--random" => random = next_value(&mut args, &arg)?.parse()?,
+                "--vip-interpreter" => vip_interpreter = Some(next_value(&mut args, &arg)?),
+                "--cheats" => cheats = Some(next_value(&mut args, &arg)?),
+                "--debugger" => debugger = true,
+                "--script" => script = Some(next_value(&mut args, &arg)?),
+                "--frames" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(value) => frames = Some(value),
+                        _ => return Err(format!("invalid number of frames '{}'", value)),
+                    }
+                }
+                "--gdb" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(value) => gdb = Some(value),
+                        _ => return Err(format!("invalid port '{}'", value)),
+                    }
+                }
+                "--profile" => profile = Some(next_value(&mut args, &arg)?),
+                "--profile-format" => profile_format = next_value(&mut args, &arg)?.parse()?,
+                "--annotate" => annotate = true,
+                "--coverage" => coverage = Some(next_value(&mut args, &arg)?),
+                "--coverage-format" => coverage_format = next_value(&mut args, &arg)?.parse()?,
+                "--no-idle-skip" => no_idle_skip = true,
+                "--watch" => watch = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ if rom.is_none() => rom = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+        if random == RandomRoutine::Vip && vip_interpreter.is_none() {
+            return Err(String::from("--random vip needs --vip-interpreter"));
+        }
+        if record.is_some() && play.is_some() {
+            return Err(String::from("--record and --play can't be used together"));
+        }
+        if frames.is_some() && (record.is_some() || play.is_some()) {
+            return Err(String::from(
+                "--frames can't be used with --record or --play",
+            ));
+        }
+        if gdb.is_some() && (record.is_some() || play.is_some() || frames.is_some()) {
+            return Err(String::from(
+                "--gdb can't be used with --record, --play or --frames",
+            ));
+        }
+        if watch && (record.is_some() || play.is_some() || frames.is_some()) {
+            return Err(String::from(
+                "--watch can't be used with --record, --play or --frames",
+            ));
+        }
+        if watch && rom.as_deref() == Some("-") {
+            return Err(String::from("--watch needs a ROM file, not stdin"));
+        }
+        Ok(Options {
+            rom: rom.unwrap_or_else(|| String::from(DEFAULT_ROM)),
+            platform,
+            database,
+            trace,
+            trace_format,
+            stack_depth,
+            quirks,
+            recompile,
+            record,
+            play,
+            seed,
+            random,
+            vip_interpreter,
+            cheats,
+            debugger,
+            script,
+            frames,
+            gdb,
+            profile,
+            profile_format,
+            annotate,
+            coverage,
+            coverage_format,
+            no_idle_skip,
+            watch,
+        })
+    }
+}
+
+fn next_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option '{}' requires a value", option))
+}
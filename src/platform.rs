@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// Programs are loaded at, and start executing from, this address.
+pub const PROGRAM_START: usize = 0x200;
+
+/// The CHIP-8 variant being emulated. This determines how much memory is
+/// available, and therefore how large a ROM can be.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Platform {
+    #[default]
+    #[serde(rename = "chip8")]
+    Chip8,
+    #[serde(rename = "schip")]
+    SuperChip,
+    #[serde(rename = "xochip")]
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// The largest ROM that fits in memory after the program start address.
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - PROGRAM_START
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        match s.to_lowercase().as_str() {
+            // Besides the short names, accept the platform ids used by the
+            // community CHIP-8 database.
+            "chip8" | "chip-8" | "originalchip8" | "hybridvip" | "modernchip8" | "chip48" => {
+                Ok(Platform::Chip8)
+            }
+            "schip" | "superchip" | "superchip1" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::SuperChip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip"),
+        }
+    }
+}
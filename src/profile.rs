@@ -0,0 +1,314 @@
+#[cfg(test)]
+mod tests;
+
+use crate::cpu::disassemble;
+use crate::platform::PROGRAM_START;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::str::FromStr;
+
+// A jump back over at most this many bytes closes a tight loop.
+const TIGHT_LOOP_BYTES: usize = 16;
+// Number of hottest addresses listed in the text report.
+const TEXT_REPORT_ADDRESSES: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ProfileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ProfileFormat, String> {
+        match s {
+            "text" => Ok(ProfileFormat::Text),
+            "json" => Ok(ProfileFormat::Json),
+            _ => Err(format!("unknown profile format '{}'", s)),
+        }
+    }
+}
+
+/// Counts how often each instruction runs, to find where a program spends its
+/// time.
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    // Executions of the instruction at each address
+    counts: Vec<u64>,
+    // The kind of instruction last run from each address
+    kinds: Vec<Option<&'static str>>,
+    // Executions of each kind of instruction, which self-modifying code can
+    // change at the same address
+    kind_counts: HashMap<&'static str, u64>,
+    // Number of times each jump was taken back to an address at most
+    // TIGHT_LOOP_BYTES before it, by start and end of the loop
+    back_jumps: HashMap<(usize, usize), u64>,
+    // The previous instruction, if it was a jump
+    last_jump: Option<usize>,
+    instructions: u64,
+}
+
+impl Profiler {
+    pub fn new(memory_size: usize) -> Profiler {
+        Profiler {
+            counts: vec![0; memory_size],
+            kinds: vec![None; memory_size],
+            kind_counts: HashMap::new(),
+            back_jumps: HashMap::new(),
+            last_jump: None,
+            instructions: 0,
+        }
+    }
+
+    /// Counts an instruction of |kind| about to run from |pc|.
+    pub fn record(&mut self, pc: usize, kind: &'static str) {
+        if let Some(jump) = self.last_jump.take() {
+            if pc <= jump && jump - pc < TIGHT_LOOP_BYTES {
+                *self.back_jumps.entry((pc, jump)).or_insert(0) += 1;
+            }
+        }
+        if let Some(count) = self.counts.get_mut(pc) {
+            *count += 1;
+            self.kinds[pc] = Some(kind);
+        }
+        *self.kind_counts.entry(kind).or_insert(0) += 1;
+        if kind == "Jump" {
+            self.last_jump = Some(pc);
+        }
+        self.instructions += 1;
+    }
+
+    /// Summarizes the counts, using |memory| to disassemble the instructions.
+    /// With |annotate|, the report includes the disassembly of the program up
+    /// to the last instruction run, with the count for each instruction.
+    pub fn report(&self, memory: &[u8], annotate: bool) -> Report {
+        let instruction_at = |addr: usize| {
+            let code = [memory[addr], memory[(addr + 1) % memory.len()]];
+            (u16::from_be_bytes(code), disassemble(&code))
+        };
+
+        let mut addresses: Vec<AddressCount> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(addr, count)| AddressCount {
+                addr,
+                count: *count,
+                instruction: instruction_at(addr).1,
+            })
+            .collect();
+        addresses.sort_by(|a, b| b.count.cmp(&a.count).then(a.addr.cmp(&b.addr)));
+
+        let mut kinds: Vec<KindCount> = self
+            .kind_counts
+            .iter()
+            .map(|(kind, count)| KindCount {
+                kind,
+                count: *count,
+            })
+            .collect();
+        kinds.sort_by(|a, b| b.count.cmp(&a.count).then(a.kind.cmp(b.kind)));
+
+        let mut loops: Vec<Loop> = self
+            .back_jumps
+            .iter()
+            .map(|((start, end), iterations)| {
+                let body = *start..=(*end + 1).min(self.counts.len() - 1);
+                let waits_on = self.kinds[body.clone()].iter().find_map(|kind| match kind {
+                    Some("LdRegDt") => Some("delay timer"),
+                    Some("LdRegKey") | Some("SkipRegKeyPressed") | Some("SkipRegKeyNPressed") => {
+                        Some("keypad")
+                    }
+                    _ => None,
+                });
+                Loop {
+                    start: *start,
+                    end: *end,
+                    iterations: *iterations,
+                    instructions: self.counts[body].iter().sum(),
+                    waits_on,
+                }
+            })
+            .collect();
+        loops.sort_by(|a, b| {
+            b.instructions
+                .cmp(&a.instructions)
+                .then(a.start.cmp(&b.start))
+        });
+
+        let disassembly = if annotate {
+            let end = self
+                .counts
+                .iter()
+                .rposition(|count| *count > 0)
+                .unwrap_or(0)
+                + 2;
+            let mut lines = Vec::new();
+            let mut addr = PROGRAM_START.min(end);
+            while addr < end.min(memory.len()) {
+                let (opcode, instruction) = instruction_at(addr);
+                lines.push(DisassemblyLine {
+                    addr,
+                    opcode,
+                    count: self.counts[addr],
+                    instruction,
+                });
+                // Programs can run code at odd addresses.
+                addr += if self.counts.get(addr + 1).is_some_and(|count| *count > 0) {
+                    1
+                } else {
+                    2
+                };
+            }
+            Some(lines)
+        } else {
+            None
+        };
+
+        Report {
+            instructions: self.instructions,
+            addresses,
+            kinds,
+            loops,
+            disassembly,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AddressCount {
+    pub addr: usize,
+    pub count: u64,
+    pub instruction: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KindCount {
+    pub kind: &'static str,
+    pub count: u64,
+}
+
+/// A jump back to within a few instructions of itself, which was taken
+/// repeatedly.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Loop {
+    pub start: usize,
+    // Address of the jump back to the start
+    pub end: usize,
+    pub iterations: u64,
+    // Executions of the instructions from start to end
+    pub instructions: u64,
+    // What the loop polls, if it looks like it's waiting on something
+    pub waits_on: Option<&'static str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DisassemblyLine {
+    pub addr: usize,
+    pub opcode: u16,
+    pub count: u64,
+    pub instruction: String,
+}
+
+/// Instruction counts sorted from most to least frequent.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub instructions: u64,
+    pub addresses: Vec<AddressCount>,
+    pub kinds: Vec<KindCount>,
+    pub loops: Vec<Loop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disassembly: Option<Vec<DisassemblyLine>>,
+}
+
+impl Report {
+    pub fn format(&self, format: ProfileFormat) -> String {
+        match format {
+            ProfileFormat::Json => {
+                serde_json::to_string(self).expect("unable to serialize profile")
+            }
+            ProfileFormat::Text => self.format_text(),
+        }
+    }
+
+    fn format_text(&self) -> String {
+        let share = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+        let mut text = format!("{} instructions\n", self.instructions);
+
+        text.push_str("\nHottest instructions:\n     count   share  addr  instruction\n");
+        for address in self.addresses.iter().take(TEXT_REPORT_ADDRESSES) {
+            writeln!(
+                text,
+                "{:>10}  {:>5.1}%  {:03X}   {}",
+                address.count,
+                share(address.count),
+                address.addr,
+                address.instruction
+            )
+            .unwrap();
+        }
+
+        text.push_str("\nInstruction kinds:\n     count   share  kind\n");
+        for kind in &self.kinds {
+            writeln!(
+                text,
+                "{:>10}  {:>5.1}%  {}",
+                kind.count,
+                share(kind.count),
+                kind.kind
+            )
+            .unwrap();
+        }
+
+        text.push_str("\nTight loops:\n     count   share  range    iterations  waits on\n");
+        for tight_loop in &self.loops {
+            writeln!(
+                text,
+                "{:>10}  {:>5.1}%  {:03X}-{:03X}  {:>10}  {}",
+                tight_loop.instructions,
+                share(tight_loop.instructions),
+                tight_loop.start,
+                tight_loop.end,
+                tight_loop.iterations,
+                tight_loop.waits_on.unwrap_or("-")
+            )
+            .unwrap();
+        }
+
+        if let Some(disassembly) = &self.disassembly {
+            text.push_str("\nDisassembly:\n     count   share  addr  code  instruction\n");
+            for line in disassembly {
+                if line.count == 0 {
+                    write!(text, "{:>10}  {:>6}", "", "").unwrap();
+                } else {
+                    write!(text, "{:>10}  {:>5.1}%", line.count, share(line.count)).unwrap();
+                }
+                writeln!(
+                    text,
+                    "  {:03X}   {:04X}  {}",
+                    line.addr, line.opcode, line.instruction
+                )
+                .unwrap();
+            }
+        }
+        text
+    }
+
+    /// Writes the report to |path|, where "-" means stdout.
+    pub fn write(&self, format: ProfileFormat, path: &str) -> io::Result<()> {
+        let mut writer: Box<dyn Write> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        writeln!(writer, "{}", self.format(format).trim_end())?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,212 @@
+use crate::cpu::CPU;
+use crate::profile::*;
+use std::sync::mpsc::channel;
+
+// A program that waits for the delay timer to run out, then loops forever.
+fn busy_wait_memory() -> Vec<u8> {
+    let mut memory = vec![0; 4096];
+    memory[0x200..0x20A].copy_from_slice(&[
+        0x60, 0x03, // 0x200: LD V0, 0x03
+        0xF0, 0x07, // 0x202: LD V0, DT
+        0x30, 0x00, // 0x204: SE V0, 0x00
+        0x12, 0x02, // 0x206: JP 0x202
+        0x12, 0x08, // 0x208: JP 0x208
+    ]);
+    memory
+}
+
+// Records the program above running with the timer at 3, 2, 1 then 0.
+fn busy_wait_profile() -> Profiler {
+    let mut profiler = Profiler::new(4096);
+    profiler.record(0x200, "LdRegByte");
+    for _ in 0..3 {
+        profiler.record(0x202, "LdRegDt");
+        profiler.record(0x204, "SkipEqRegBytes");
+        profiler.record(0x206, "Jump");
+    }
+    profiler.record(0x202, "LdRegDt");
+    profiler.record(0x204, "SkipEqRegBytes");
+    for _ in 0..2 {
+        profiler.record(0x208, "Jump");
+    }
+    profiler
+}
+
+#[test]
+fn report_sorts_addresses_by_count() {
+    let report = busy_wait_profile().report(&busy_wait_memory(), false);
+    assert_eq!(14, report.instructions);
+    assert_eq!(
+        vec![
+            (0x202, 4, "LD V0, DT"),
+            (0x204, 4, "SE V0, 0x00"),
+            (0x206, 3, "JP 0x202"),
+            (0x208, 2, "JP 0x208"),
+            (0x200, 1, "LD V0, 0x03"),
+        ],
+        report
+            .addresses
+            .iter()
+            .map(|a| (a.addr, a.count, a.instruction.as_str()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(None, report.disassembly);
+}
+
+#[test]
+fn report_counts_kinds() {
+    let report = busy_wait_profile().report(&busy_wait_memory(), false);
+    assert_eq!(
+        vec![
+            KindCount {
+                kind: "Jump",
+                count: 5
+            },
+            KindCount {
+                kind: "LdRegDt",
+                count: 4
+            },
+            KindCount {
+                kind: "SkipEqRegBytes",
+                count: 4
+            },
+            KindCount {
+                kind: "LdRegByte",
+                count: 1
+            },
+        ],
+        report.kinds
+    );
+}
+
+#[test]
+fn report_counts_kinds_of_rewritten_instructions() {
+    // The instruction at 0x200 is overwritten partway through the run.
+    let mut profiler = Profiler::new(4096);
+    profiler.record(0x200, "LdRegByte");
+    profiler.record(0x200, "LdRegByte");
+    profiler.record(0x200, "AddRegByte");
+    let report = profiler.report(&busy_wait_memory(), false);
+    assert_eq!(
+        vec![
+            KindCount {
+                kind: "LdRegByte",
+                count: 2
+            },
+            KindCount {
+                kind: "AddRegByte",
+                count: 1
+            },
+        ],
+        report.kinds
+    );
+}
+
+#[test]
+fn report_finds_tight_loops() {
+    let report = busy_wait_profile().report(&busy_wait_memory(), false);
+    assert_eq!(
+        vec![
+            Loop {
+                start: 0x202,
+                end: 0x206,
+                iterations: 3,
+                instructions: 11,
+                waits_on: Some("delay timer"),
+            },
+            Loop {
+                start: 0x208,
+                end: 0x208,
+                iterations: 1,
+                instructions: 2,
+                waits_on: None,
+            },
+        ],
+        report.loops
+    );
+}
+
+#[test]
+fn report_ignores_long_jumps_back() {
+    let mut profiler = Profiler::new(4096);
+    for _ in 0..3 {
+        profiler.record(0x200, "LdRegKey");
+        profiler.record(0x240, "Jump");
+    }
+    assert!(profiler.report(&busy_wait_memory(), false).loops.is_empty());
+}
+
+#[test]
+fn report_annotates_disassembly() {
+    let report = busy_wait_profile().report(&busy_wait_memory(), true);
+    assert_eq!(
+        Some(vec![
+            (0x200, 0x6003, 1),
+            (0x202, 0xF007, 4),
+            (0x204, 0x3000, 4),
+            (0x206, 0x1202, 3),
+            (0x208, 0x1208, 2),
+        ]),
+        report.disassembly.map(|lines| lines
+            .iter()
+            .map(|line| (line.addr, line.opcode, line.count))
+            .collect::<Vec<_>>())
+    );
+}
+
+#[test]
+fn format_text() {
+    let text = busy_wait_profile()
+        .report(&busy_wait_memory(), true)
+        .format(ProfileFormat::Text);
+    assert!(text.starts_with("14 instructions\n"));
+    assert!(text.contains("         4   28.6%  202   LD V0, DT\n"));
+    assert!(text.contains("         5   35.7%  Jump\n"));
+    assert!(text.contains("        11   78.6%  202-206           3  delay timer\n"));
+    assert!(text.contains("         1    7.1%  200   6003  LD V0, 0x03\n"));
+}
+
+#[test]
+fn format_json() {
+    let json: serde_json::Value = serde_json::from_str(
+        &busy_wait_profile()
+            .report(&busy_wait_memory(), false)
+            .format(ProfileFormat::Json),
+    )
+    .unwrap();
+    assert_eq!(14, json["instructions"]);
+    assert_eq!(0x202, json["addresses"][0]["addr"]);
+    assert_eq!("delay timer", json["loops"][0]["waits_on"]);
+    assert!(json.get("disassembly").is_none());
+}
+
+#[test]
+fn parse_format() {
+    assert_eq!(Ok(ProfileFormat::Text), "text".parse());
+    assert_eq!(Ok(ProfileFormat::Json), "json".parse());
+    assert!("xml".parse::<ProfileFormat>().is_err());
+}
+
+#[test]
+fn counts_cpu_instructions() {
+    let mut cpu = CPU::new(channel().1);
+    cpu.set_tick_rate(4);
+    cpu.set_profiling(true);
+    cpu.load_game_data(&[
+        0x60, 0x02, // 0x200: LD V0, 0x02
+        0xF0, 0x15, // 0x202: LD DT, V0
+        0xF0, 0x07, // 0x204: LD V0, DT
+        0x30, 0x00, // 0x206: SE V0, 0x00
+        0x12, 0x04, // 0x208: JP 0x204
+        0x12, 0x0A, // 0x20A: JP 0x20A
+    ])
+    .unwrap();
+    for _ in 0..3 {
+        cpu.run_frame().unwrap();
+    }
+    let report = cpu.profile_report(false).unwrap();
+    assert_eq!(12, report.instructions);
+    assert_eq!("LD V0, DT", report.addresses[0].instruction);
+    assert_eq!(0x204, report.loops[0].start);
+    assert_eq!(Some("delay timer"), report.loops[0].waits_on);
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests;
+
+use serde::{Deserialize, Serialize};
+
+/// Behaviors that differ between CHIP-8 interpreters. Field names follow the
+/// community CHIP-8 database, so overrides can be read straight from it. The
+/// defaults match this interpreter's historical behavior.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Quirks {
+    // Fx55/Fx65 increment I by x instead of x + 1
+    pub memory_increment_by_x: bool,
+    // Fx55/Fx65 leave I unchanged
+    pub memory_leave_i_unchanged: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub logic: bool,
+    // Dxyn waits for the display's vertical blank, so at most one sprite is
+    // drawn per frame
+    pub vblank: bool,
+    // Sprites wrap around the edges of the screen instead of being clipped.
+    // The starting coordinates wrap either way.
+    pub wrap: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            logic: false,
+            vblank: false,
+            wrap: true,
+        }
+    }
+}
+
+impl Quirks {
+    /// Turns the quirk called |name| in the community database on or off,
+    /// for overriding quirks from the command line.
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let quirk = match name {
+            "memoryIncrementByX" => &mut self.memory_increment_by_x,
+            "memoryLeaveIUnchanged" => &mut self.memory_leave_i_unchanged,
+            "logic" => &mut self.logic,
+            "vblank" => &mut self.vblank,
+            "wrap" => &mut self.wrap,
+            _ => return Err(format!("unknown quirk '{}'", name)),
+        };
+        *quirk = enabled;
+        Ok(())
+    }
+}
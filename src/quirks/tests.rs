@@ -0,0 +1,19 @@
+use crate::quirks::*;
+
+#[test]
+fn set_by_name() {
+    let mut quirks = Quirks::default();
+    quirks.set("vblank", true).unwrap();
+    quirks.set("wrap", false).unwrap();
+    quirks.set("memoryIncrementByX", true).unwrap();
+    assert_eq!(
+        Quirks {
+            memory_increment_by_x: true,
+            vblank: true,
+            wrap: false,
+            ..Quirks::default()
+        },
+        quirks
+    );
+    assert!(quirks.set("shift", true).is_err());
+}
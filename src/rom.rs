@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests;
+
+use crate::platform::Platform;
+use flate2::read::GzDecoder;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::Cursor;
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 0x03, 0x04];
+// File extensions commonly used for ROMs, used to pick an entry out of a zip
+// archive that contains other files (READMEs, etc.) as well.
+const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "rom"];
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Archive(String),
+    Empty,
+    TooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(e) => write!(f, "unable to read ROM: {}", e),
+            RomError::Archive(e) => write!(f, "unable to unpack ROM archive: {}", e),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, max } => write!(
+                f,
+                "ROM is {} bytes, but at most {} bytes fit in memory",
+                size, max
+            ),
+        }
+    }
+}
+
+impl error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> RomError {
+        RomError::Io(e)
+    }
+}
+
+/// Reads a ROM from |path|, where "-" means stdin.
+pub fn read(path: &str) -> Result<Vec<u8>, RomError> {
+    if path == "-" {
+        read_stdin()
+    } else {
+        read_file(path)
+    }
+}
+
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, RomError> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    unpack(data)
+}
+
+pub fn read_stdin() -> Result<Vec<u8>, RomError> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    unpack(data)
+}
+
+/// Returns the raw ROM bytes contained in |data|, decompressing it first if it
+/// is a gzip stream or a zip archive.
+pub fn unpack(data: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    if data.starts_with(&GZIP_MAGIC) {
+        read_limited(GzDecoder::new(&data[..]))
+    } else if data.starts_with(&ZIP_MAGIC) {
+        unzip(data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Checks that |data| is a program that fits in the memory of |platform|.
+pub fn validate(data: &[u8], platform: Platform) -> Result<(), RomError> {
+    let max = platform.max_rom_size();
+    if data.is_empty() {
+        Err(RomError::Empty)
+    } else if data.len() > max {
+        Err(RomError::TooLarge {
+            size: data.len(),
+            max,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn unzip(data: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).map_err(|e| RomError::Archive(e.to_string()))?;
+
+    // Prefer an entry that looks like a ROM, falling back to the first file.
+    let mut chosen = None;
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index(index)
+            .map_err(|e| RomError::Archive(e.to_string()))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let is_rom = Path::new(entry.name())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ROM_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            .unwrap_or(false);
+        if is_rom {
+            chosen = Some(index);
+            break;
+        }
+        if chosen.is_none() {
+            chosen = Some(index);
+        }
+    }
+
+    let index = chosen.ok_or_else(|| RomError::Archive("archive contains no files".into()))?;
+    let entry = archive
+        .by_index(index)
+        .map_err(|e| RomError::Archive(e.to_string()))?;
+    read_limited(entry)
+}
+
+// Decompresses |reader|, giving up once it's clear the result won't fit in the
+// memory of any platform, so a small archive can't expand into gigabytes.
+fn read_limited<R: Read>(reader: R) -> Result<Vec<u8>, RomError> {
+    let max = Platform::XoChip.max_rom_size();
+    let mut rom = Vec::new();
+    reader.take(max as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > max {
+        return Err(RomError::Archive(format!(
+            "unpacked ROM is over {} bytes",
+            max
+        )));
+    }
+    Ok(rom)
+}
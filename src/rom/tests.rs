@@ -0,0 +1,79 @@
+use crate::rom::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+const ROM: [u8; 4] = [0x00, 0xE0, 0x12, 0x00];
+
+#[test]
+fn unpack_raw() {
+    assert_eq!(ROM.to_vec(), unpack(ROM.to_vec()).unwrap());
+}
+
+#[test]
+fn unpack_gzip() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&ROM).unwrap();
+    let data = encoder.finish().unwrap();
+    assert_eq!(ROM.to_vec(), unpack(data).unwrap());
+}
+
+#[test]
+fn unpack_gzip_too_large() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&vec![0; 1 << 20]).unwrap();
+    let data = encoder.finish().unwrap();
+    match unpack(data) {
+        Err(RomError::Archive(_)) => {}
+        other => panic!("expected an archive error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unpack_zip_too_large() {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer
+        .start_file("game.ch8", FileOptions::default())
+        .unwrap();
+    writer.write_all(&vec![0; 1 << 20]).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    match unpack(data) {
+        Err(RomError::Archive(_)) => {}
+        other => panic!("expected an archive error, got {:?}", other),
+    }
+}
+
+#[test]
+fn unpack_zip_prefers_rom_entry() {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer
+        .start_file("README.txt", FileOptions::default())
+        .unwrap();
+    writer.write_all(b"not a rom").unwrap();
+    writer
+        .start_file("game.ch8", FileOptions::default())
+        .unwrap();
+    writer.write_all(&ROM).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+    assert_eq!(ROM.to_vec(), unpack(data).unwrap());
+}
+
+#[test]
+fn validate_sizes() {
+    assert!(validate(&ROM, Platform::Chip8).is_ok());
+    assert!(validate(&[0; 0xE00], Platform::Chip8).is_ok());
+    match validate(&[], Platform::Chip8) {
+        Err(RomError::Empty) => {}
+        other => panic!("expected an empty ROM error, got {:?}", other),
+    }
+    match validate(&[0; 0xE01], Platform::Chip8) {
+        Err(RomError::TooLarge { size, max }) => {
+            assert_eq!(0xE01, size);
+            assert_eq!(0xE00, max);
+        }
+        other => panic!("expected a ROM too large error, got {:?}", other),
+    }
+    assert!(validate(&[0; 0xE01], Platform::XoChip).is_ok());
+}
@@ -0,0 +1,283 @@
+#[cfg(test)]
+mod tests;
+
+use crate::display::Display;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, ParseError, Scope, AST};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    Parse(ParseError),
+    Runtime(Box<EvalAltResult>),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Io(e) => write!(f, "unable to read script: {}", e),
+            ScriptError::Parse(e) => write!(f, "unable to parse script: {}", e),
+            ScriptError::Runtime(e) => write!(f, "script failed: {}", e),
+        }
+    }
+}
+
+impl error::Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(e: io::Error) -> ScriptError {
+        ScriptError::Io(e)
+    }
+}
+
+impl From<ParseError> for ScriptError {
+    fn from(e: ParseError) -> ScriptError {
+        ScriptError::Parse(e)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(e: Box<EvalAltResult>) -> ScriptError {
+        ScriptError::Runtime(e)
+    }
+}
+
+/// The points at which the CPU calls into a script.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hook {
+    // At the start of every frame, before any instructions run
+    Frame,
+    // Before the instruction at the given address runs
+    Instruction(usize),
+    // After every Dxyn
+    Draw,
+}
+
+impl Hook {
+    fn function_name(self) -> &'static str {
+        match self {
+            Hook::Frame => "on_frame",
+            Hook::Instruction(_) => "on_instruction",
+            Hook::Draw => "on_draw",
+        }
+    }
+}
+
+/// The machine state a script can see and change. The CPU copies its state in
+/// before each hook, and back out afterwards.
+#[derive(Debug, Default)]
+pub struct Machine {
+    pub v: [u8; 16],
+    pub i: usize,
+    pub pc: usize,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub memory: Vec<u8>,
+    pub display: Display,
+    // Addresses written by the script, so the CPU can drop stale translations
+    pub written: Vec<usize>,
+    pub keys: [bool; 16],
+    pub frame: u64,
+    // Whether the script asked to end the run
+    pub exited: bool,
+}
+
+/// A Rhai script with hooks called by the CPU while it runs. The script can
+/// define any of:
+///
+///   fn on_frame() { ... }
+///   fn on_instruction(pc) { ... }
+///   fn on_draw() { ... }
+///
+/// Hooks can keep state between calls in |this|, which starts as an empty
+/// object map.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    // Bound to |this| in every hook
+    state: Dynamic,
+    machine: Arc<Mutex<Machine>>,
+    hooks: Vec<&'static str>,
+}
+
+impl Script {
+    /// Compiles |source| and runs its top-level statements.
+    pub fn new(source: &str) -> Result<Script, ScriptError> {
+        let machine = Arc::new(Mutex::new(Machine::default()));
+        let engine = create_engine(&machine);
+        let ast = engine.compile(source)?;
+        let hooks = [Hook::Frame, Hook::Instruction(0), Hook::Draw]
+            .iter()
+            .map(|hook| hook.function_name())
+            .filter(|name| ast.iter_functions().any(|function| function.name == *name))
+            .collect();
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+        Ok(Script {
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            machine,
+            hooks,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Script, ScriptError> {
+        Script::new(&fs::read_to_string(path)?)
+    }
+
+    /// Returns whether the script defines |hook|.
+    pub fn has_hook(&self, hook: Hook) -> bool {
+        self.hooks.contains(&hook.function_name())
+    }
+
+    /// Calls |hook|, if the script defines it.
+    pub fn call(&mut self, hook: Hook) -> Result<(), ScriptError> {
+        if !self.has_hook(hook) {
+            return Ok(());
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let name = hook.function_name();
+        // Hooks may end with an expression, whose value is ignored.
+        let _: Dynamic = match hook {
+            Hook::Instruction(pc) => self.engine.call_fn_with_options(
+                options,
+                &mut self.scope,
+                &self.ast,
+                name,
+                (pc as i64,),
+            )?,
+            _ => self
+                .engine
+                .call_fn_with_options(options, &mut self.scope, &self.ast, name, ())?,
+        };
+        Ok(())
+    }
+
+    pub fn machine(&self) -> MutexGuard<'_, Machine> {
+        self.machine.lock().unwrap()
+    }
+
+    /// Returns whether the script has called exit().
+    pub fn exited(&self) -> bool {
+        self.machine().exited
+    }
+}
+
+// Creates an engine with the functions scripts use to inspect and control the
+// machine. Numbers are masked to the size of what they're stored in, and
+// addresses wrap around memory like they do for programs.
+fn create_engine(machine: &Arc<Mutex<Machine>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let m = machine.clone();
+    engine.register_fn("v", move |reg: i64| {
+        i64::from(m.lock().unwrap().v[reg as usize & 0xF])
+    });
+    let m = machine.clone();
+    engine.register_fn("set_v", move |reg: i64, value: i64| {
+        m.lock().unwrap().v[reg as usize & 0xF] = value as u8;
+    });
+    let m = machine.clone();
+    engine.register_fn("i", move || m.lock().unwrap().i as i64);
+    let m = machine.clone();
+    engine.register_fn("set_i", move |value: i64| {
+        m.lock().unwrap().i = value as usize & 0xFFFF;
+    });
+    let m = machine.clone();
+    engine.register_fn("pc", move || m.lock().unwrap().pc as i64);
+    let m = machine.clone();
+    engine.register_fn("set_pc", move |value: i64| {
+        let mut machine = m.lock().unwrap();
+        machine.pc = wrap_address(value, &machine.memory);
+    });
+    let m = machine.clone();
+    engine.register_fn("sp", move || m.lock().unwrap().sp as i64);
+    let m = machine.clone();
+    engine.register_fn("dt", move || i64::from(m.lock().unwrap().delay_timer));
+    let m = machine.clone();
+    engine.register_fn("set_dt", move |value: i64| {
+        m.lock().unwrap().delay_timer = value as u8;
+    });
+    let m = machine.clone();
+    engine.register_fn("frame", move || m.lock().unwrap().frame as i64);
+
+    let m = machine.clone();
+    engine.register_fn("peek", move |addr: i64| {
+        let machine = m.lock().unwrap();
+        let addr = wrap_address(addr, &machine.memory);
+        machine.memory.get(addr).map_or(0, |byte| i64::from(*byte))
+    });
+    let m = machine.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| {
+        let mut machine = m.lock().unwrap();
+        let addr = wrap_address(addr, &machine.memory);
+        if let Some(byte) = machine.memory.get_mut(addr) {
+            *byte = value as u8;
+            machine.written.push(addr);
+        }
+    });
+
+    let m = machine.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64| {
+        let display = &m.lock().unwrap().display;
+        let (x, y) = pixel_location(display, x, y);
+        display.pixel(x, y)
+    });
+    let m = machine.clone();
+    engine.register_fn("set_pixel", move |x: i64, y: i64, lit: bool| {
+        let display = &mut m.lock().unwrap().display;
+        let (x, y) = pixel_location(display, x, y);
+        display.set_pixel(x, y, lit);
+    });
+
+    let m = machine.clone();
+    engine.register_fn("key", move |key: i64| {
+        m.lock().unwrap().keys[key as usize & 0xF]
+    });
+    let m = machine.clone();
+    engine.register_fn("press", move |key: i64| {
+        m.lock().unwrap().keys[key as usize & 0xF] = true;
+    });
+    let m = machine.clone();
+    engine.register_fn("release", move |key: i64| {
+        m.lock().unwrap().keys[key as usize & 0xF] = false;
+    });
+
+    let m = machine.clone();
+    engine.register_fn("exit", move || m.lock().unwrap().exited = true);
+    engine.register_fn(
+        "assert",
+        |condition: bool, message: &str| -> Result<(), Box<EvalAltResult>> {
+            if condition {
+                Ok(())
+            } else {
+                Err(format!("assertion failed: {}", message).into())
+            }
+        },
+    );
+    engine
+}
+
+// Memory is only available while a hook runs, so top-level statements see
+// none.
+fn wrap_address(addr: i64, memory: &[u8]) -> usize {
+    addr as usize & memory.len().wrapping_sub(1)
+}
+
+// Wraps |x|, |y| around the display, including negative coordinates.
+fn pixel_location(display: &Display, x: i64, y: i64) -> (usize, usize) {
+    (
+        x.rem_euclid(display.width() as i64) as usize,
+        y.rem_euclid(display.height() as i64) as usize,
+    )
+}
@@ -0,0 +1,86 @@
+use crate::script::*;
+
+fn create_script(source: &str) -> Script {
+    Script::new(source).unwrap()
+}
+
+#[test]
+fn has_hook() {
+    let script = create_script("fn on_frame() {} fn on_draw() {} fn helper() {}");
+    assert!(script.has_hook(Hook::Frame));
+    assert!(script.has_hook(Hook::Draw));
+    assert!(!script.has_hook(Hook::Instruction(0x200)));
+}
+
+#[test]
+fn call_reads_and_writes_machine() {
+    let mut script = create_script(
+        "fn on_instruction(pc) {
+            set_v(1, v(0) + 1);
+            poke(pc + 0x1000, 0x12);
+            set_pc(pc + 2);
+            press(0xA);
+        }",
+    );
+    {
+        let mut machine = script.machine();
+        machine.v[0] = 0x41;
+        machine.memory = vec![0; 0x1000];
+    }
+    script.call(Hook::Instruction(0x200)).unwrap();
+    let machine = script.machine();
+    assert_eq!(0x42, machine.v[1]);
+    assert_eq!(0x12, machine.memory[0x200]);
+    assert_eq!(vec![0x200], machine.written);
+    assert_eq!(0x202, machine.pc);
+    assert!(machine.keys[0xA]);
+}
+
+#[test]
+fn call_keeps_state_in_this() {
+    let mut script = create_script(
+        "fn on_frame() {
+            if this.frames == () { this.frames = 0; }
+            this.frames += 1;
+            if this.frames == 3 { exit(); }
+        }",
+    );
+    script.call(Hook::Frame).unwrap();
+    script.call(Hook::Frame).unwrap();
+    assert!(!script.exited());
+    script.call(Hook::Frame).unwrap();
+    assert!(script.exited());
+}
+
+#[test]
+fn call_missing_hook() {
+    let mut script = create_script("fn on_frame() { throw \"unexpected\"; }");
+    assert!(script.call(Hook::Draw).is_ok());
+}
+
+#[test]
+fn assert_fails() {
+    let mut script = create_script("fn on_draw() { assert(pixel(0, 0), \"no pixel\"); }");
+    match script.call(Hook::Draw) {
+        Err(e @ ScriptError::Runtime(_)) => assert!(e.to_string().contains("no pixel")),
+        other => panic!("expected a runtime error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn pixels_wrap() {
+    let mut script = create_script(
+        "fn on_frame() {
+            set_pixel(-1, 32, true);
+            assert(pixel(63, 0), \"pixel not set\");
+        }",
+    );
+    script.call(Hook::Frame).unwrap();
+    assert_eq!(0x01, script.machine().display.as_bytes()[7]);
+}
+
+#[test]
+fn parse_error() {
+    let script = Script::new("fn on_frame( {");
+    assert!(matches!(script, Err(ScriptError::Parse(_))));
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests;
+
+use serde::Serialize;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::str::FromStr;
+
+const CSV_HEADER: &str =
+    "cycle,pc,opcode,mnemonic,v0,v1,v2,v3,v4,v5,v6,v7,v8,v9,va,vb,vc,vd,ve,vf,i,sp";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    Csv,
+    JsonLines,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TraceFormat, String> {
+        match s {
+            "csv" => Ok(TraceFormat::Csv),
+            "jsonl" | "json" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!("unknown trace format '{}'", s)),
+        }
+    }
+}
+
+/// The machine state right before an instruction is executed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TraceRecord {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u16,
+}
+
+impl TraceRecord {
+    pub fn format(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Csv => {
+                let mut line = format!(
+                    "{},{:04X},{:04X},\"{}\"",
+                    self.cycle, self.pc, self.opcode, self.mnemonic
+                );
+                for reg in self.v.iter() {
+                    write!(line, ",{:02X}", reg).unwrap();
+                }
+                write!(line, ",{:04X},{:X}", self.i, self.sp).unwrap();
+                line
+            }
+            TraceFormat::JsonLines => {
+                serde_json::to_string(self).expect("unable to serialize trace record")
+            }
+        }
+    }
+}
+
+/// Writes one line per executed instruction, so runs can be diffed against
+/// each other or against other emulators.
+pub struct Tracer {
+    format: TraceFormat,
+    writer: Box<dyn Write + Send>,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, mut writer: Box<dyn Write + Send>) -> io::Result<Tracer> {
+        if format == TraceFormat::Csv {
+            writeln!(writer, "{}", CSV_HEADER)?;
+        }
+        Ok(Tracer { format, writer })
+    }
+
+    /// Creates a tracer writing to |path|, where "-" means stdout.
+    pub fn create(format: TraceFormat, path: &str) -> io::Result<Tracer> {
+        let writer: Box<dyn Write + Send> = if path == "-" {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Tracer::new(format, writer)
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        writeln!(self.writer, "{}", record.format(self.format))
+    }
+}
@@ -0,0 +1,32 @@
+use crate::trace::*;
+
+fn record() -> TraceRecord {
+    let mut v = [0; 16];
+    v[1] = 0x12;
+    v[0xF] = 1;
+    TraceRecord {
+        cycle: 7,
+        pc: 0x202,
+        opcode: 0x6112,
+        mnemonic: String::from("LD V1, 0x12"),
+        v,
+        i: 0x300,
+        sp: 2,
+    }
+}
+
+#[test]
+fn format_csv() {
+    assert_eq!(
+        "7,0202,6112,\"LD V1, 0x12\",00,12,00,00,00,00,00,00,00,00,00,00,00,00,00,01,0300,2",
+        record().format(TraceFormat::Csv)
+    );
+}
+
+#[test]
+fn format_json_lines() {
+    assert_eq!(
+        r#"{"cycle":7,"pc":514,"opcode":24850,"mnemonic":"LD V1, 0x12","v":[0,18,0,0,0,0,0,0,0,0,0,0,0,0,0,1],"i":768,"sp":2}"#,
+        record().format(TraceFormat::JsonLines)
+    );
+}
@@ -1,87 +1,351 @@
-use crate::FRAME_BUFFER_BYTES;
+use crate::control::Control;
+use crate::display::Display;
+use crate::memory_view::{self, Highlight, MemoryEditor, MemoryView, Poke};
+use piston_window::texture::{CreateTexture, Format, UpdateTexture};
 use piston_window::*;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
 use std::sync::Arc;
 use std::sync::RwLock;
 
 const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
 const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
-const PIXEL_SCALE_FACTOR: f64 = 10.0;
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Size of the window's drawing area, which the display is scaled to fill.
+const WINDOW_WIDTH: f64 = 640.0;
+
+// Shows the memory viewer in place of the display when pressed.
+const MEMORY_VIEW_KEY: Key = Key::F6;
+// Layout of the memory viewer, in window pixels. Hex digits are drawn with the
+// built-in 4x5 font.
+const GLYPH_SCALE: f64 = 2.0;
+const CHAR_WIDTH: f64 = 5.0 * GLYPH_SCALE;
+const BYTE_WIDTH: f64 = 2.0 * CHAR_WIDTH + 4.0;
+const ROW_HEIGHT: f64 = 18.0;
+const MARGIN: f64 = 8.0;
+const SPRITE_LEFT: f64 = 456.0;
+const SPRITE_PIXEL_SIZE: f64 = 8.0;
+
+/// Colors used to draw unlit and lit pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub background: [f32; 4],
+    pub foreground: [f32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            background: BLACK,
+            foreground: GREEN,
+        }
+    }
+}
 
 pub struct WindowHandler {
-    frame_buffer: Arc<RwLock<[u8; FRAME_BUFFER_BYTES]>>,
-    // Sender to notify other threads that the window is closed
-    closed_sender: Sender<bool>,
-    key_event_sender: Sender<Event>,
+    title: String,
+    palette: Palette,
+    // Completed frames published by the CPU, until it stops
+    frame_receiver: Receiver<Display>,
+    // The latest frame received and not yet drawn into the texture
+    new_frame: Option<Display>,
+    // Sends key events to the CPU, and tells it to quit when the window closes
+    controls: Sender<Control>,
+    memory_view: Option<Arc<RwLock<MemoryView>>>,
+    poke_sender: Option<Sender<Poke>>,
+    memory_editor: MemoryEditor,
+    // Whether the memory viewer is shown instead of the display
+    showing_memory: bool,
+    // The latest frame received, drawn into the texture when it arrives
+    texture: Option<G2dTexture>,
 }
 
 impl WindowHandler {
     pub fn new(
-        frame_buffer: Arc<RwLock<[u8; FRAME_BUFFER_BYTES]>>,
-        closed_sender: Sender<bool>,
-        key_event_sender: Sender<Event>,
+        title: String,
+        palette: Palette,
+        frame_receiver: Receiver<Display>,
+        controls: Sender<Control>,
     ) -> WindowHandler {
         WindowHandler {
-            frame_buffer,
-            closed_sender,
-            key_event_sender,
+            title,
+            palette,
+            frame_receiver,
+            new_frame: None,
+            controls,
+            memory_view: None,
+            poke_sender: None,
+            memory_editor: MemoryEditor::default(),
+            showing_memory: false,
+            texture: None,
         }
     }
 
+    /// Enables the memory viewer, which shows |view| and sends edits to
+    /// |poke_sender|.
+    pub fn set_memory_view(&mut self, view: Arc<RwLock<MemoryView>>, poke_sender: Sender<Poke>) {
+        self.memory_view = Some(view);
+        self.poke_sender = Some(poke_sender);
+    }
+
     pub fn run(&mut self) {
-        let mut window: PistonWindow = WindowSettings::new("Chip8", (640, 320))
-            .exit_on_esc(false)
-            .resizable(false)
-            .build()
-            .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
+        let mut window: PistonWindow =
+            WindowSettings::new(self.title.as_str(), (WINDOW_WIDTH as u32, 320))
+                .exit_on_esc(false)
+                .resizable(false)
+                .build()
+                .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
         while let Some(e) = window.next() {
+            self.receive_frame(&mut window);
             self.draw_frame_buffer(&mut window, &e);
             self.handle_key_event(&e);
         }
-        self.closed_sender
-            .send(true)
-            .expect("unable to send window-closed message");
     }
 
     fn draw_frame_buffer(&mut self, window: &mut PistonWindow, e: &Event) {
+        if self.showing_memory {
+            if let Some(view) = self.memory_view.clone() {
+                let view = view.read().unwrap();
+                window.draw_2d(e, |c, g| self.draw_memory_view(&view, c, g));
+                return;
+            }
+        }
+        if e.render_args().is_none() {
+            return;
+        }
+        self.update_texture(window);
+        let texture = &self.texture;
+        let palette = self.palette;
         window.draw_2d(e, |c, g| {
-            clear(BLACK, g);
-            for (index, byte) in self.frame_buffer.read().unwrap().iter().enumerate() {
-                let row = index / 8;
-                let octet_index = index % 8;
-                for bit_index in 0..8 {
-                    if bit_is_set(*byte, bit_index) {
-                        let top = PIXEL_SCALE_FACTOR * row as f64;
-                        let left = PIXEL_SCALE_FACTOR
-                            * (octet_index * 8 + (8 - bit_index - 1) as usize) as f64;
-                        rectangle(
-                            GREEN,
-                            [left, top, PIXEL_SCALE_FACTOR, PIXEL_SCALE_FACTOR],
-                            c.transform,
-                            g,
-                        );
-                    }
-                }
+            clear(palette.background, g);
+            if let Some(texture) = texture {
+                let scale = WINDOW_WIDTH / f64::from(texture.get_width());
+                image(texture, c.transform.scale(scale, scale), g);
             }
         });
     }
 
+    // Takes the frame published by the CPU, if any, so it can publish the
+    // next one, even while the display isn't shown. Closes the window once
+    // the CPU has stopped.
+    fn receive_frame(&mut self, window: &mut PistonWindow) {
+        match self.frame_receiver.try_recv() {
+            Ok(display) => self.new_frame = Some(display),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => window.set_should_close(true),
+        }
+    }
+
+    // Copies the latest frame received into the texture.
+    fn update_texture(&mut self, window: &mut PistonWindow) {
+        let display = match self.new_frame.take() {
+            Some(display) => display,
+            None => return,
+        };
+        let pixels = display.to_rgba(
+            to_rgba8(self.palette.background),
+            to_rgba8(self.palette.foreground),
+        );
+        let size = [display.width() as u32, display.height() as u32];
+        let resized = match &self.texture {
+            Some(texture) => texture.get_size() != (size[0], size[1]),
+            None => true,
+        };
+        if resized {
+            let settings = TextureSettings::new().filter(Filter::Nearest);
+            self.texture = Some(
+                Texture::create(&mut window.factory, Format::Rgba8, &pixels, size, &settings)
+                    .unwrap_or_else(|e| panic!("Failed to create texture: {:?}", e)),
+            );
+        } else if let Some(texture) = &mut self.texture {
+            UpdateTexture::update(
+                texture,
+                &mut window.encoder,
+                Format::Rgba8,
+                &pixels,
+                [0, 0],
+                size,
+            )
+            .unwrap_or_else(|e| panic!("Failed to update texture: {:?}", e));
+        }
+    }
+
+    // Draws a page of memory as a hex grid, with the bytes from the cursor
+    // decoded as a sprite to its right.
+    fn draw_memory_view(&self, view: &MemoryView, c: Context, g: &mut G2d) {
+        clear(BLACK, g);
+        let editor = &self.memory_editor;
+        let page_start = editor.page_start();
+        // The bytes start after the address and a space.
+        let address_digits = view.address_digits();
+        let bytes_left = MARGIN + (address_digits + 1) as f64 * CHAR_WIDTH;
+        for row in 0..memory_view::ROWS_PER_PAGE {
+            let row_start = page_start + row * memory_view::BYTES_PER_ROW;
+            if row_start >= view.memory.len() {
+                break;
+            }
+            let top = MARGIN + ROW_HEIGHT * row as f64;
+            draw_hex(
+                row_start,
+                address_digits,
+                MARGIN,
+                top,
+                self.palette.foreground,
+                c,
+                g,
+            );
+            for column in 0..memory_view::BYTES_PER_ROW {
+                let addr = row_start + column;
+                let left = bytes_left + BYTE_WIDTH * column as f64;
+                let cell = [left - 2.0, top - 2.0, BYTE_WIDTH, ROW_HEIGHT - 2.0];
+                if let Some(color) = highlight_color(view.highlight(addr)) {
+                    rectangle(color, cell, c.transform, g);
+                }
+                let value = match editor.high_nibble {
+                    Some(high) if addr == editor.cursor => usize::from(high),
+                    _ => usize::from(view.memory[addr]),
+                };
+                let digits = if addr == editor.cursor && editor.high_nibble.is_some() {
+                    1
+                } else {
+                    2
+                };
+                draw_hex(value, digits, left, top, self.palette.foreground, c, g);
+                if addr == editor.cursor {
+                    Rectangle::new_border(WHITE, 1.0).draw(cell, &c.draw_state, c.transform, g);
+                }
+            }
+        }
+
+        draw_hex(
+            editor.cursor,
+            address_digits,
+            SPRITE_LEFT,
+            MARGIN,
+            WHITE,
+            c,
+            g,
+        );
+        for (row, pixels) in editor.sprite(view).iter().enumerate() {
+            for (column, lit) in pixels.iter().enumerate() {
+                let color = if *lit {
+                    self.palette.foreground
+                } else {
+                    self.palette.background
+                };
+                rectangle(
+                    color,
+                    [
+                        SPRITE_LEFT + SPRITE_PIXEL_SIZE * column as f64,
+                        MARGIN + ROW_HEIGHT + SPRITE_PIXEL_SIZE * row as f64,
+                        SPRITE_PIXEL_SIZE,
+                        SPRITE_PIXEL_SIZE,
+                    ],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+    }
+
     fn handle_key_event(&mut self, e: &Event) {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            if key == MEMORY_VIEW_KEY {
+                if let Some(view) = &self.memory_view {
+                    self.showing_memory = !self.showing_memory;
+                    view.write().unwrap().shown = self.showing_memory;
+                    return;
+                }
+            }
+            // The memory viewer takes all keys other than the emulator's
+            // controls, which are on the function keys and Tab.
+            if self.showing_memory && !is_control_key(key) {
+                self.handle_memory_view_key(key);
+                return;
+            }
+        }
+        // The CPU may have stopped, in which case the window is about to close.
         e.press(|_| {
-            self.key_event_sender
-                .send(e.clone())
-                .expect("unable to send key press event");
+            let _ = self.controls.send(Control::Input(e.clone()));
         });
         e.release(|_| {
-            self.key_event_sender
-                .send(e.clone())
-                .expect("unable to send key release event");
+            let _ = self.controls.send(Control::Input(e.clone()));
         });
     }
+
+    fn handle_memory_view_key(&mut self, key: Key) {
+        let view = match &self.memory_view {
+            Some(view) => view.read().unwrap(),
+            None => return,
+        };
+        if let Some(poke) = self.memory_editor.handle_key(key, &view) {
+            if let Some(sender) = &self.poke_sender {
+                // The CPU may have stopped, in which case there's nothing to
+                // edit anymore.
+                let _ = sender.send(poke);
+            }
+        }
+    }
+}
+
+// Tells the CPU to quit when the window closes, even if it panicked, since
+// other threads may still hold senders of controls.
+impl Drop for WindowHandler {
+    fn drop(&mut self) {
+        let _ = self.controls.send(Control::Quit);
+    }
+}
+
+fn is_control_key(key: Key) -> bool {
+    key == Key::Tab || (Key::F1 as u32..=Key::F24 as u32).contains(&(key as u32))
 }
 
-// Return whether or not the bit at index |bit_index| (from least significant)
-// is set.
-fn bit_is_set(byte: u8, bit_index: u8) -> bool {
-    byte & (1 << bit_index) > 0
+fn to_rgba8(color: [f32; 4]) -> [u8; 4] {
+    let mut rgba = [0; 4];
+    for (byte, channel) in rgba.iter_mut().zip(color.iter()) {
+        *byte = (channel.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    rgba
+}
+
+fn highlight_color(highlight: Highlight) -> Option<[f32; 4]> {
+    match highlight {
+        Highlight::Pc => Some([0.6, 0.1, 0.1, 1.0]),
+        Highlight::RecentWrite => Some([0.6, 0.5, 0.0, 1.0]),
+        Highlight::IRegion => Some([0.1, 0.2, 0.6, 1.0]),
+        Highlight::Font => Some([0.25, 0.25, 0.25, 1.0]),
+        Highlight::None => None,
+    }
+}
+
+// Draws the lowest |digits| hex digits of |value| with the built-in font.
+fn draw_hex(
+    value: usize,
+    digits: usize,
+    left: f64,
+    top: f64,
+    color: [f32; 4],
+    c: Context,
+    g: &mut G2d,
+) {
+    for digit in 0..digits {
+        let nibble = (value >> (4 * (digits - digit - 1))) & 0xF;
+        let glyph = memory_view::glyph(nibble as u8);
+        for (row, pixels) in glyph.iter().enumerate() {
+            for (column, lit) in pixels.iter().enumerate() {
+                if *lit {
+                    rectangle(
+                        color,
+                        [
+                            left + CHAR_WIDTH * digit as f64 + GLYPH_SCALE * column as f64,
+                            top + GLYPH_SCALE * row as f64,
+                            GLYPH_SCALE,
+                            GLYPH_SCALE,
+                        ],
+                        c.transform,
+                        g,
+                    );
+                }
+            }
+        }
+    }
 }